@@ -0,0 +1,15 @@
+#!/bin/bash
+
+# Compare parallel and sequential reading, pass a directory on a spinning disk
+# to see the difference, the page cache is dropped before every run
+
+SCRIPT_DIR="$(dirname "$(realpath "$0")")"
+TEST_FILES="${1:-$SCRIPT_DIR/../test_files}"
+BINARY="$SCRIPT_DIR/../target/release/deckard-cli"
+
+cargo build --release
+
+echo "Test read order"
+hyperfine --warmup 1 --prepare "sync; echo 3 | sudo tee /proc/sys/vm/drop_caches" \
+	"$BINARY --full_hash --read_order parallel $TEST_FILES" \
+	"$BINARY --full_hash --read_order sequential $TEST_FILES"
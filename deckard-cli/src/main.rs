@@ -1,21 +1,147 @@
-use color_eyre::eyre::Result;
+use color_eyre::eyre::{bail, Result};
 use colored::*;
+use deckard::cache::HashCache;
+#[cfg(unix)]
+use deckard::daemon::Daemon;
 use deckard::index::FileIndex;
 use deckard::*;
-use log::info;
+use log::{error, info, warn};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 use std::time::Instant;
 
 mod cli;
 
+/// Exit status of a scheduled run that found new duplicates
+const NEW_DUPLICATES_EXIT_CODE: i32 = 2;
+/// Exit status of a scan interrupted with Ctrl-C, like shells report SIGINT
+const INTERRUPTED_EXIT_CODE: i32 = 130;
+
 fn main() -> Result<()> {
     color_eyre::install()?;
-    env_logger::init();
 
     let args = cli::cli().get_matches();
-    let config = cli::get_config();
+    let verbose = args.get_count("verbose");
+    let level = match verbose {
+        0 => "error",
+        1 => "info",
+        _ => "debug",
+    };
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or(level)).init();
+    #[cfg(feature = "otel")]
+    let _telemetry = match args.get_one::<String>("otlp_endpoint") {
+        Some(endpoint) => Some(telemetry::init(endpoint)?),
+        None => None,
+    };
+
+    let mut config = cli::get_config();
+
+    if args.get_flag("save_config") {
+        config.save("deckard-cli");
+        println!(
+            "Saved configuration to {:?}",
+            config::SearchConfig::get_config_path("deckard-cli")
+        );
+    }
+
+    // checksum tools hash whole files in one pass, left out of the saved config
+    if args.get_one::<String>("write_checksums").is_some() {
+        config.hasher_config.full_hash = true;
+        config.hasher_config.parallel_size = 0;
+    }
 
     if args.get_flag("open_config") {
-        open_config();
+        let config_path = config::SearchConfig::get_config_path("deckard-cli");
+        println!("Opening default configuration file: {:?}", config_path);
+        config::SearchConfig::edit_config("deckard-cli")?;
+        return Ok(());
+    }
+
+    if config.action_config.read_only {
+        for action in ["delete", "dedupe_kernel"] {
+            if args.get_flag(action) {
+                bail!("--{} is disabled in read-only mode", action);
+            }
+        }
+        if args.contains_id("clean_from") {
+            bail!("--clean_from is disabled in read-only mode");
+        }
+    }
+
+    if let Some(path) = args.get_one::<String>("clean_from") {
+        clean_selection(&selection::Selection::load(path)?, &config);
+        return Ok(());
+    }
+
+    if let Some(shard_file) = args.get_one::<String>("process_shard") {
+        let now = Instant::now();
+        let mut shard = FileIndex::load(shard_file)?;
+        let processed = shard.process_files(None, None);
+        shard.save(shard_file)?;
+        println!(
+            "Processed {} files in {}, {} errors",
+            processed.files.to_string().green(),
+            format!("{:.2?}", now.elapsed()).blue(),
+            processed.errors.to_string().red()
+        );
+        return Ok(());
+    }
+
+    if let Some(shard_files) = args.get_many::<String>("merge_shards") {
+        let now = Instant::now();
+        let mut shards = Vec::new();
+        for shard_file in shard_files {
+            shards.push(FileIndex::load(shard_file)?);
+        }
+        let mut file_index = FileIndex::new(Default::default(), config);
+        file_index.merge_shards(shards);
+        info!(
+            "Found {} matches in {}",
+            file_index.duplicates_len().to_string().green(),
+            format!("{:.2?}", now.elapsed()).blue()
+        );
+        print_results(&file_index);
+        return Ok(());
+    }
+
+    if args.get_flag("scheduled") {
+        let mut new = false;
+        for run in schedule::run(&schedule::ScheduleConfig::load())? {
+            println!(
+                "{}: {} new duplicates, report {}",
+                run.profile.green(),
+                run.new_duplicates.len().to_string().red(),
+                run.report.to_string_lossy().yellow()
+            );
+            new |= !run.new_duplicates.is_empty();
+        }
+        if new {
+            std::process::exit(NEW_DUPLICATES_EXIT_CODE);
+        }
+        return Ok(());
+    }
+
+    if let Some(baseline) = args.get_one::<String>("verify") {
+        let now = Instant::now();
+        let checksums = verify::read_checksums(baseline)?;
+        // without paths only the listed files are checked
+        let target_paths = match args.get_many::<String>("params") {
+            Some(values) => collect_paths(values.collect()),
+            None => Default::default(),
+        };
+        let mut file_index = FileIndex::new(target_paths, config);
+        file_index.index_dirs();
+        let verification = verify::verify(&file_index, &checksums, &None);
+        info!(
+            "Verified {} files in {}",
+            checksums.len().to_string().green(),
+            format!("{:.2?}", now.elapsed()).blue()
+        );
+        print_verification(&verification);
+        if !verification.is_clean() {
+            std::process::exit(1);
+        }
         return Ok(());
     }
 
@@ -23,60 +149,760 @@ fn main() -> Result<()> {
         Some(values) => values.map(|v| v.as_str()).collect::<Vec<&str>>(),
         None => vec!["."],
     };
+    // user@host:/path arguments are scanned over SSH, s3://bucket/prefix
+    // ones in object storage
+    let (remote_dirs, target_dirs): (Vec<&str>, Vec<&str>) = target_dirs
+        .into_iter()
+        .partition(|d| d.starts_with("s3://") || remote::RemoteRoot::parse(d).is_some());
+    let remote_roots: Vec<remote::RemoteRoot> = remote_dirs
+        .iter()
+        .filter_map(|d| remote::RemoteRoot::parse(d))
+        .collect();
+    #[cfg(feature = "s3")]
+    let bucket_roots: Vec<s3::BucketRoot> = remote_dirs
+        .iter()
+        .filter_map(|d| s3::BucketRoot::parse(d))
+        .collect();
+    #[cfg(not(feature = "s3"))]
+    if remote_dirs.iter().any(|d| d.starts_with("s3://")) {
+        bail!("s3:// paths need deckard-cli built with the s3 feature");
+    }
+    if !remote_dirs.is_empty() {
+        // the remote files can't be changed from here
+        for action in ["delete", "dedupe_kernel"] {
+            if args.get_flag(action) {
+                bail!("--{} can't be used with remote paths", action);
+            }
+        }
+        for action in ["plan", "save_selection"] {
+            if args.contains_id(action) {
+                bail!("--{} can't be used with remote paths", action);
+            }
+        }
+    }
+    if !remote_roots.is_empty() {
+        remote::prepare_config(&mut config);
+    }
 
     let target_paths = collect_paths(target_dirs.clone());
-    println!("Paths: {}", format!("{:?}", target_paths).yellow());
+
+    #[cfg(unix)]
+    if let Some(socket) = args.get_one::<String>("daemon") {
+        let interval =
+            std::time::Duration::from_secs(*args.get_one::<u64>("watch_interval").unwrap());
+        let daemon = Arc::new(Daemon::new(target_paths, config));
+        if let Some(addr) = args.get_one::<String>("metrics_addr") {
+            daemon.clone().serve_metrics(addr)?;
+        }
+        daemon.serve(socket, interval)?;
+        return Ok(());
+    }
+    let report = args.get_one::<String>("report");
+    let json_legacy = args.get_flag("json_legacy");
+    if report.is_none() && !json_legacy {
+        println!("Paths: {}", format!("{:?}", target_paths).yellow());
+    }
+
+    // the first Ctrl-C lets the files in progress finish and compares the
+    // processed ones, the second one quits right away
+    let cancel = Arc::new(AtomicBool::new(false));
+    let interrupted = cancel.clone();
+    ctrlc::set_handler(move || {
+        if interrupted.swap(true, Ordering::SeqCst) {
+            std::process::exit(INTERRUPTED_EXIT_CODE);
+        }
+        eprintln!("Interrupted, finishing the files in progress, press Ctrl-C again to quit");
+    })?;
 
     let now = Instant::now();
     let mut file_index = FileIndex::new(target_paths, config);
-    file_index.index_dirs();
-    let elapsed = now.elapsed();
-    info!(
-        "Indexed {} files in {}",
-        file_index.files_len().to_string().green(),
-        format!("{:.2?}", elapsed).blue()
-    );
+    if verbose >= 2 {
+        file_index.collect_metrics();
+    }
+    if file_index.config.cache {
+        file_index.cache = Some(HashCache::load(
+            HashCache::default_path(),
+            &file_index.config,
+        ));
+    }
+    // an interrupted run leaves the processed files behind to resume from
+    file_index.checkpoint = Some(HashCache::checkpoint_path());
+    if args.get_flag("resume") {
+        let checkpoint = HashCache::load(HashCache::checkpoint_path(), &file_index.config);
+        info!(
+            "Resuming with {} processed files",
+            checkpoint.len().to_string().green()
+        );
+        file_index
+            .cache
+            .get_or_insert_with(|| HashCache::new(&file_index.config))
+            .merge(checkpoint);
+    }
+    if file_index.config.low_memory {
+        scan_low_memory(&mut file_index, &cancel, now)?;
+    } else {
+        let indexed = file_index.index_dirs();
+        let elapsed = now.elapsed();
+        info!(
+            "Indexed {} files in {}, skipped {} special files",
+            indexed.files.to_string().green(),
+            format!("{:.2?}", elapsed).blue(),
+            file_index.stats.special_files.to_string().cyan()
+        );
 
-    let now = Instant::now();
-    file_index.process_files(None);
-    let elapsed = now.elapsed();
+        if let Some(target) = args.get_one::<String>("find_copies_of") {
+            let copies = file_index.find_copies_of(Path::new(target), None)?;
+            println!(
+                "\n{} copies of {}:",
+                copies.len().to_string().green(),
+                target.yellow()
+            );
+            for copy in copies {
+                println!("  {}", copy.to_string_lossy().yellow());
+            }
+            return Ok(());
+        }
+
+        if let Some(count) = args.get_one::<usize>("shards") {
+            let shard_dir = PathBuf::from(args.get_one::<String>("shard_dir").unwrap());
+            for (i, shard) in file_index.shard(*count).iter().enumerate() {
+                let shard_file = shard_dir.join(format!("shard_{}.json", i));
+                shard.save(&shard_file)?;
+                println!(
+                    "Wrote {} files to {}",
+                    shard.files_len().to_string().green(),
+                    shard_file.to_string_lossy().yellow()
+                );
+            }
+            return Ok(());
+        }
+
+        let now = Instant::now();
+        let processed = file_index.process_files(Some(cancel.clone()), None);
+        let elapsed = now.elapsed();
+        info!(
+            "Processed {} files in {}, {} from cache",
+            processed.files.to_string().green(),
+            format!("{:.2?}", elapsed).blue(),
+            processed.cached.to_string().cyan()
+        );
+        if processed.changed > 0 {
+            println!(
+                "{} files changed during the scan and were left out",
+                processed.changed.to_string().yellow()
+            );
+        }
+        if let Some(cache) = file_index
+            .cache
+            .as_ref()
+            .filter(|_| file_index.config.cache)
+        {
+            cache.save(HashCache::default_path())?;
+        }
+
+        let now = Instant::now();
+        file_index.find_duplicates(Some(cancel.clone()), None);
+        let elapsed = now.elapsed();
+        info!(
+            "Found {} matches in {}",
+            file_index.duplicates_len().to_string().green(),
+            format!("{:.2?}", elapsed).blue()
+        );
+    }
+    for root in &remote_roots {
+        let now = Instant::now();
+        let sizes = file_index.files.values().map(|f| f.size).collect();
+        let remote = remote::scan(root, &file_index.config, &sizes)?;
+        let remote_files = remote.files_len();
+        remote::link(&mut file_index, remote);
+        info!(
+            "Indexed {} files on {} in {}",
+            remote_files.to_string().green(),
+            root.host.yellow(),
+            format!("{:.2?}", now.elapsed()).blue()
+        );
+    }
+    #[cfg(feature = "s3")]
+    for root in &bucket_roots {
+        let now = Instant::now();
+        let remote_config = &file_index.config.remote;
+        let objects = root.list(remote_config)?;
+        let (listed, part_size) = (objects.len(), remote_config.multipart_part_size);
+        s3::link(&mut file_index, root, objects, part_size);
+        info!(
+            "Listed {} objects in {} in {}",
+            listed.to_string().green(),
+            root.bucket.yellow(),
+            format!("{:.2?}", now.elapsed()).blue()
+        );
+    }
+    if let Some(path) = args.get_one::<String>("compare_against") {
+        let remote = FileIndex::load(path)?;
+        if remote.config.hash_settings() != file_index.config.hash_settings() {
+            bail!("{} was scanned with different hash settings", path);
+        }
+        let now = Instant::now();
+        let remote_files = remote.files_len();
+        file_index.merge(remote);
+        info!(
+            "Compared with {} files of {} in {}, found {} matches",
+            remote_files.to_string().green(),
+            path.yellow(),
+            format!("{:.2?}", now.elapsed()).blue(),
+            file_index.duplicates_len().to_string().green()
+        );
+    }
+    #[cfg(feature = "notify")]
+    if file_index.config.notify && !file_index.stats.cancelled {
+        notify::scan_finished(&file_index);
+    }
+    #[cfg(not(feature = "notify"))]
+    if file_index.config.notify {
+        warn!("notifications need deckard-cli built with the notify feature");
+    }
+
+    let snapshot = match args.get_one::<String>("diff") {
+        Some(path) => Some(FileIndex::load(path)?),
+        None => None,
+    };
+    match report.map(|r| r.as_str()) {
+        Some("md") => print!("{}", report::markdown(&file_index)),
+        Some("json") => println!("{}", report::json(&file_index)?),
+        _ if json_legacy => println!("{}", report::json_legacy(&file_index)?),
+        _ => match &snapshot {
+            Some(snapshot) => print_diff(&report::diff(snapshot, &file_index)),
+            None => print_results(&file_index),
+        },
+    }
+
+    if file_index.stats.cancelled {
+        if report.is_none() && !json_legacy {
+            println!("\n{}", "Partial results, the scan was interrupted".red());
+        }
+        std::process::exit(INTERRUPTED_EXIT_CODE);
+    }
+
+    if let Some(path) = args.get_one::<String>("snapshot") {
+        file_index.save(path)?;
+        info!("Saved snapshot to {}", path.yellow());
+    }
+
+    if let Some(mut export) = args.get_many::<String>("export") {
+        let (format, path) = (export.next().unwrap(), export.next().unwrap());
+        match format.as_str() {
+            #[cfg(feature = "sqlite")]
+            "sqlite" => {
+                let run = export::sqlite(&file_index, path)?;
+                info!("Exported run {} to {}", run, path.yellow());
+            }
+            #[cfg(not(feature = "sqlite"))]
+            "sqlite" => bail!(
+                "exporting {} needs deckard-cli built with the sqlite feature",
+                path
+            ),
+            _ => bail!("unsupported export format {}", format),
+        }
+    }
+
+    if let Some(path) = args.get_one::<String>("save_selection") {
+        let removals = policy::removals(&file_index.config.action_config.keep_policy, &file_index);
+        let selection = selection::Selection::new(&file_index, &removals);
+        selection.save(path)?;
+        info!(
+            "Saved {} selected files to {}",
+            selection.files.len().to_string().green(),
+            path.yellow()
+        );
+    }
+
+    if let Some(path) = args.get_one::<String>("write_checksums") {
+        let written = export::checksums(&file_index, path)?;
+        info!(
+            "Wrote {} checksums to {}",
+            written.to_string().green(),
+            path.yellow()
+        );
+    }
+
+    if file_index.config.partial {
+        print_partial_duplicates(&partial::find_partial_duplicates(&file_index, &None));
+    }
+
+    if file_index.config.chunk_config.compare {
+        print_similar_files(&chunks::find_similar_files(&file_index, &None));
+    }
+
+    if let Some(format) = args.get_one::<String>("plan") {
+        let action = if args.get_flag("dedupe_kernel") {
+            policy::PlanAction::Dedupe
+        } else {
+            policy::PlanAction::Remove
+        };
+        let plan = policy::plan(&file_index, action);
+        match format.as_str() {
+            "json" => println!("{}", policy::plan_json(&plan)?),
+            _ => print_plan(&plan),
+        }
+    } else if args.get_flag("delete") {
+        delete_duplicates(&mut file_index);
+    } else if args.get_flag("dedupe_kernel") {
+        dedupe_duplicates(&file_index);
+    } else if file_index.config.action_config.dry_run {
+        print_removal_preview(&file_index);
+    }
+
+    match args.get_one::<String>("report_by").map(|r| r.as_str()) {
+        Some("ext") => print_wasted(
+            "extension",
+            report::wasted_by_extension(&file_index)
+                .into_iter()
+                .map(|(ext, size, count)| {
+                    let ext = if ext.is_empty() {
+                        "(none)".to_string()
+                    } else {
+                        format!(".{}", ext)
+                    };
+                    (ext, size, count)
+                }),
+        ),
+        Some("dir") => print_wasted(
+            "directory",
+            report::wasted_by_top_dir(&file_index)
+                .into_iter()
+                .map(|(dir, size, count)| (dir.to_string_lossy().to_string(), size, count)),
+        ),
+        _ => {}
+    }
+
+    let exec = args.get_one::<String>("exec");
+    let exec_per_file = args.get_one::<String>("exec_per_file");
+    if exec.is_some() || exec_per_file.is_some() {
+        run_hooks(&file_index, exec, exec_per_file);
+    }
+
+    if args.get_flag("name_report") {
+        print_divergent_names(&file_index);
+    }
+
+    if args.get_flag("hardlink_report") {
+        print_hardlinks(&file_index);
+    }
+
+    let denied = file_index.denied_paths();
+    if !denied.is_empty() {
+        if verbose > 0 {
+            println!("\nPermission denied:");
+            for path in denied {
+                println!("  {}", path.to_string_lossy().yellow());
+            }
+        } else {
+            println!(
+                "\n{} paths skipped due to permissions, use -v to list",
+                denied.len().to_string().red()
+            );
+        }
+    }
+
+    if let Some(metrics) = file_index.metrics() {
+        println!("\nTimings:\n{}", metrics);
+    }
+
+    Ok(())
+}
+
+/// Index and compare with the files spilled to disk, one size at a time
+#[cfg(feature = "sqlite")]
+fn scan_low_memory(
+    file_index: &mut FileIndex,
+    cancel: &Arc<AtomicBool>,
+    now: Instant,
+) -> Result<()> {
+    let scanned = file_index.scan_low_memory(Some(cancel.clone()))?;
     info!(
-        "Processed {} files in {}",
-        file_index.files_len().to_string().green(),
-        format!("{:.2?}", elapsed).blue()
+        "Scanned {} files in {}, found {} matches",
+        scanned.files.to_string().green(),
+        format!("{:.2?}", now.elapsed()).blue(),
+        file_index.duplicates_len().to_string().green()
     );
+    if let Some(cache) = file_index
+        .cache
+        .as_ref()
+        .filter(|_| file_index.config.cache)
+    {
+        cache.save(HashCache::default_path())?;
+    }
+    Ok(())
+}
 
-    let now = Instant::now();
-    file_index.find_duplicates_d(None);
-    let elapsed = now.elapsed();
-    info!(
-        "Found {} matches in {}",
-        file_index.duplicates_len().to_string().green(),
-        format!("{:.2?}", elapsed).blue()
+#[cfg(not(feature = "sqlite"))]
+fn scan_low_memory(
+    _file_index: &mut FileIndex,
+    _cancel: &Arc<AtomicBool>,
+    _now: Instant,
+) -> Result<()> {
+    bail!("--low_memory needs deckard-cli built with the sqlite feature");
+}
+
+fn print_verification(verification: &verify::Verification) {
+    println!(
+        "{} files unchanged",
+        verification.unchanged.to_string().green()
     );
+    for (label, paths) in [
+        ("Modified", &verification.modified),
+        ("Missing", &verification.missing),
+        ("New", &verification.new),
+    ] {
+        if paths.is_empty() {
+            continue;
+        }
+        println!("\n{} ({}):", label, paths.len().to_string().red());
+        for path in paths {
+            println!("  {}", path.to_string_lossy().yellow());
+        }
+    }
+    if !verification.errors.is_empty() {
+        println!("\nErrors: {}", verification.errors.len().to_string().red());
+        for (path, error) in &verification.errors {
+            println!("{} {}", path.to_string_lossy().yellow(), error.red());
+        }
+    }
+}
 
+fn print_results(file_index: &FileIndex) {
     println!("\nMatches:");
-    for (file, file_copies) in &file_index.duplicates {
-        let name = file_index.file_name(file).unwrap();
-        let mut match_names = Vec::new();
+    for group in report::group_summaries(file_index) {
+        if group.confidence < 100 {
+            println!("{}", format!("{}% confidence", group.confidence).cyan());
+        }
+        for file in &group.files {
+            let name = file_index.file_name(file).unwrap();
+            let mut match_names: Vec<_> = file_index.duplicates[file]
+                .iter()
+                .map(|copy| copy.to_string_lossy())
+                .collect();
+            match_names.sort();
+
+            println!(
+                "{} matches {}",
+                name.green(),
+                format!("{:#?}", match_names).yellow()
+            );
+        }
+    }
 
-        for file_copy in file_copies {
-            match_names.push(file_copy.to_string_lossy());
+    if file_index.errors_len() > 0 {
+        println!("\nErrors: {}", file_index.errors_len().to_string().red());
+        let mut errors: Vec<_> = file_index.errors.iter().collect();
+        errors.sort();
+        for (path, error) in errors {
+            println!("{} {}", path.to_string_lossy().yellow(), error.red());
         }
+    }
+}
 
+fn print_wasted(by: &str, wasted: impl Iterator<Item = (String, u64, usize)>) {
+    println!("\nWasted space by {}:", by);
+    for (key, size, count) in wasted {
         println!(
-            "{} matches {}",
-            name.green(),
-            format!("{:#?}", match_names).yellow()
+            "{:>12} {:>6} {}",
+            humansize::format_size(size, humansize::DECIMAL).blue(),
+            count.to_string().red(),
+            key.yellow()
         );
     }
+}
 
-    Ok(())
+/// Run the user commands for every group, only printing them in dry-run mode
+fn run_hooks(file_index: &FileIndex, exec: Option<&String>, exec_per_file: Option<&String>) {
+    let dry_run = file_index.config.action_config.dry_run;
+    let run = |command: String| {
+        if dry_run {
+            println!("{}", command);
+            return;
+        }
+        match hook::run(&command) {
+            Ok(status) if !status.success() => {
+                error!("{} exited with {}", command, status)
+            }
+            Ok(_) => {}
+            Err(e) => error!("failed running {}: {}", command, e),
+        }
+    };
+
+    for (id, group) in report::group_summaries(file_index).iter().enumerate() {
+        let Some(keep) = group
+            .files
+            .iter()
+            .max_by_key(|f| file_index.file_size(f).unwrap_or_default())
+        else {
+            continue;
+        };
+        if let Some(template) = exec {
+            run(hook::group_command(template, id, keep, &group.files));
+        }
+        if let Some(template) = exec_per_file {
+            for file in &group.files {
+                run(hook::file_command(template, id, keep, file));
+            }
+        }
+    }
+}
+
+/// Remove every copy the keep policy doesn't keep, only previewing in dry-run mode
+fn delete_duplicates(file_index: &mut FileIndex) {
+    let action = &file_index.config.action_config;
+    let removals = if action.verify_before_action && !action.dry_run {
+        verified_removals(file_index)
+    } else {
+        policy::removals(&action.keep_policy, file_index)
+    };
+    let preview = fsops::preview_removal(&removals, &file_index.dirs, !action.no_remove_dirs);
+    if action.dry_run {
+        print_preview(&preview);
+        return;
+    }
+
+    let outcome = fsops::remove(&preview);
+    print_outcome(&preview, &outcome);
+    file_index.remove_files(&outcome.files.into_iter().collect());
+}
+
+/// Remove the files of a saved selection that are unchanged since they were selected
+fn clean_selection(selection: &selection::Selection, config: &config::SearchConfig) {
+    let (unchanged, changed) = selection.check();
+    if !changed.is_empty() {
+        println!(
+            "\nSkipping {} files changed since they were selected:",
+            changed.len().to_string().red()
+        );
+        for (path, reason) in &changed {
+            println!("  {} {}", path.to_string_lossy().yellow(), reason.red());
+        }
+    }
+
+    let action = &config.action_config;
+    let preview = fsops::preview_removal(&unchanged, &selection.roots, !action.no_remove_dirs);
+    if action.dry_run {
+        print_preview(&preview);
+        return;
+    }
+    print_outcome(&preview, &fsops::remove(&preview));
+}
+
+fn print_outcome(preview: &fsops::RemovalPreview, outcome: &fsops::RemovalOutcome) {
+    println!(
+        "\nRemoved {} files ({}) and {} directories",
+        outcome.files.len().to_string().red(),
+        humansize::format_size(preview.bytes, humansize::DECIMAL).blue(),
+        outcome.dirs.len().to_string().red()
+    );
+    for (path, e) in &outcome.failed {
+        println!("{} {}", path.to_string_lossy().yellow(), e.red());
+    }
+}
+
+fn print_plan(plan: &policy::Plan) {
+    let verb = match plan.action {
+        policy::PlanAction::Remove => "remove",
+        policy::PlanAction::Dedupe => "deduplicate",
+    };
+    for group in &plan.groups {
+        println!("\nkeep {}", group.keep.to_string_lossy().green());
+        for file in &group.files {
+            println!("  {} {}", verb, file.to_string_lossy().yellow());
+        }
+    }
+    println!(
+        "\nWould {} {} files in {} groups ({})",
+        verb,
+        plan.files.to_string().red(),
+        plan.groups.len().to_string().green(),
+        humansize::format_size(plan.bytes, humansize::DECIMAL).blue()
+    );
 }
 
-/// Open the default configuration file in the default editor
-fn open_config() {
-    let config_path = config::SearchConfig::get_config_path("deckard-cli");
-    println!("Opening default configuration file: {:?}", config_path);
-    let _ = std::process::Command::new("open").arg(config_path).output();
+/// Share the extents of every copy with the kept file of its group
+fn dedupe_duplicates(file_index: &FileIndex) {
+    let decisions = policy::decisions(&file_index.config.action_config.keep_policy, file_index);
+    let dry_run = file_index.config.action_config.dry_run;
+    if dry_run {
+        println!("\nDry run, would deduplicate:");
+    }
+
+    let (mut files, mut bytes) = (0, 0);
+    for decision in decisions.iter().filter(|d| d.keep.len() == 1) {
+        let keep = &decision.keep[0];
+        for file in &decision.remove {
+            if dry_run {
+                println!(
+                    "  {} with {}",
+                    file.to_string_lossy().yellow(),
+                    keep.to_string_lossy().green()
+                );
+                continue;
+            }
+            match fsops::dedupe(keep, file) {
+                Ok(deduped) => {
+                    files += 1;
+                    bytes += deduped;
+                }
+                Err(e) => println!(
+                    "{} {}",
+                    file.to_string_lossy().yellow(),
+                    e.to_string().red()
+                ),
+            }
+        }
+    }
+    if !dry_run {
+        println!(
+            "\nDeduplicated {} files ({})",
+            files.to_string().green(),
+            humansize::format_size(bytes, humansize::DECIMAL).blue()
+        );
+    }
+}
+
+/// Copies to remove that are byte for byte identical to the kept file of their group
+fn verified_removals(file_index: &FileIndex) -> Vec<PathBuf> {
+    let groups: Vec<(PathBuf, Vec<PathBuf>)> =
+        policy::decisions(&file_index.config.action_config.keep_policy, file_index)
+            .into_iter()
+            .filter(|d| d.keep.len() == 1 && !d.remove.is_empty())
+            .map(|mut d| (d.keep.remove(0), d.remove))
+            .collect();
+    let (verified, mismatches) = fsops::verify_groups(&groups);
+    if !mismatches.is_empty() {
+        println!(
+            "\n{} groups differ from the kept copy and were left alone:",
+            mismatches.len().to_string().red()
+        );
+        for mismatch in &mismatches {
+            println!(
+                "  {} {} {}",
+                mismatch.keep.to_string_lossy().yellow(),
+                mismatch.file.to_string_lossy().yellow(),
+                mismatch.reason.red()
+            );
+        }
+    }
+    verified
+}
+
+/// Show what the keep policy would remove
+fn print_removal_preview(file_index: &FileIndex) {
+    let preview = fsops::preview_removal(
+        &policy::removals(&file_index.config.action_config.keep_policy, file_index),
+        &file_index.dirs,
+        !file_index.config.action_config.no_remove_dirs,
+    );
+    print_preview(&preview);
+}
+
+fn print_preview(preview: &fsops::RemovalPreview) {
+    println!(
+        "\nDry run, would remove {} files ({}):",
+        preview.files.len().to_string().red(),
+        humansize::format_size(preview.bytes, humansize::DECIMAL).blue()
+    );
+    for file in &preview.files {
+        println!("  {}", file.to_string_lossy().yellow());
+    }
+    if !preview.dirs.is_empty() {
+        println!(
+            "and {} emptied directories:",
+            preview.dirs.len().to_string().red()
+        );
+        for dir in &preview.dirs {
+            println!("  {}", dir.to_string_lossy().yellow());
+        }
+    }
+}
+
+fn print_diff(diff: &report::DuplicateDiff) {
+    if diff.is_empty() {
+        println!("\nNo changes since the snapshot");
+        return;
+    }
+    for (title, changes) in [("Appeared", &diff.appeared), ("Resolved", &diff.resolved)] {
+        if changes.is_empty() {
+            continue;
+        }
+        println!("\n{}:", title);
+        for (file, copies) in changes {
+            println!(
+                "{} matches {}",
+                file.to_string_lossy().green(),
+                format!("{:#?}", copies).yellow()
+            );
+        }
+    }
+}
+
+fn print_partial_duplicates(partials: &[partial::PartialDuplicate]) {
+    println!("\nPartial duplicates:");
+    for partial in partials {
+        println!(
+            "{} ({}) is the start of {}",
+            partial.partial.to_string_lossy().green(),
+            humansize::format_size(partial.size, humansize::DECIMAL).blue(),
+            partial.full.to_string_lossy().yellow()
+        );
+    }
+}
+
+fn print_similar_files(similar: &[chunks::SimilarFiles]) {
+    println!("\nSimilar files:");
+    for pair in similar {
+        println!(
+            "{} and {} share {:.1}% of their chunks",
+            pair.a.to_string_lossy().green(),
+            pair.b.to_string_lossy().yellow(),
+            pair.shared
+        );
+    }
+}
+
+fn print_hardlinks(file_index: &FileIndex) {
+    let groups = report::hardlink_groups(file_index);
+    let shared: u64 = groups.iter().map(|g| g.shared()).sum();
+    println!(
+        "\n{} hardlinked groups share {}:",
+        groups.len().to_string().green(),
+        humansize::format_size(shared, humansize::DECIMAL).blue()
+    );
+    for group in &groups {
+        let paths: Vec<_> = group.files.iter().map(|p| p.to_string_lossy()).collect();
+        println!(
+            "{:>12} {}",
+            humansize::format_size(group.shared(), humansize::DECIMAL).blue(),
+            paths.join(", ").yellow()
+        );
+    }
+
+    let farms = report::link_farms(&groups);
+    if !farms.is_empty() {
+        println!("\nLink farms:");
+    }
+    for farm in farms {
+        let dirs: Vec<_> = farm.dirs.iter().map(|d| d.to_string_lossy()).collect();
+        println!(
+            "{:>12} {:>6} {}",
+            humansize::format_size(farm.shared, humansize::DECIMAL).blue(),
+            farm.groups.to_string().red(),
+            dirs.join(", ").yellow()
+        );
+    }
+}
+
+fn print_divergent_names(file_index: &FileIndex) {
+    println!("\nSame name, different content:");
+    for divergent in report::divergent_names(file_index) {
+        println!("{}", divergent.name.green());
+        for variant in divergent.variants {
+            let paths: Vec<_> = variant.iter().map(|p| p.to_string_lossy()).collect();
+            println!("  {}", paths.join(", ").yellow());
+        }
+    }
 }
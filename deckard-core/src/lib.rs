@@ -0,0 +1,395 @@
+//! Grouping of duplicate files from their sizes and hashes. It never touches
+//! the file system, so it also builds for wasm32 to explore exported indexes
+//! in the browser.
+
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+/// Copies of every file that has at least one, linked both ways
+pub type Duplicates = HashMap<PathBuf, HashSet<PathBuf>>;
+
+/// Content hashes of a file, borrowed from an indexed entry
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Hashes<'a> {
+    pub size: u64,
+    pub hash: Option<&'a str>,
+    pub full_hash: Option<&'a str>,
+    pub paranoid_hash: Option<&'a str>,
+}
+
+impl Hashes<'_> {
+    /// Same size and quick hash, and the full hash when it's required.
+    /// Paranoid mode always requires both the full and the paranoid hash.
+    pub fn same_content(&self, other: &Self, full_hash: bool, paranoid: bool) -> bool {
+        if self.size != other.size || self.hash.is_none() || self.hash != other.hash {
+            return false;
+        }
+        if !full_hash && !paranoid {
+            return true;
+        }
+        let paranoid_match = !paranoid
+            || (self.paranoid_hash.is_some() && self.paranoid_hash == other.paranoid_hash);
+        self.full_hash.is_some() && self.full_hash == other.full_hash && paranoid_match
+    }
+}
+
+/// A processed file as exported with the index, other fields are ignored
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+pub struct Entry {
+    pub path: PathBuf,
+    pub size: u64,
+    pub hash: Option<String>,
+    pub full_hash: Option<String>,
+    #[serde(default)]
+    pub paranoid_hash: Option<String>,
+}
+
+impl Entry {
+    pub fn hashes(&self) -> Hashes<'_> {
+        Hashes {
+            size: self.size,
+            hash: self.hash.as_deref(),
+            full_hash: self.full_hash.as_deref(),
+            paranoid_hash: self.paranoid_hash.as_deref(),
+        }
+    }
+}
+
+/// The parts of an index saved with `FileIndex::save` needed to explore it
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+pub struct ExportedIndex {
+    #[serde(default)]
+    pub files: HashMap<PathBuf, Entry>,
+    #[serde(default)]
+    pub duplicates: Duplicates,
+}
+
+impl ExportedIndex {
+    pub fn from_json(json: &str) -> Result<Self, serde_json::Error> {
+        serde_json::from_str(json)
+    }
+
+    /// Space freed by keeping only the largest file of every group
+    pub fn reclaimable_size(&self) -> u64 {
+        reclaimable_size(&groups(&self.duplicates), |path| {
+            self.files.get(path).map(|f| f.size)
+        })
+    }
+}
+
+/// Record that the two files are copies of each other
+pub fn link(duplicates: &mut Duplicates, a: &Path, b: &Path) {
+    duplicates
+        .entry(a.to_path_buf())
+        .or_default()
+        .insert(b.to_path_buf());
+    duplicates
+        .entry(b.to_path_buf())
+        .or_default()
+        .insert(a.to_path_buf());
+}
+
+/// How matches chain into groups. Exact copies always form coherent groups,
+/// but with perceptual matching A can match B and B match C while A and C
+/// don't match.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Transitivity {
+    /// Files connected through any chain of matches share a group
+    #[default]
+    Transitive,
+    /// Every file of a group matches every other file of it, files are
+    /// placed in the first group they fully match in path order
+    Strict,
+}
+
+/// Disjoint sets of indices with path compression and union by size
+#[derive(Debug, Clone)]
+pub struct UnionFind {
+    parent: Vec<usize>,
+    size: Vec<usize>,
+}
+
+impl UnionFind {
+    pub fn new(len: usize) -> Self {
+        Self {
+            parent: (0..len).collect(),
+            size: vec![1; len],
+        }
+    }
+
+    pub fn find(&mut self, i: usize) -> usize {
+        let mut root = i;
+        while self.parent[root] != root {
+            root = self.parent[root];
+        }
+        let mut i = i;
+        while self.parent[i] != root {
+            let next = self.parent[i];
+            self.parent[i] = root;
+            i = next;
+        }
+        root
+    }
+
+    pub fn union(&mut self, a: usize, b: usize) {
+        let (a, b) = (self.find(a), self.find(b));
+        if a == b {
+            return;
+        }
+        let (large, small) = if self.size[a] >= self.size[b] {
+            (a, b)
+        } else {
+            (b, a)
+        };
+        self.parent[small] = large;
+        self.size[large] += self.size[small];
+    }
+}
+
+/// Split the linked files into groups of copies, connected through any chain of matches
+pub fn groups(duplicates: &Duplicates) -> Vec<Vec<PathBuf>> {
+    groups_with(duplicates, Transitivity::Transitive)
+}
+
+/// Split the linked files into groups following the transitivity policy.
+/// The files of a group and the groups are sorted by path, so the same
+/// matches always give the same groups.
+pub fn groups_with(duplicates: &Duplicates, transitivity: Transitivity) -> Vec<Vec<PathBuf>> {
+    match transitivity {
+        Transitivity::Transitive => groups_chaining(duplicates, |_, _| true),
+        Transitivity::Strict => groups_chaining(duplicates, |_, _| false),
+    }
+}
+
+/// Split the linked files into groups where only the links accepted by
+/// `chains` connect files transitively. Files joined by the other links
+/// must match every file of the group directly, and are placed in the
+/// first group they fully match in path order.
+pub fn groups_chaining(
+    duplicates: &Duplicates,
+    chains: impl Fn(&PathBuf, &PathBuf) -> bool,
+) -> Vec<Vec<PathBuf>> {
+    let mut files: Vec<&PathBuf> = duplicates.keys().collect();
+    files.sort();
+    let position: HashMap<&PathBuf, usize> =
+        files.iter().enumerate().map(|(i, f)| (*f, i)).collect();
+
+    let mut linked = UnionFind::new(files.len());
+    let mut chained = UnionFind::new(files.len());
+    for (file, copies) in duplicates {
+        for copy in copies {
+            if let Some(&j) = position.get(copy) {
+                linked.union(position[file], j);
+                if chains(file, copy) {
+                    chained.union(position[file], j);
+                }
+            }
+        }
+    }
+
+    // files in path order, so the clusters are too
+    let mut components: HashMap<usize, Vec<usize>> = HashMap::new();
+    let mut clusters: HashMap<usize, Vec<&PathBuf>> = HashMap::new();
+    for (i, file) in files.iter().enumerate() {
+        let cluster = chained.find(i);
+        if !clusters.contains_key(&cluster) {
+            components.entry(linked.find(i)).or_default().push(cluster);
+        }
+        clusters.entry(cluster).or_default().push(file);
+    }
+
+    let mut groups: Vec<Vec<PathBuf>> = Vec::new();
+    for component in components.into_values() {
+        let component: Vec<&[&PathBuf]> = component.iter().map(|c| &clusters[c][..]).collect();
+        groups.extend(cliques(duplicates, &component));
+    }
+    groups.retain(|group| group.len() > 1);
+    groups.sort();
+    groups
+}
+
+/// Merge the chained clusters of a component into groups whose clusters
+/// all match each other directly
+fn cliques(duplicates: &Duplicates, component: &[&[&PathBuf]]) -> Vec<Vec<PathBuf>> {
+    let matches = |a: &PathBuf, b: &PathBuf| duplicates.get(a).is_some_and(|c| c.contains(b));
+    let mut cliques: Vec<Vec<PathBuf>> = Vec::new();
+    for cluster in component {
+        match cliques.iter_mut().find(|clique| {
+            clique
+                .iter()
+                .all(|member| cluster.iter().all(|file| matches(member, file)))
+        }) {
+            Some(clique) => clique.extend(cluster.iter().map(|f| (*f).clone())),
+            None => cliques.push(cluster.iter().map(|f| (*f).clone()).collect()),
+        }
+    }
+    for clique in &mut cliques {
+        clique.sort();
+    }
+    cliques
+}
+
+/// Space freed by keeping only the largest file of every group
+pub fn reclaimable_size(groups: &[Vec<PathBuf>], size: impl Fn(&PathBuf) -> Option<u64>) -> u64 {
+    groups
+        .iter()
+        .map(|group| {
+            let sizes: Vec<u64> = group.iter().filter_map(&size).collect();
+            let total: u64 = sizes.iter().sum();
+            total - sizes.iter().max().unwrap_or(&0)
+        })
+        .sum()
+}
+
+/// Link the entries with the same content, only entries of the same size and
+/// quick hash are compared
+pub fn find_duplicates(entries: &[Entry], full_hash: bool, paranoid: bool) -> Duplicates {
+    let mut buckets: HashMap<(u64, &str), Vec<&Entry>> = HashMap::new();
+    for entry in entries {
+        if let Some(hash) = entry.hash.as_deref() {
+            buckets.entry((entry.size, hash)).or_default().push(entry);
+        }
+    }
+
+    let mut duplicates = Duplicates::new();
+    for bucket in buckets.values() {
+        for (i, a) in bucket.iter().enumerate() {
+            for b in &bucket[i + 1..] {
+                if a.hashes().same_content(&b.hashes(), full_hash, paranoid) {
+                    link(&mut duplicates, &a.path, &b.path);
+                }
+            }
+        }
+    }
+    duplicates
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(path: &str, size: u64, hash: &str, full_hash: Option<&str>) -> Entry {
+        Entry {
+            path: PathBuf::from(path),
+            size,
+            hash: Some(hash.to_string()),
+            full_hash: full_hash.map(str::to_string),
+            paranoid_hash: None,
+        }
+    }
+
+    #[test]
+    fn group_entries_by_content() {
+        let entries = vec![
+            entry("a", 10, "x", Some("1")),
+            entry("b", 10, "x", Some("1")),
+            entry("c", 10, "x", Some("2")),
+            entry("d", 20, "x", Some("1")),
+            entry("e", 10, "y", Some("1")),
+        ];
+
+        let quick = groups(&find_duplicates(&entries, false, false));
+        assert_eq!(quick.len(), 1);
+        assert_eq!(quick[0].len(), 3);
+
+        let full = groups(&find_duplicates(&entries, true, false));
+        assert_eq!(full, vec![vec![PathBuf::from("a"), PathBuf::from("b")]]);
+        assert_eq!(reclaimable_size(&full, |_| Some(10)), 10);
+    }
+
+    #[test]
+    fn paranoid_requires_full_hashes() {
+        let a = entry("a", 10, "x", Some("1"));
+        let mut b = entry("b", 10, "x", Some("1"));
+
+        // without paranoid hashes nothing matches, even without full hashing
+        assert!(!a.hashes().same_content(&b.hashes(), false, true));
+
+        let a = Entry {
+            paranoid_hash: Some("p".to_string()),
+            ..a
+        };
+        b.paranoid_hash = Some("q".to_string());
+        assert!(!a.hashes().same_content(&b.hashes(), false, true));
+        b.paranoid_hash = Some("p".to_string());
+        assert!(a.hashes().same_content(&b.hashes(), false, true));
+        b.full_hash = Some("2".to_string());
+        assert!(!a.hashes().same_content(&b.hashes(), false, true));
+    }
+
+    #[test]
+    fn group_chained_matches() {
+        let mut duplicates = Duplicates::new();
+        link(&mut duplicates, Path::new("c"), Path::new("b"));
+        link(&mut duplicates, Path::new("b"), Path::new("a"));
+        link(&mut duplicates, Path::new("y"), Path::new("x"));
+        let paths = |names: &[&str]| names.iter().map(PathBuf::from).collect::<Vec<_>>();
+
+        assert_eq!(
+            groups(&duplicates),
+            vec![paths(&["a", "b", "c"]), paths(&["x", "y"])]
+        );
+        // a and c don't match, c is left without a group
+        assert_eq!(
+            groups_with(&duplicates, Transitivity::Strict),
+            vec![paths(&["a", "b"]), paths(&["x", "y"])]
+        );
+
+        link(&mut duplicates, Path::new("a"), Path::new("c"));
+        assert_eq!(
+            groups_with(&duplicates, Transitivity::Strict),
+            vec![paths(&["a", "b", "c"]), paths(&["x", "y"])]
+        );
+    }
+
+    #[test]
+    fn group_chaining_links() {
+        let mut duplicates = Duplicates::new();
+        // a burst where the links to d are weak
+        link(&mut duplicates, Path::new("a"), Path::new("b"));
+        link(&mut duplicates, Path::new("b"), Path::new("c"));
+        link(&mut duplicates, Path::new("c"), Path::new("d"));
+        link(&mut duplicates, Path::new("d"), Path::new("e"));
+        let paths = |names: &[&str]| names.iter().map(PathBuf::from).collect::<Vec<_>>();
+        let strong = |a: &PathBuf, b: &PathBuf| {
+            let pair = [a.as_path(), b.as_path()];
+            !pair.contains(&Path::new("d"))
+        };
+
+        // d doesn't match a or b directly so it can't join them
+        assert_eq!(
+            groups_chaining(&duplicates, strong),
+            vec![paths(&["a", "b", "c"]), paths(&["d", "e"])]
+        );
+        assert_eq!(
+            groups_chaining(&duplicates, |_, _| true),
+            vec![paths(&["a", "b", "c", "d", "e"])]
+        );
+    }
+
+    #[test]
+    fn union_find_sets() {
+        let mut sets = UnionFind::new(5);
+        sets.union(0, 1);
+        sets.union(3, 4);
+        sets.union(1, 4);
+        assert_eq!(sets.find(0), sets.find(3));
+        assert_ne!(sets.find(0), sets.find(2));
+    }
+
+    #[test]
+    fn read_exported_index() {
+        let json = r#"{
+            "dirs": ["/data"],
+            "files": {"/data/a": {"path": "/data/a", "name": "a", "size": 4, "hash": "h", "full_hash": null},
+                      "/data/b": {"path": "/data/b", "name": "b", "size": 4, "hash": "h", "full_hash": null}},
+            "duplicates": {"/data/a": ["/data/b"], "/data/b": ["/data/a"]}
+        }"#;
+        let index = ExportedIndex::from_json(json).unwrap();
+        assert_eq!(index.files.len(), 2);
+        assert_eq!(groups(&index.duplicates).len(), 1);
+        assert_eq!(index.reclaimable_size(), 4);
+    }
+}
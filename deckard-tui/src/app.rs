@@ -1,37 +1,62 @@
 use std::{
-    borrow::Cow,
     collections::{HashMap, HashSet},
-    env,
-    ops::Index,
-    path::PathBuf,
-    usize,
+    env, fs,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{Receiver, TryRecvError},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
 };
 
-use color_eyre::{
-    eyre::{bail, Result, WrapErr},
-    owo_colors::OwoColorize,
-};
-use deckard::config::SearchConfig;
+use log::error;
+
+use color_eyre::eyre::{bail, Result, WrapErr};
+use deckard::config::{SearchConfig, TableColumn};
 use ratatui::{
     buffer::Buffer,
-    crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind},
-    layout::{Alignment, Constraint, Direction, Layout, Margin, Rect},
+    crossterm::event::{
+        self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers, MouseButton, MouseEvent,
+        MouseEventKind,
+    },
+    layout::{Constraint, Direction, Layout, Rect},
     style::{Modifier, Style, Stylize},
-    symbols::border,
     text::{Line, Text},
-    widgets::{
-        block::{title, Position, Title},
-        Block, BorderType, Borders, Cell, HighlightSpacing, Paragraph, Row, Scrollbar,
-        ScrollbarOrientation, ScrollbarState, StatefulWidget, Table, TableState, Widget,
-    },
-    Frame,
+    widgets::{Block, BorderType, Borders, Cell, Clear, Gauge, Paragraph, Row, Table, Widget},
 };
 
-use deckard::index::FileIndex;
-
-use crate::table::FileTable;
+use deckard::cache::HashCache;
+use deckard::file::get_mime_type;
+use deckard::fsops::{self, RemovalPreview};
+use deckard::index::{FileIndex, PriorityQueue};
+#[cfg(feature = "notify")]
+use deckard::notify;
+use deckard::policy::{self, KeepPolicy};
+use deckard::progress::{Progress, ProgressCallback, ScanPhase};
+use deckard::selection::Selection;
+
+use crate::command::Command;
+use crate::filter::Filter;
+use crate::help::Help;
+use crate::settings::Setting;
+use crate::table::{column_name, parse_column, FileTable, Jump};
+use crate::tree::FileTree;
+
+/// Emptied directories listed before confirming a removal
+const PREVIEW_DIRS: usize = 5;
+/// Longest wait for a cancelled scan to stop when quitting
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Percentage a pane grows or shrinks by with each key press
+const RESIZE_STEP: u16 = 5;
+/// Narrowest a pane can get, in percent
+const MIN_PANE: u16 = 20;
 
 #[derive(Debug, Default)]
+// the marked files and the help can't take the focus yet
+#[allow(dead_code)]
 enum FocusedWindow {
     #[default]
     Files,
@@ -40,15 +65,6 @@ enum FocusedWindow {
     Help,
 }
 
-#[derive(Debug, Default)]
-enum Sorting {
-    #[default]
-    None,
-    Count,
-    Size,
-    Date,
-}
-
 #[derive(Debug, Default)]
 pub struct App {
     focused_window: FocusedWindow,
@@ -57,72 +73,387 @@ pub struct App {
     file_table: FileTable,
     clone_table: FileTable,
     marked_table: FileTable,
+    file_tree: FileTree,
     marked_files: HashSet<PathBuf>,
+    /// Sizes shown in the summary, refreshed when the marks change
+    marked_size: u64,
+    reclaimable_size: u64,
+    confirm_delete: Option<PathBuf>,
+    confirm_remove: Option<RemovalPreview>,
+    dir_stats: Option<PathBuf>,
+    show_denied: bool,
+    /// Draft of the config edited in the settings screen and the selected setting
+    settings: Option<(SearchConfig, usize)>,
+    help: Option<Help>,
+    /// Count and `g` typed before a jump key
+    pending_keys: String,
+    /// Conditions the listed files have to meet
+    filters: Vec<Filter>,
+    command_input: Option<String>,
+    status: Option<String>,
+    scan: Option<JoinHandle<FileIndex>>,
+    scan_progress: Arc<Mutex<Progress>>,
+    scan_indexed: Arc<Mutex<Option<FileIndex>>>,
+    priority: PriorityQueue,
+    priority_requests: Vec<(PathBuf, Receiver<HashSet<PathBuf>>)>,
+    cancel: Arc<AtomicBool>,
     show_clones_table: bool,
     show_marked_table: bool,
     show_file_info: bool,
+    /// Columns or pane sizes changed since the config was saved
+    layout_changed: bool,
 }
 
 impl App {
     pub fn new(target_paths: HashSet<PathBuf>, config: SearchConfig) -> Self {
+        let columns = config.tui.columns.clone();
         Self {
             focused_window: FocusedWindow::Files,
             exit: false,
             file_index: FileIndex::new(target_paths, config),
-            file_table: FileTable::new(vec!["File", "Size", "Count", " "]),
-            clone_table: FileTable::new(vec!["Clone", "Date", "Size", " "]),
-            marked_table: FileTable::new(vec![]),
+            file_table: FileTable::new("File", columns.clone()),
+            clone_table: FileTable::new("Clone", columns.clone()),
+            marked_table: FileTable::new("", columns),
+            file_tree: FileTree::new(),
             marked_files: HashSet::new(),
+            marked_size: 0,
+            reclaimable_size: 0,
+            confirm_delete: None,
+            confirm_remove: None,
+            dir_stats: None,
+            show_denied: false,
+            settings: None,
+            help: None,
+            pending_keys: String::new(),
+            filters: Vec::new(),
+            command_input: None,
+            status: None,
+            scan: None,
+            scan_progress: Arc::new(Mutex::new(Progress::default())),
+            scan_indexed: Arc::new(Mutex::new(None)),
+            priority: PriorityQueue::default(),
+            priority_requests: Vec::new(),
+            cancel: Arc::new(AtomicBool::new(false)),
             show_marked_table: true,
             show_clones_table: true,
             show_file_info: true,
+            layout_changed: false,
         }
     }
 
     /// runs the application's main loop until the user quits
     pub fn run(&mut self, terminal: &mut crate::tui::Tui) -> Result<()> {
-        self.file_index.index_dirs();
-        self.file_index.process_files();
-        self.file_index.find_duplicates();
-
-        // update
-        if self.file_index.duplicates_len() > 0 {
-            self.update_file_table();
-            self.update_clone_table();
-        }
+        self.start_scan();
 
         while !self.exit {
+            if self.scan.as_ref().is_some_and(|s| s.is_finished()) {
+                self.finish_scan();
+            } else if self.is_scanning() {
+                self.poll_scan();
+            }
             terminal.draw(|frame| self.render_ui(frame.area(), frame.buffer_mut()))?;
             self.handle_events().wrap_err("handle events failed")?;
         }
+        if self.is_scanning() {
+            self.status = Some("stopping the scan".to_string());
+            terminal.draw(|frame| self.render_ui(frame.area(), frame.buffer_mut()))?;
+        }
+        self.save_layout();
+        self.shutdown()
+    }
+
+    /// Cancel a running scan and wait for the files in progress to finish,
+    /// so the scan thread saves the hash cache before the process exits
+    fn shutdown(&mut self) -> Result<()> {
+        let Some(scan) = self.scan.take() else {
+            return Ok(());
+        };
+        self.cancel_scan();
+        let start = Instant::now();
+        while !scan.is_finished() {
+            if start.elapsed() >= SHUTDOWN_TIMEOUT {
+                bail!("the scan didn't stop within {:?}", SHUTDOWN_TIMEOUT);
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        if scan.join().is_err() {
+            bail!("the scan thread panicked");
+        }
         Ok(())
     }
 
+    /// Scan the target paths in a background thread
+    fn start_scan(&mut self) {
+        let mut file_index = std::mem::take(&mut self.file_index);
+        let progress = self.scan_progress.clone();
+        let indexed = self.scan_indexed.clone();
+        let cancel = self.cancel.clone();
+        self.priority = file_index.priority_queue();
+
+        self.scan = Some(thread::spawn(move || {
+            file_index.index_dirs();
+            if file_index.config.disk_usage_mode {
+                // only the sizes are needed to browse the disk usage
+                return file_index;
+            }
+            // let the user pick files to process first
+            *indexed.lock().unwrap() = Some(file_index.clone());
+
+            if file_index.config.cache {
+                file_index.cache = Some(HashCache::load(
+                    HashCache::default_path(),
+                    &file_index.config,
+                ));
+            }
+
+            let callback_progress = progress.clone();
+            let callback: ProgressCallback = Arc::new(move |update: &Progress| {
+                *callback_progress.lock().unwrap() = update.clone();
+            });
+
+            progress.lock().unwrap().phase = ScanPhase::Processing;
+            file_index.process_files(Some(cancel.clone()), Some(callback.clone()));
+            if let Some(cache) = &file_index.cache {
+                if let Err(e) = cache.save(HashCache::default_path()) {
+                    error!("failed saving hash cache: {}", e);
+                }
+            }
+
+            *progress.lock().unwrap() = Progress {
+                phase: ScanPhase::Comparing,
+                ..Default::default()
+            };
+            file_index.find_duplicates(Some(cancel), Some(callback));
+            #[cfg(feature = "notify")]
+            if file_index.config.notify && !file_index.stats.cancelled {
+                notify::scan_finished(&file_index);
+            }
+
+            file_index
+        }));
+    }
+
+    /// List the indexed files and show the duplicates of requested files while scanning
+    fn poll_scan(&mut self) {
+        if let Some(file_index) = self.scan_indexed.lock().unwrap().take() {
+            self.file_index = file_index;
+            let mut paths: Vec<PathBuf> = self.file_index.files.keys().cloned().collect();
+            paths.sort_by_key(|p| std::cmp::Reverse(self.file_index.file_size(p)));
+            self.file_table.update_table(paths);
+            self.file_table.select_first();
+        }
+
+        let mut answered = Vec::new();
+        self.priority_requests
+            .retain(|(path, copies)| match copies.try_recv() {
+                Ok(copies) => {
+                    answered.push((path.clone(), copies));
+                    false
+                }
+                Err(TryRecvError::Empty) => true,
+                Err(TryRecvError::Disconnected) => false,
+            });
+
+        for (path, copies) in answered {
+            self.status = Some(format!(
+                "{} has {} duplicates",
+                path.to_string_lossy(),
+                copies.len()
+            ));
+            self.file_index.duplicates.insert(path, copies);
+            self.update_clone_table();
+        }
+    }
+
+    /// Ask the scan to process the selected file and its possible copies next
+    fn process_now(&mut self) {
+        let processing = matches!(
+            self.scan_progress.lock().unwrap().phase,
+            ScanPhase::Processing
+        );
+        if !processing {
+            return;
+        }
+        if let Some(path) = self.file_table.selected_path() {
+            let copies = self.priority.request(path.clone());
+            self.status = Some(format!("processing {} first", path.to_string_lossy()));
+            self.priority_requests.push((path, copies));
+        }
+    }
+
+    /// Take over the index from the finished scan
+    fn finish_scan(&mut self) {
+        let Some(scan) = self.scan.take() else {
+            return;
+        };
+        match scan.join() {
+            Ok(file_index) => self.file_index = file_index,
+            Err(_) => {
+                error!("scan thread panicked");
+                return;
+            }
+        }
+        self.priority_requests.clear();
+        self.update_sizes();
+        self.file_table.update_table(Vec::new());
+        self.clone_table.update_table(Vec::new());
+
+        if self.disk_usage_mode() {
+            self.show_clones_table = false;
+            self.file_tree.update_tree(&self.file_index);
+            self.file_tree.select_first();
+        } else if self.file_index.duplicates_len() > 0 {
+            self.update_file_table();
+            self.update_clone_table();
+        }
+    }
+
+    fn is_scanning(&self) -> bool {
+        self.scan.is_some()
+    }
+
+    fn scan_cancelled(&self) -> bool {
+        self.cancel.load(Ordering::Relaxed)
+    }
+
+    /// Stop the scan early, the duplicates found so far are kept
+    fn cancel_scan(&mut self) {
+        self.cancel.store(true, Ordering::Relaxed);
+    }
+
     /// updates the application's state based on user input
     fn handle_events(&mut self) -> Result<()> {
+        // keep redrawing the progress while scanning
+        if !event::poll(Duration::from_millis(100))? {
+            return Ok(());
+        }
         match event::read()? {
             // it's important to check that the event is a key press event as
             // crossterm also emits key release and repeat events on Windows.
             Event::Key(key_event) if key_event.kind == KeyEventKind::Press => self
                 .handle_key_event(key_event)
                 .wrap_err_with(|| format!("handling key event failed:\n{key_event:#?}")),
+            Event::Mouse(MouseEvent {
+                kind: MouseEventKind::Down(MouseButton::Left),
+                column,
+                row,
+                ..
+            }) => {
+                self.click_header(column, row);
+                Ok(())
+            }
             _ => Ok(()),
         }
     }
 
     fn handle_key_event(&mut self, key_event: KeyEvent) -> Result<()> {
+        if self.is_scanning() {
+            match key_event.code {
+                KeyCode::Char('q') => {
+                    self.cancel_scan();
+                    self.exit();
+                }
+                KeyCode::Esc => self.cancel_scan(),
+                KeyCode::Char('j') | KeyCode::Down => self.next(),
+                KeyCode::Char('k') | KeyCode::Up => self.previous(),
+                KeyCode::Char('n') => self.process_now(),
+                _ => {}
+            }
+            return Ok(());
+        }
+
+        if self.command_input.is_some() {
+            self.handle_command_input(key_event);
+            return Ok(());
+        }
+
+        if self.confirm_delete.is_some() {
+            match key_event.code {
+                KeyCode::Char('y') => self.delete_confirmed(),
+                _ => self.confirm_delete = None,
+            }
+            return Ok(());
+        }
+
+        if let Some(preview) = self.confirm_remove.take() {
+            if key_event.code == KeyCode::Char('y') {
+                self.remove_confirmed(preview);
+            }
+            return Ok(());
+        }
+
+        if self.show_denied {
+            self.show_denied = false;
+            return Ok(());
+        }
+
+        if self.settings.is_some() {
+            self.handle_settings_key(key_event.code);
+            return Ok(());
+        }
+
+        if let Some(help) = self.help.as_mut() {
+            if !help.handle_key(key_event.code) {
+                self.help = None;
+            }
+            return Ok(());
+        }
+
+        if let Some(dir) = self.dir_stats.take() {
+            if key_event.code == KeyCode::Char('m') {
+                self.mark_dir(&dir);
+            }
+            return Ok(());
+        }
+
+        if self.disk_usage_mode() {
+            match key_event.code {
+                KeyCode::Char('l') | KeyCode::Right | KeyCode::Enter => {
+                    self.file_tree.enter();
+                    return Ok(());
+                }
+                KeyCode::Char('h') | KeyCode::Left => {
+                    self.file_tree.leave();
+                    return Ok(());
+                }
+                _ => {}
+            }
+        }
+
+        if self.handle_visual_key(key_event.code) {
+            return Ok(());
+        }
+
+        if self.handle_jump_key(key_event) {
+            return Ok(());
+        }
+
+        self.status = None;
         match key_event.code {
             KeyCode::Char('q') | KeyCode::Esc => self.exit(),
+            KeyCode::Char(':') => self.command_input = Some(String::new()),
+            KeyCode::Char('?') => self.help = Some(Help::default()),
+            KeyCode::Char('n') => self.select_marked(true),
+            KeyCode::Char('N') => self.select_marked(false),
             KeyCode::Char('j') | KeyCode::Down => self.next(),
             KeyCode::Char('k') | KeyCode::Up => self.previous(),
             KeyCode::Char('i') => self.toggle_info(),
             KeyCode::Char('o') => self.open_file(),
+            KeyCode::Char('r') => self.start_rename(),
             KeyCode::Char('p') => self.open_path(),
             KeyCode::Char('D') | KeyCode::Delete => self.delete(),
             KeyCode::Char('t') | KeyCode::Backspace => self.trash(),
             KeyCode::Char('c') => self.toggle_show_clones_table(),
             KeyCode::Char(' ') => self.mark(),
+            KeyCode::Char('x') => self.toggle_mark(),
+            KeyCode::Char('v') => self.start_visual(),
             KeyCode::Char('a') => self.mark_all(),
+            KeyCode::Char('>') => self.resize_panes(RESIZE_STEP as i16, 0),
+            KeyCode::Char('<') => self.resize_panes(-(RESIZE_STEP as i16), 0),
+            KeyCode::Char('+') | KeyCode::Char('=') => self.resize_panes(0, RESIZE_STEP as i16),
+            KeyCode::Char('-') => self.resize_panes(0, -(RESIZE_STEP as i16)),
+            KeyCode::Char('s') => self.cycle_sort(),
+            KeyCode::Char('S') => self.toggle_sort_direction(),
             KeyCode::Char('l') | KeyCode::Right => self.focus_clones_table(),
             KeyCode::Char('h') | KeyCode::Left => self.focus_files_table(),
             _ => {}
@@ -130,35 +461,441 @@ impl App {
         Ok(())
     }
 
+    fn handle_command_input(&mut self, key_event: KeyEvent) {
+        let Some(input) = self.command_input.as_mut() else {
+            return;
+        };
+        match key_event.code {
+            KeyCode::Char(c) => input.push(c),
+            KeyCode::Backspace if input.is_empty() => self.command_input = None,
+            KeyCode::Backspace => _ = input.pop(),
+            KeyCode::Enter => {
+                let input = input.clone();
+                self.command_input = None;
+                self.execute_command(&input);
+            }
+            KeyCode::Esc => self.command_input = None,
+            _ => {}
+        }
+    }
+
+    fn handle_settings_key(&mut self, key: KeyCode) {
+        let Some((draft, selected)) = self.settings.as_mut() else {
+            return;
+        };
+        let setting = Setting::ALL[*selected];
+        let mut value = setting.get(draft);
+        if value.edit(key) {
+            setting.set(draft, value);
+            return;
+        }
+        match key {
+            KeyCode::Char('j') | KeyCode::Down => {
+                *selected = (*selected + 1) % Setting::ALL.len();
+            }
+            KeyCode::Char('k') | KeyCode::Up => {
+                *selected = (*selected + Setting::ALL.len() - 1) % Setting::ALL.len();
+            }
+            KeyCode::Char('s') | KeyCode::Enter => self.save_settings(false),
+            KeyCode::Char('r') => self.save_settings(true),
+            KeyCode::Char('q') | KeyCode::Esc => self.settings = None,
+            _ => {}
+        }
+    }
+
+    /// Apply the edited config and save the edited settings, rescanning with
+    /// it when asked. The command line overrides are not saved.
+    fn save_settings(&mut self, rescan: bool) {
+        let Some((draft, _)) = self.settings.take() else {
+            return;
+        };
+        let mut saved = SearchConfig::load("deckard-cli");
+        for setting in Setting::ALL {
+            setting.set(&mut saved, setting.get(&draft));
+        }
+        saved.save("deckard-cli");
+        self.file_index.config = draft;
+        if rescan {
+            self.rescan();
+            self.status = Some("saved the settings, rescanning".to_string());
+        } else {
+            self.status = Some("saved the settings, rescan to apply them".to_string());
+        }
+    }
+
+    /// Show the columns in every table, they are saved on exit
+    fn set_columns(&mut self, columns: Vec<TableColumn>) {
+        self.file_table.set_columns(columns.clone());
+        self.clone_table.set_columns(columns.clone());
+        self.marked_table.set_columns(columns.clone());
+        self.file_index.config.tui.columns = columns;
+        self.layout_changed = true;
+        self.resort();
+    }
+
+    /// Save the columns and pane sizes into the config file, leaving the rest
+    /// of the file as it is
+    fn save_layout(&mut self) {
+        if !self.layout_changed {
+            return;
+        }
+        let mut saved = SearchConfig::load("deckard-cli");
+        saved.tui = self.file_index.config.tui.clone();
+        saved.save("deckard-cli");
+        self.layout_changed = false;
+    }
+
+    /// Grow or shrink the files pane and the tables by percentage points, the
+    /// layout is saved on exit
+    fn resize_panes(&mut self, width: i16, height: i16) {
+        let resize = |percent: u16, by: i16| {
+            percent
+                .saturating_add_signed(by)
+                .clamp(MIN_PANE, 100 - MIN_PANE)
+        };
+        let tui = &mut self.file_index.config.tui;
+        tui.files_width = resize(tui.files_width, width);
+        tui.tables_height = resize(tui.tables_height, height);
+        self.status = Some(format!(
+            "files {}% wide, tables {}% high",
+            tui.files_width, tui.tables_height
+        ));
+        self.layout_changed = true;
+    }
+
+    /// Hide the column when shown, otherwise show it last
+    fn toggle_column(&mut self, column: TableColumn) {
+        let mut columns = self.file_index.config.tui.columns.clone();
+        if columns.contains(&column) {
+            columns.retain(|c| *c != column);
+            self.status = Some(format!("hid the {} column", column_name(column)));
+        } else {
+            columns.push(column);
+            self.status = Some(format!("showing the {} column", column_name(column)));
+        }
+        self.set_columns(columns);
+    }
+
+    /// Start over with the current config, the marked files are dropped
+    fn rescan(&mut self) {
+        let dirs = self.file_index.dirs.clone();
+        let config = self.file_index.config.clone();
+        self.file_index = FileIndex::new(dirs, config);
+        self.cancel = Arc::new(AtomicBool::new(false));
+        *self.scan_progress.lock().unwrap() = Progress::default();
+        self.marked_files.clear();
+        self.update_marked_table();
+        self.file_table.update_table(Vec::new());
+        self.file_table.select_none();
+        self.clone_table.update_table(Vec::new());
+        self.clone_table.select_none();
+        self.start_scan();
+    }
+
+    fn execute_command(&mut self, input: &str) {
+        if input.trim().is_empty() {
+            return;
+        }
+        let command = match Command::parse(input) {
+            Ok(command) => command,
+            Err(e) => {
+                self.status = Some(e);
+                return;
+            }
+        };
+
+        match command {
+            Command::MarkRoot(root) => self.mark_root(&root),
+            Command::FilterType(prefix) => {
+                if prefix.is_empty() {
+                    self.filters.retain(|f| !matches!(f, Filter::Type(_)));
+                    self.apply_filters();
+                } else {
+                    self.add_filter(Filter::Type(prefix));
+                }
+            }
+            Command::FilterRegex(pattern) => match Filter::regex(&pattern) {
+                Ok(filter) => self.add_filter(filter),
+                Err(e) => self.status = Some(e),
+            },
+            Command::FilterSize(size) => match Filter::size(&size) {
+                Ok(filter) => self.add_filter(filter),
+                Err(e) => self.status = Some(e),
+            },
+            Command::FilterExtension(ext) => self.add_filter(Filter::extension(&ext)),
+            Command::FilterClones(count) => match Filter::clones(&count) {
+                Ok(filter) => self.add_filter(filter),
+                Err(e) => self.status = Some(e),
+            },
+            Command::MarkClones(count) => match Filter::clones(&count) {
+                Ok(filter) => self.mark_matching(&filter),
+                Err(e) => self.status = Some(e),
+            },
+            Command::OpenWith(app) => self.open_with(&app),
+            Command::Rename(name) => self.rename(&name),
+            Command::MarkFilter(pattern) => match Filter::pattern(&pattern) {
+                Ok(filter) => self.mark_matching(&filter),
+                Err(e) => self.status = Some(e),
+            },
+            Command::Column(name) => match parse_column(&name) {
+                Ok(column) => self.toggle_column(column),
+                Err(e) => self.status = Some(e),
+            },
+            Command::Columns(names) => {
+                match names
+                    .split([',', ' '])
+                    .filter(|n| !n.is_empty())
+                    .map(parse_column)
+                    .collect::<Result<Vec<_>, _>>()
+                {
+                    Ok(mut columns) => {
+                        let mut seen = Vec::new();
+                        columns.retain(|c| {
+                            let first = !seen.contains(c);
+                            seen.push(*c);
+                            first
+                        });
+                        self.set_columns(columns);
+                        self.status = Some("saved the columns".to_string());
+                    }
+                    Err(e) => self.status = Some(e),
+                }
+            }
+            Command::FilterClear => {
+                self.filters.clear();
+                self.apply_filters();
+            }
+            Command::MarkKeep(policy) => match policy.parse() {
+                Ok(policy) => self.mark_keep(&policy),
+                Err(e) => self.status = Some(e),
+            },
+            Command::DirStats => {
+                self.dir_stats = self
+                    .active_selected_file()
+                    .and_then(|f| f.parent().map(|p| p.to_path_buf()));
+            }
+            Command::Denied => {
+                if self.file_index.denied.is_empty() {
+                    self.status = Some("no paths skipped due to permissions".to_string());
+                } else {
+                    self.show_denied = true;
+                }
+            }
+            Command::Settings => {
+                self.settings = Some((self.file_index.config.clone(), 0));
+            }
+            Command::SaveSelection(path) => {
+                let selection = Selection::new(&self.file_index, &self.marked_files);
+                self.status = Some(match selection.save(&path) {
+                    Ok(_) => format!("saved {} marked files to {}", selection.files.len(), path),
+                    Err(e) => format!("failed saving the selection: {}", e),
+                });
+            }
+        }
+    }
+
     fn exit(&mut self) {
         self.exit = true;
     }
 
     fn mark(&mut self) {
         if let Some(path) = self.active_selected_file() {
-            self.marked_files.insert(path.clone());
-            let v = self.marked_files.clone().into_iter().collect();
-            self.marked_table.update_table(&v);
+            self.marked_files.insert(path);
+            self.update_marked_table();
+        }
+    }
+
+    fn toggle_mark(&mut self) {
+        if let Some(path) = self.active_selected_file() {
+            if !self.marked_files.remove(&path) {
+                self.marked_files.insert(path);
+            }
+            self.update_marked_table();
+        }
+    }
+
+    fn start_visual(&mut self) {
+        if self.disk_usage_mode() {
+            return;
+        }
+        if let Some(table) = self.focused_table() {
+            table.start_visual();
         }
     }
 
-    fn mark_all(&mut self) {}
+    /// Keys of visual mode, returns false when not in visual mode or the key
+    /// is handled as usual, like moving the selection
+    fn handle_visual_key(&mut self, key: KeyCode) -> bool {
+        let Some(table) = self.focused_table().filter(|t| t.in_visual()) else {
+            return false;
+        };
+        match key {
+            KeyCode::Char(' ') | KeyCode::Char('m') => {
+                let paths = table.visual_paths();
+                table.end_visual();
+                self.mark_range(paths);
+            }
+            KeyCode::Char('v') | KeyCode::Esc => table.end_visual(),
+            // switching tables leaves visual mode
+            KeyCode::Char('h') | KeyCode::Left | KeyCode::Char('l') | KeyCode::Right => {
+                table.end_visual();
+                return false;
+            }
+            _ => return false,
+        }
+        true
+    }
 
-    fn clear_mark(&mut self) {
-        self.marked_files = HashSet::new();
+    /// Mark every file of the range, or unmark them when all are marked
+    fn mark_range(&mut self, paths: Vec<PathBuf>) {
+        let count = paths.len();
+        if paths.iter().all(|p| self.marked_files.contains(p)) {
+            for path in &paths {
+                self.marked_files.remove(path);
+            }
+            self.status = Some(format!("unmarked {} files", count));
+        } else {
+            self.marked_files.extend(paths);
+            self.status = Some(format!("marked {} files", count));
+        }
+        self.update_marked_table();
+    }
+
+    /// Mark the shown duplicates found under a scan root, given by its path
+    /// or label. Files hidden by the filters or without a copy outside of
+    /// the root are left alone.
+    fn mark_root(&mut self, root: &str) {
+        let path = fs::canonicalize(root).unwrap_or_else(|_| PathBuf::from(root));
+        let root = self
+            .file_index
+            .dirs
+            .iter()
+            .find(|d| **d == path || d.file_name().is_some_and(|n| n.to_string_lossy() == root))
+            .cloned();
+        let Some(root) = root else {
+            self.status = Some(format!("no scan root matches {}", path.to_string_lossy()));
+            return;
+        };
+
+        let mut marked = 0;
+        let mut skipped = 0;
+        for (file, copies) in &self.file_index.duplicates {
+            if !file.starts_with(&root) || !self.matches_filters(file) {
+                continue;
+            }
+            if copies.iter().any(|c| !c.starts_with(&root)) {
+                if self.marked_files.insert(file.clone()) {
+                    marked += 1;
+                }
+            } else {
+                skipped += 1;
+            }
+        }
+        self.update_marked_table();
+
+        self.status = Some(format!(
+            "marked {} files under {}, {} without copies elsewhere skipped",
+            marked,
+            root.to_string_lossy(),
+            skipped
+        ));
+    }
+
+    /// Files directly inside the directory that have duplicates
+    fn dir_duplicates(&self, dir: &Path) -> Vec<PathBuf> {
+        self.file_index
+            .duplicates
+            .keys()
+            .filter(|f| f.parent() == Some(dir))
+            .cloned()
+            .collect()
+    }
+
+    fn mark_dir(&mut self, dir: &Path) {
+        let duplicates = self.dir_duplicates(dir);
+        let marked = duplicates.len();
+        self.marked_files.extend(duplicates);
+        self.update_marked_table();
+        self.status = Some(format!(
+            "marked {} duplicates in {}",
+            marked,
+            dir.to_string_lossy()
+        ));
+    }
+
+    fn update_marked_table(&mut self) {
+        let mut paths: Vec<PathBuf> = self.marked_files.iter().cloned().collect();
+        paths.sort();
+        self.marked_table.update_table(paths);
+        self.update_sizes();
+    }
+
+    fn update_sizes(&mut self) {
+        self.marked_size = self
+            .marked_files
+            .iter()
+            .filter_map(|f| self.file_index.file_size(f))
+            .sum();
+        self.reclaimable_size = self.file_index.reclaimable_size();
+    }
+
+    /// Mark every copy the keep policy would remove
+    fn mark_all(&mut self) {
+        let policy = self.file_index.config.action_config.keep_policy.clone();
+        self.mark_keep(&policy);
+    }
+
+    fn mark_keep(&mut self, policy: &KeepPolicy) {
+        let removals = policy::removals(policy, &self.file_index);
+        let marked = removals.len();
+        self.marked_files.extend(removals);
+        self.update_marked_table();
+        self.status = Some(format!(
+            "marked {} duplicates keeping the {:?} copy",
+            marked, policy
+        ));
+    }
+
+    fn disk_usage_mode(&self) -> bool {
+        self.file_index.config.disk_usage_mode
     }
 
     fn active_selected_file(&self) -> Option<PathBuf> {
-        if matches!(self.focused_window, FocusedWindow::Clones) {
+        if self.disk_usage_mode() {
+            self.file_tree.selected_path()
+        } else if matches!(self.focused_window, FocusedWindow::Clones) {
             self.clone_table.selected_path()
         } else {
             self.file_table.selected_path()
         }
     }
 
+    /// Open the selected file with the opener of its MIME type, or the
+    /// system default without one
     fn open_file(&mut self) {
-        if let Some(selected_file) = self.active_selected_file() {
-            _ = open::that_detached(selected_file);
+        let Some(selected_file) = self.active_selected_file() else {
+            return;
+        };
+        let mime_type = self
+            .file_index
+            .file_entry(&selected_file)
+            .and_then(|f| f.mime_type.clone())
+            .or_else(|| get_mime_type(&selected_file).ok());
+        let opener = mime_type
+            .and_then(|m| self.file_index.config.tui.opener_for(&m))
+            .map(str::to_string);
+        match opener {
+            Some(app) => self.open_with(&app),
+            None => _ = open::that_detached(selected_file),
+        }
+    }
+
+    fn open_with(&mut self, app: &str) {
+        let Some(selected_file) = self.active_selected_file() else {
+            return;
+        };
+        if let Err(e) = open::with_detached(&selected_file, app) {
+            self.status = Some(format!("failed opening with {}: {}", app, e));
         }
     }
 
@@ -170,7 +907,190 @@ impl App {
         }
     }
 
-    fn delete(&mut self) {}
+    /// Type the rename command with the current name of the selected file
+    fn start_rename(&mut self) {
+        let name = self
+            .active_selected_file()
+            .filter(|f| self.file_index.files.contains_key(f))
+            .and_then(|f| f.file_name().map(|n| n.to_string_lossy().to_string()));
+        if let Some(name) = name {
+            self.command_input = Some(format!("rename {}", name));
+        }
+    }
+
+    /// Rename the selected file within its directory
+    fn rename(&mut self, name: &str) {
+        if self.file_index.config.action_config.read_only {
+            self.status = Some("read-only mode, nothing can be renamed".to_string());
+            return;
+        }
+        let Some(from) = self
+            .active_selected_file()
+            .filter(|f| self.file_index.files.contains_key(f))
+        else {
+            self.status = Some("no file selected".to_string());
+            return;
+        };
+        if name.contains(std::path::is_separator) {
+            self.status = Some("the new name can't contain a path separator".to_string());
+            return;
+        }
+        let to = from.with_file_name(name);
+        if to == from {
+            return;
+        }
+        if let Err(e) = self.file_index.rename_file(&from, to.clone()) {
+            error!("failed renaming {:?}: {}", from, e);
+            self.status = Some(format!("failed renaming: {}", e));
+            return;
+        }
+
+        if self.marked_files.remove(&from) {
+            self.marked_files.insert(to.clone());
+        }
+        self.update_marked_table();
+        if self.disk_usage_mode() {
+            self.file_tree.update_tree(&self.file_index);
+            self.file_tree.select_first();
+        } else {
+            let selected =
+                self.file_table
+                    .selected_path()
+                    .map(|p| if p == from { to.clone() } else { p });
+            self.update_file_table();
+            if let Some(selected) = selected {
+                self.file_table.select_path(&selected);
+            }
+            self.update_clone_table();
+            self.clone_table.select_path(&to);
+        }
+        self.status = Some(format!("renamed to {}", name));
+    }
+
+    fn delete(&mut self) {
+        if self.file_index.config.action_config.read_only {
+            self.status = Some("read-only mode, nothing can be removed".to_string());
+        } else if self.disk_usage_mode() {
+            self.confirm_delete = self.file_tree.selected_path();
+        } else if !self.marked_files.is_empty() {
+            let marked: Vec<PathBuf> = self.marked_files.iter().cloned().collect();
+            self.confirm_remove = Some(fsops::preview_removal(
+                &marked,
+                &self.file_index.dirs,
+                !self.file_index.config.action_config.no_remove_dirs,
+            ));
+        }
+    }
+
+    fn remove_confirmed(&mut self, preview: RemovalPreview) {
+        if self.file_index.config.action_config.dry_run {
+            self.status = Some(format!(
+                "dry run, nothing was removed ({} files, {} directories)",
+                preview.files.len(),
+                preview.dirs.len()
+            ));
+            return;
+        }
+
+        let (preview, skipped) = if self.file_index.config.action_config.verify_before_action {
+            self.verify_removal(preview)
+        } else {
+            (preview, 0)
+        };
+
+        let outcome = fsops::remove(&preview);
+        let removed: HashSet<PathBuf> = outcome.files.iter().cloned().collect();
+        self.file_index.remove_files(&removed);
+        self.marked_files.retain(|f| !removed.contains(f));
+
+        self.status = Some(if skipped > 0 {
+            format!(
+                "removed {} files and {} directories, {} groups differ from the kept copy and were left alone",
+                outcome.files.len(),
+                outcome.dirs.len(),
+                skipped
+            )
+        } else {
+            format!(
+                "removed {} files and {} directories",
+                outcome.files.len(),
+                outcome.dirs.len()
+            )
+        });
+        self.update_file_table();
+        self.update_clone_table();
+        self.update_marked_table();
+    }
+
+    /// Compare the marked copies with an unmarked copy of their group, groups
+    /// with a mismatch are dropped from the removal. Returns the new preview
+    /// and the number of dropped groups.
+    fn verify_removal(&self, preview: RemovalPreview) -> (RemovalPreview, usize) {
+        let removed: HashSet<&PathBuf> = preview.files.iter().collect();
+        let mut groups: Vec<(PathBuf, Vec<PathBuf>)> = Vec::new();
+        let mut grouped: HashSet<PathBuf> = HashSet::new();
+        for mut group in self.file_index.duplicate_groups() {
+            group.sort();
+            let Some(keep) = group.iter().find(|f| !removed.contains(f)).cloned() else {
+                continue;
+            };
+            let files: Vec<PathBuf> = group.into_iter().filter(|f| removed.contains(f)).collect();
+            if !files.is_empty() {
+                grouped.extend(files.iter().cloned());
+                groups.push((keep, files));
+            }
+        }
+
+        let (mut verified, mismatches) = fsops::verify_groups(&groups);
+        if mismatches.is_empty() {
+            return (preview, 0);
+        }
+        // files without a kept copy to compare with are removed as marked
+        verified.extend(
+            preview
+                .files
+                .iter()
+                .filter(|f| !grouped.contains(*f))
+                .cloned(),
+        );
+        let preview = fsops::preview_removal(
+            &verified,
+            &self.file_index.dirs,
+            !self.file_index.config.action_config.no_remove_dirs,
+        );
+        (preview, mismatches.len())
+    }
+
+    fn delete_confirmed(&mut self) {
+        let Some(path) = self.confirm_delete.take() else {
+            return;
+        };
+        if self.file_index.config.action_config.dry_run {
+            self.status = Some(format!(
+                "dry run, {} was not deleted",
+                path.to_string_lossy()
+            ));
+            return;
+        }
+
+        let result = if path.is_dir() {
+            fs::remove_dir_all(&path)
+        } else {
+            fs::remove_file(&path)
+        };
+        if let Err(e) = result {
+            error!("failed deleting {:?}: {}", path, e);
+            return;
+        }
+
+        self.file_index.files.retain(|f, _| !f.starts_with(&path));
+        self.file_index
+            .duplicates
+            .retain(|f, _| !f.starts_with(&path));
+        self.marked_files.retain(|f| !f.starts_with(&path));
+        self.update_marked_table();
+        self.file_tree.remove(&path);
+    }
     fn trash(&mut self) {}
 
     fn focus_files_table(&mut self) {
@@ -191,8 +1111,67 @@ impl App {
         self.show_file_info = !self.show_file_info;
     }
 
+    /// Vim-like jumps, a count typed before `G` or `gg` picks the row
+    fn handle_jump_key(&mut self, key_event: KeyEvent) -> bool {
+        let control = key_event.modifiers.contains(KeyModifiers::CONTROL);
+        let count = self
+            .pending_keys
+            .trim_end_matches('g')
+            .parse::<usize>()
+            .ok();
+        let row = |count: Option<usize>, default: Jump| {
+            count.map_or(default, |n| Jump::Row(n.saturating_sub(1)))
+        };
+        let jump = match key_event.code {
+            KeyCode::Char('d') if control => Jump::HalfPageDown,
+            KeyCode::Char('u') if control => Jump::HalfPageUp,
+            KeyCode::Home => Jump::First,
+            KeyCode::End => Jump::Last,
+            KeyCode::Char('G') => row(count, Jump::Last),
+            KeyCode::Char('g') if self.pending_keys.ends_with('g') => row(count, Jump::First),
+            KeyCode::Char(c) if c == 'g' || c.is_ascii_digit() => {
+                self.pending_keys.push(c);
+                return true;
+            }
+            _ => {
+                self.pending_keys.clear();
+                return false;
+            }
+        };
+        self.pending_keys.clear();
+        self.jump(jump);
+        true
+    }
+
+    /// Move the file selection to the next or previous marked file
+    fn select_marked(&mut self, forward: bool) {
+        let marked = &self.marked_files;
+        if self
+            .file_table
+            .select_next_where(forward, |p| marked.contains(p))
+        {
+            self.focused_window = FocusedWindow::Files;
+            self.update_clone_table();
+        } else {
+            self.status = Some("no marked files listed".to_string());
+        }
+    }
+
+    fn jump(&mut self, jump: Jump) {
+        if self.disk_usage_mode() {
+            self.file_tree.jump(jump);
+        } else if matches!(self.focused_window, FocusedWindow::Clones) {
+            self.clone_table.jump(jump);
+        } else {
+            self.file_table.jump(jump);
+            self.update_clone_table();
+        }
+    }
+
     pub fn next(&mut self) {
-        if matches!(self.focused_window, FocusedWindow::Clones) {
+        if self.disk_usage_mode() {
+            self.file_tree.select_next();
+        } else if matches!(self.focused_window, FocusedWindow::Clones) {
             self.clone_table.select_next();
         } else {
             self.file_table.select_next();
@@ -201,7 +1180,9 @@ impl App {
     }
 
     pub fn previous(&mut self) {
-        if matches!(self.focused_window, FocusedWindow::Clones) {
+        if self.disk_usage_mode() {
+            self.file_tree.select_previous();
+        } else if matches!(self.focused_window, FocusedWindow::Clones) {
             self.clone_table.select_previous();
         } else {
             self.file_table.select_previous();
@@ -235,7 +1216,13 @@ impl App {
     // }
 
     fn update_file_table(&mut self) {
-        let mut paths: Vec<PathBuf> = self.file_index.duplicates.keys().cloned().collect();
+        let mut paths: Vec<PathBuf> = self
+            .file_index
+            .duplicates
+            .keys()
+            .filter(|p| self.matches_filters(p))
+            .cloned()
+            .collect();
 
         paths.sort_by(|a, b| {
             let a_size = self.file_index.file_size(a).unwrap();
@@ -243,18 +1230,155 @@ impl App {
             b_size.cmp(&a_size)
         });
 
-        self.file_table.update_table(&paths);
+        self.file_table.update_table(paths);
+        self.file_table.sort_paths(&self.file_index);
         self.file_table.select_first();
     }
 
+    /// Table the sort keys apply to
+    fn focused_table(&mut self) -> Option<&mut FileTable> {
+        match self.focused_window {
+            FocusedWindow::Files => Some(&mut self.file_table),
+            FocusedWindow::Clones => Some(&mut self.clone_table),
+            _ => None,
+        }
+    }
+
+    fn cycle_sort(&mut self) {
+        if let Some(table) = self.focused_table() {
+            table.cycle_sort();
+        }
+        self.resort();
+    }
+
+    fn toggle_sort_direction(&mut self) {
+        if let Some(table) = self.focused_table() {
+            table.toggle_sort_direction();
+        }
+        self.resort();
+    }
+
+    /// Sort the table whose header was clicked by the clicked column
+    fn click_header(&mut self, x: u16, y: u16) {
+        if let Some(column) = self.file_table.column_at(x, y) {
+            self.file_table.sort_by(column);
+        } else if let Some(column) = self.clone_table.column_at(x, y) {
+            self.clone_table.sort_by(column);
+        } else {
+            return;
+        }
+        self.resort();
+    }
+
+    /// Apply the sort of both tables, a table without one keeps its order
+    /// until it's filled again
+    fn resort(&mut self) {
+        let selected = self.file_table.selected_path();
+        self.file_table.sort_paths(&self.file_index);
+        self.clone_table.sort_paths(&self.file_index);
+        if self.file_table.selected_path() != selected {
+            self.update_clone_table();
+        }
+    }
+
+    fn matches_filters(&self, path: &Path) -> bool {
+        if self.filters.is_empty() {
+            return true;
+        }
+        self.file_index.file_entry(path).is_some_and(|f| {
+            let clones = self.clone_count(path);
+            self.filters.iter().all(|filter| filter.matches(f, clones))
+        })
+    }
+
+    fn clone_count(&self, path: &Path) -> usize {
+        self.file_index
+            .duplicates
+            .get(path)
+            .map_or(0, |copies| copies.len())
+    }
+
+    /// Mark every file with duplicates passing the filter
+    fn mark_matching(&mut self, filter: &Filter) {
+        let matching: Vec<PathBuf> = self
+            .file_index
+            .duplicates
+            .iter()
+            .filter(|(path, copies)| {
+                self.file_index
+                    .file_entry(path)
+                    .is_some_and(|f| filter.matches(f, copies.len()))
+            })
+            .map(|(path, _)| path.clone())
+            .collect();
+        let marked = matching.len();
+        self.marked_files.extend(matching);
+        self.update_marked_table();
+        self.status = Some(format!("marked {} files with {}", marked, filter));
+    }
+
+    /// Add a filter to the chain, replacing the filter of the same kind
+    fn add_filter(&mut self, filter: Filter) {
+        self.filters.retain(|f| !f.same_kind(&filter));
+        self.filters.push(filter);
+        self.apply_filters();
+    }
+
+    fn apply_filters(&mut self) {
+        self.update_file_table();
+        self.update_clone_table();
+        self.status = Some(if self.filters.is_empty() {
+            "showing all files".to_string()
+        } else {
+            format!("showing {} files", self.file_table.table_len)
+        });
+    }
+
+    /// Active filters as shown in the summary
+    fn filter_chain(&self) -> String {
+        self.filters
+            .iter()
+            .map(|f| f.to_string())
+            .collect::<Vec<_>>()
+            .join(" and ")
+    }
+
     fn update_clone_table(&mut self) {
         if let Some(selected_file) = self.file_table.selected_path().as_ref() {
             if let Some(clone_paths) = self.file_index.duplicates.get(selected_file) {
                 let paths = clone_paths.iter().cloned().collect();
-                self.clone_table.update_table(&paths);
+                let scores = self.clone_scores(selected_file, clone_paths);
+                self.clone_table.update_table(paths);
+                self.clone_table.set_scores(scores);
+                self.clone_table.sort_paths(&self.file_index);
                 self.clone_table.select_first();
+                return;
             }
         }
+        self.clone_table.update_table(Vec::new());
+        self.clone_table.select_none();
+    }
+
+    /// Confidence of each clone matching the selected file, only worth
+    /// showing when some of them are perceptual matches
+    fn clone_scores(&self, file: &Path, clones: &HashSet<PathBuf>) -> HashMap<PathBuf, u8> {
+        let Some(entry) = self.file_index.file_entry(file) else {
+            return HashMap::new();
+        };
+        let scores: HashMap<PathBuf, u8> = clones
+            .iter()
+            .filter_map(|c| {
+                let confidence = self
+                    .file_index
+                    .file_entry(c)?
+                    .confidence(entry, &self.file_index.config)?;
+                Some((c.clone(), confidence))
+            })
+            .collect();
+        if scores.values().all(|&s| s == 100) {
+            return HashMap::new();
+        }
+        scores
     }
 
     // fn next_file(&mut self) {
@@ -515,7 +1639,7 @@ impl App {
         let spans = vec![
             "Deckard".bold(),
             " v".into(),
-            format!("{}", env!("CARGO_PKG_VERSION")).into(),
+            env!("CARGO_PKG_VERSION").into(),
         ];
         let title = Line::from(spans);
         let header = Paragraph::new(title)
@@ -525,10 +1649,13 @@ impl App {
     }
 
     fn render_file_info(&self, buf: &mut Buffer, area: Rect) {
-        let info_lines = if let Some(selected_file) = self.active_selected_file() {
-            let file_entry = &self.file_index.files[&selected_file];
+        let selected_file = self.active_selected_file();
+        let file_entry = selected_file
+            .as_ref()
+            .and_then(|f| self.file_index.files.get(f));
 
-            vec![
+        let info_lines = if let Some(file_entry) = file_entry {
+            let mut lines = vec![
                 Line::from(vec!["name: ".into(), file_entry.name.to_string().yellow()]),
                 Line::from(vec![
                     "size: ".into(),
@@ -572,6 +1699,59 @@ impl App {
                         .to_string()
                         .yellow(),
                 ]),
+            ];
+            if let Some(image) = &file_entry.image_info {
+                // after the mime type
+                lines.insert(
+                    5,
+                    Line::from(vec![
+                        "image: ".into(),
+                        format!("{}x{} {}", image.width, image.height, image.format).cyan(),
+                    ]),
+                );
+            }
+            if let Some(audio) = &file_entry.audio_info {
+                let bitrate = audio
+                    .bitrate
+                    .map(|b| format!(" {} kbit/s", b))
+                    .unwrap_or_default();
+                lines.insert(
+                    5,
+                    Line::from(vec![
+                        "audio: ".into(),
+                        format!("{}{}", audio.codec, bitrate).cyan(),
+                    ]),
+                );
+            }
+            lines
+        } else if let Some(selected_dir) = selected_file {
+            let size = self.file_tree.size(&selected_dir);
+            vec![
+                Line::from(vec![
+                    "name: ".into(),
+                    selected_dir
+                        .file_name()
+                        .unwrap_or(selected_dir.as_os_str())
+                        .to_string_lossy()
+                        .to_string()
+                        .yellow(),
+                ]),
+                Line::from(vec![
+                    "size: ".into(),
+                    humansize::format_size(size, humansize::DECIMAL)
+                        .to_string()
+                        .blue(),
+                    " (".into(),
+                    size.to_string().blue(),
+                    ")".into(),
+                ]),
+                Line::from(vec![
+                    "path: ".into(),
+                    deckard::to_relative_path(&selected_dir)
+                        .to_string_lossy()
+                        .to_string()
+                        .yellow(),
+                ]),
             ]
         } else {
             vec![Line::from(vec!["none".into()])]
@@ -611,8 +1791,51 @@ impl App {
         let duplicate_lines = vec![
             Line::from(vec![
                 "Clones: ".into(),
-                self.file_index.files_len().to_string().magenta(),
+                self.file_index.duplicates_len().to_string().magenta(),
                 " Total: ".into(),
+                self.file_index.files_len().to_string().magenta(),
+                if self.scan_cancelled() {
+                    " (partial results)".yellow()
+                } else {
+                    "".into()
+                },
+                if self.file_index.stats.cache_hits > 0 {
+                    format!(" Cached: {}", self.file_index.stats.cache_hits).cyan()
+                } else {
+                    "".into()
+                },
+                if self.file_index.errors_len() > 0 {
+                    format!(" Errors: {}", self.file_index.errors_len()).red()
+                } else {
+                    "".into()
+                },
+                if !self.file_index.denied.is_empty() {
+                    format!(" Denied: {}", self.file_index.denied.len()).red()
+                } else {
+                    "".into()
+                },
+                if self.file_index.stats.changed > 0 {
+                    format!(" Changed: {}", self.file_index.stats.changed).yellow()
+                } else {
+                    "".into()
+                },
+            ]),
+            Line::from(vec![
+                "Duplicates: ".into(),
+                humansize::format_size(self.file_index.duplicates_size(), humansize::DECIMAL)
+                    .blue(),
+                " Reclaimable: ".into(),
+                humansize::format_size(self.reclaimable_size, humansize::DECIMAL).green(),
+                " Marked: ".into(),
+                humansize::format_size(self.marked_size, humansize::DECIMAL).red(),
+                " (".into(),
+                self.marked_files.len().to_string().red(),
+                " files)".into(),
+                if self.filters.is_empty() {
+                    "".into()
+                } else {
+                    format!(" Filter: {}", self.filter_chain()).cyan()
+                },
             ]),
             Line::from(vec!["Paths: ".into(), dir_joined.yellow()]),
         ];
@@ -629,20 +1852,372 @@ impl App {
     }
 
     fn render_footer(&self, buf: &mut Buffer, area: Rect) {
+        if let Some(input) = &self.command_input {
+            Paragraph::new(Line::from(vec![
+                ":".into(),
+                input.clone().into(),
+                "_".slow_blink(),
+            ]))
+            .render(area, buf);
+            return;
+        }
+        if let Some(status) = &self.status {
+            Paragraph::new(Line::from(status.clone().yellow())).render(area, buf);
+            return;
+        }
+
+        let visual = match self.focused_window {
+            FocusedWindow::Files => self.file_table.visual_paths().len(),
+            FocusedWindow::Clones => self.clone_table.visual_paths().len(),
+            _ => 0,
+        };
+        if visual > 0 {
+            let instructions = Line::from(vec![
+                " VISUAL ".bold().reversed(),
+                format!(" {} rows", visual).into(),
+                " Mark ".into(),
+                "<Space>".blue().bold(),
+                " Cancel ".into(),
+                "<Esc> ".blue().bold(),
+            ]);
+            Paragraph::new(instructions).render(area, buf);
+            return;
+        }
+
         let instructions = Line::from(vec![
-            " Decrement ".into(),
-            "<Left>".blue().bold(),
-            " Increment ".into(),
-            "<Right>".blue().bold(),
+            " Command ".into(),
+            "<:>".blue().bold(),
+            " Help ".into(),
+            "<?>".blue().bold(),
             " Quit ".into(),
             "<Q> ".blue().bold(),
         ]);
         let info_footer = Paragraph::new(instructions).style(Style::new());
         info_footer.render(area, buf)
     }
+
+    fn render_progress(&self, buf: &mut Buffer, area: Rect) {
+        if !self.is_scanning() {
+            return;
+        }
+
+        let progress = self.scan_progress.lock().unwrap().clone();
+        let phase = match progress.phase {
+            ScanPhase::Indexing => "Indexing",
+            ScanPhase::Processing => "Processing",
+            ScanPhase::Comparing => "Comparing",
+        };
+        let title = match progress.current_path.as_ref().and_then(|p| p.file_name()) {
+            Some(name) if progress.phase == ScanPhase::Processing => {
+                format!(" {} {} ", phase, name.to_string_lossy())
+            }
+            _ => format!(" {} ", phase),
+        };
+
+        let mut label = format!("{}/{}", progress.files_done, progress.files_total);
+        if progress.bytes_total > 0 {
+            label.push_str(&format!(
+                " {}/{}",
+                humansize::format_size(progress.bytes_done, humansize::DECIMAL),
+                humansize::format_size(progress.bytes_total, humansize::DECIMAL)
+            ));
+        }
+        if progress.files_cached > 0 {
+            label.push_str(&format!(
+                " ({} cached, {} read)",
+                progress.files_cached,
+                progress.files_done.saturating_sub(progress.files_cached)
+            ));
+        }
+        let mut timing = Vec::new();
+        if !progress.elapsed.is_zero() {
+            timing.push(format!("elapsed {}", format_duration(progress.elapsed)));
+        }
+        if let Some(throughput) = progress.throughput() {
+            timing.push(format!(
+                "{}/s",
+                humansize::format_size(throughput as u64, humansize::DECIMAL)
+            ));
+        }
+        if let Some(eta) = progress.eta {
+            timing.push(format!("ETA {}", format_duration(eta)));
+        }
+        let instructions = if self.scan_cancelled() {
+            Line::from(" Stopping... ")
+        } else {
+            Line::from(vec![
+                " Process selected now ".into(),
+                "<N>".blue().bold(),
+                " Stop ".into(),
+                "<Esc>".blue().bold(),
+                " Quit ".into(),
+                "<Q> ".blue().bold(),
+            ])
+        };
+
+        let area = centered_rect(area, 60, 4);
+        Clear.render(area, buf);
+        let block = Block::bordered()
+            .title(title)
+            .title_bottom(instructions.centered())
+            .border_type(BorderType::Thick)
+            .border_style(Style::new().green());
+        let [gauge_area, timing_area] =
+            Layout::vertical([Constraint::Length(1), Constraint::Length(1)])
+                .areas(block.inner(area));
+        block.render(area, buf);
+        Gauge::default()
+            .gauge_style(Style::new().green())
+            .ratio(progress.ratio())
+            .label(label)
+            .render(gauge_area, buf);
+        Line::from(timing.join("  "))
+            .centered()
+            .render(timing_area, buf);
+    }
+
+    fn render_confirm_delete(&self, buf: &mut Buffer, area: Rect) {
+        let Some(path) = &self.confirm_delete else {
+            return;
+        };
+
+        let size = self.file_tree.size(path);
+        let lines = vec![
+            Line::from(vec![
+                "Delete ".into(),
+                deckard::to_relative_path(path)
+                    .to_string_lossy()
+                    .to_string()
+                    .yellow(),
+                " (".into(),
+                humansize::format_size(size, humansize::DECIMAL).blue(),
+                ")?".into(),
+            ]),
+            Line::from(vec![
+                "Confirm ".into(),
+                "<Y>".blue().bold(),
+                " Cancel ".into(),
+                "<any key>".blue().bold(),
+            ]),
+        ];
+
+        let title = if self.file_index.config.action_config.dry_run {
+            " Dry run "
+        } else {
+            " Delete "
+        };
+        let area = centered_rect(area, 60, 4);
+        Clear.render(area, buf);
+        Paragraph::new(Text::from(lines))
+            .centered()
+            .block(
+                Block::bordered()
+                    .title(title)
+                    .border_type(BorderType::Thick)
+                    .border_style(Style::new().red()),
+            )
+            .render(area, buf);
+    }
 }
 
 impl App {
+    fn render_confirm_remove(&self, buf: &mut Buffer, area: Rect) {
+        let Some(preview) = &self.confirm_remove else {
+            return;
+        };
+
+        let mut lines = vec![Line::from(vec![
+            "Remove ".into(),
+            preview.files.len().to_string().red(),
+            " files (".into(),
+            humansize::format_size(preview.bytes, humansize::DECIMAL).blue(),
+            ") and ".into(),
+            preview.dirs.len().to_string().red(),
+            " emptied directories?".into(),
+        ])];
+        for dir in preview.dirs.iter().take(PREVIEW_DIRS) {
+            lines.push(Line::from(
+                deckard::to_relative_path(dir)
+                    .to_string_lossy()
+                    .to_string()
+                    .yellow(),
+            ));
+        }
+        if preview.dirs.len() > PREVIEW_DIRS {
+            lines.push(Line::from(format!(
+                "and {} more",
+                preview.dirs.len() - PREVIEW_DIRS
+            )));
+        }
+        lines.push(Line::from(vec![
+            "Confirm ".into(),
+            "<Y>".blue().bold(),
+            " Cancel ".into(),
+            "<any key>".blue().bold(),
+        ]));
+
+        let title = if self.file_index.config.action_config.dry_run {
+            " Dry run "
+        } else {
+            " Remove "
+        };
+        let area = centered_rect(area, 60, lines.len() as u16 + 2);
+        Clear.render(area, buf);
+        Paragraph::new(Text::from(lines))
+            .centered()
+            .block(
+                Block::bordered()
+                    .title(title)
+                    .border_type(BorderType::Thick)
+                    .border_style(Style::new().red()),
+            )
+            .render(area, buf);
+    }
+
+    fn render_settings(&self, buf: &mut Buffer, area: Rect) {
+        let Some((draft, selected)) = &self.settings else {
+            return;
+        };
+
+        let rows = Setting::ALL.iter().enumerate().map(|(i, setting)| {
+            let row = Row::new(vec![
+                Cell::from(setting.name()),
+                Cell::from(setting.get(draft).label().yellow()),
+            ]);
+            if i == *selected {
+                row.style(Style::new().add_modifier(Modifier::REVERSED))
+            } else {
+                row
+            }
+        });
+        let instructions = Line::from(vec![
+            " Change ".into(),
+            "<Space|0-9>".blue().bold(),
+            " Save ".into(),
+            "<S>".blue().bold(),
+            " Save and rescan ".into(),
+            "<R>".blue().bold(),
+            " Cancel ".into(),
+            "<Esc> ".blue().bold(),
+        ]);
+
+        // a row per setting, the help line and the borders
+        let area = centered_rect(area, 60, Setting::ALL.len() as u16 + 4);
+        Clear.render(area, buf);
+        let block = Block::bordered()
+            .title(" Settings ")
+            .title_bottom(instructions.centered())
+            .border_type(BorderType::Thick)
+            .border_style(Style::new().green());
+        let [table_area, help_area] =
+            Layout::vertical([Constraint::Fill(1), Constraint::Length(1)]).areas(block.inner(area));
+        block.render(area, buf);
+        Widget::render(
+            Table::new(rows, [Constraint::Fill(1), Constraint::Length(8)]),
+            table_area,
+            buf,
+        );
+        Line::from(Setting::ALL[*selected].help().dark_gray())
+            .centered()
+            .render(help_area, buf);
+    }
+
+    fn render_denied(&self, buf: &mut Buffer, area: Rect) {
+        if !self.show_denied {
+            return;
+        }
+
+        let denied = self.file_index.denied_paths();
+        // leave room for the borders and the close hint
+        let rows = (area.height as usize).saturating_sub(6).max(1);
+        let mut lines: Vec<Line> = denied
+            .iter()
+            .take(rows)
+            .map(|p| {
+                Line::from(
+                    deckard::to_relative_path(p)
+                        .to_string_lossy()
+                        .to_string()
+                        .yellow(),
+                )
+            })
+            .collect();
+        if denied.len() > rows {
+            lines.push(Line::from(format!("and {} more", denied.len() - rows)));
+        }
+        lines.push(Line::from(vec!["Close ".into(), "<any key>".blue().bold()]));
+
+        let area = centered_rect(area, 80, lines.len() as u16 + 2);
+        Clear.render(area, buf);
+        Paragraph::new(Text::from(lines))
+            .block(
+                Block::bordered()
+                    .title(format!(" Permission denied ({}) ", denied.len()))
+                    .border_type(BorderType::Thick)
+                    .border_style(Style::new().red()),
+            )
+            .render(area, buf);
+    }
+
+    fn render_dir_stats(&self, buf: &mut Buffer, area: Rect) {
+        let Some(dir) = &self.dir_stats else {
+            return;
+        };
+
+        let files = self
+            .file_index
+            .files
+            .keys()
+            .filter(|f| f.parent() == Some(dir.as_path()))
+            .count();
+        let duplicates = self.dir_duplicates(dir);
+        let size: u64 = duplicates
+            .iter()
+            .filter_map(|f| self.file_index.file_size(f))
+            .sum();
+        let marked = duplicates
+            .iter()
+            .filter(|f| self.marked_files.contains(*f))
+            .count();
+
+        let lines = vec![
+            Line::from(
+                deckard::to_relative_path(dir)
+                    .to_string_lossy()
+                    .to_string()
+                    .yellow(),
+            ),
+            Line::from(vec![
+                "Files: ".into(),
+                files.to_string().magenta(),
+                " Duplicates: ".into(),
+                duplicates.len().to_string().magenta(),
+                " (".into(),
+                humansize::format_size(size, humansize::DECIMAL).blue(),
+                ") Marked: ".into(),
+                marked.to_string().red(),
+            ]),
+            Line::from(vec![
+                "Mark all duplicates ".into(),
+                "<M>".blue().bold(),
+                " Close ".into(),
+                "<any key>".blue().bold(),
+            ]),
+        ];
+
+        let area = centered_rect(area, 60, 5);
+        Clear.render(area, buf);
+        Paragraph::new(Text::from(lines))
+            .centered()
+            .block(
+                Block::bordered()
+                    .title(" Directory ")
+                    .border_type(BorderType::Thick)
+                    .border_style(Style::new().green()),
+            )
+            .render(area, buf);
+    }
+
     fn render_ui(&mut self, area: Rect, buf: &mut Buffer) {
         let rects = Layout::vertical([
             Constraint::Length(1),
@@ -689,14 +2264,21 @@ impl App {
 
         // let files_text = Text::from(files);
 
+        let layout = &self.file_index.config.tui;
         let main_sub_area_constrains = if self.show_clones_table || self.show_file_info {
-            [Constraint::Percentage(50), Constraint::Percentage(50)]
+            [
+                Constraint::Percentage(layout.files_width),
+                Constraint::Percentage(100u16.saturating_sub(layout.files_width)),
+            ]
         } else {
             [Constraint::Percentage(100), Constraint::Percentage(0)]
         };
 
         let main_sub_area_inner_constrains = if self.show_file_info {
-            [Constraint::Percentage(60), Constraint::Percentage(40)]
+            [
+                Constraint::Percentage(layout.tables_height),
+                Constraint::Percentage(100u16.saturating_sub(layout.tables_height)),
+            ]
         } else {
             [Constraint::Percentage(100), Constraint::Percentage(0)]
         };
@@ -716,12 +2298,16 @@ impl App {
             .constraints(main_sub_area_inner_constrains)
             .split(main_sub_area[1]);
 
-        self.file_table.render(
-            buf,
-            main_sub_area_left[0],
-            matches!(self.focused_window, FocusedWindow::Files),
-            &self.file_index,
-        );
+        if self.disk_usage_mode() {
+            self.file_tree.render(buf, main_sub_area_left[0], true);
+        } else {
+            self.file_table.render(
+                buf,
+                main_sub_area_left[0],
+                matches!(self.focused_window, FocusedWindow::Files),
+                &self.file_index,
+            );
+        }
 
         if self.show_marked_table {
             self.marked_table
@@ -744,6 +2330,15 @@ impl App {
 
         self.render_summary(buf, rects[2]);
         self.render_footer(buf, rects[3]);
+        self.render_confirm_delete(buf, area);
+        self.render_confirm_remove(buf, area);
+        self.render_dir_stats(buf, area);
+        self.render_denied(buf, area);
+        self.render_settings(buf, area);
+        if let Some(help) = &self.help {
+            help.render(buf, area);
+        }
+        self.render_progress(buf, area);
 
         // Paragraph::new(files_text)
         //     .block(Block::new().borders(Borders::all()))
@@ -755,15 +2350,70 @@ impl App {
     }
 }
 
+/// Short human readable duration like 1h02m or 3m20s
+fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    if secs >= 3600 {
+        format!("{}h{:02}m", secs / 3600, secs % 3600 / 60)
+    } else if secs >= 60 {
+        format!("{}m{:02}s", secs / 60, secs % 60)
+    } else {
+        format!("{}s", secs)
+    }
+}
+
+/// Area of the given size in the middle of `area`
+pub fn centered_rect(area: Rect, width_percent: u16, height: u16) -> Rect {
+    let [_, area, _] = Layout::vertical([
+        Constraint::Fill(1),
+        Constraint::Length(height),
+        Constraint::Fill(1),
+    ])
+    .areas(area);
+    let [_, area, _] = Layout::horizontal([
+        Constraint::Fill(1),
+        Constraint::Percentage(width_percent),
+        Constraint::Fill(1),
+    ])
+    .areas(area);
+    area
+}
+
 /// Make the path relative to the commont search parth
 pub fn format_path(path: &PathBuf, target_paths: &HashSet<PathBuf>) -> String {
     let common_path = deckard::find_common_path(target_paths);
 
     let relative_path = if let Some(common_path) = &common_path {
-        let path = path.strip_prefix(&common_path).unwrap_or(path);
+        let path = path.strip_prefix(common_path).unwrap_or(path);
         path
     } else {
         path
     };
     relative_path.to_string_lossy().to_string()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dry_run_delete_keeps_directory() {
+        let dir = env::temp_dir().join(format!("deckard_tui_delete_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("a"), "kept").unwrap();
+
+        let mut config = SearchConfig {
+            disk_usage_mode: true,
+            ..Default::default()
+        };
+        config.action_config.dry_run = true;
+        let mut app = App::new(HashSet::from([dir.clone()]), config);
+        app.confirm_delete = Some(dir.clone());
+        app.delete_confirmed();
+
+        let kept = dir.join("a").exists();
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(kept);
+        assert!(app.confirm_delete.is_none());
+    }
+}
@@ -1,6 +1,11 @@
-use clap::{command, value_parser, Arg, Command};
-use deckard::config::SearchConfig;
+use clap::{command, value_parser, Arg, ArgMatches, Command};
+use deckard::config::{
+    ChromaPreset, CompareScope, ComparisonStrategy, HashAlgorithm, Isolate, MediaClass, NameMatch,
+    ReadOrder, SearchConfig, Transitivity,
+};
+use deckard::policy::KeepPolicy;
 use log::debug;
+use std::path::PathBuf;
 
 pub fn cli() -> Command {
     command!()
@@ -14,6 +19,12 @@ pub fn cli() -> Command {
                 .help("List of paths to traverse")
                 .num_args(1..),
         )
+        .arg(
+            Arg::new("save_config")
+                .long("save_config")
+                .action(clap::ArgAction::SetTrue)
+                .help("Save the config with the given flags applied as the new default"),
+        )
         .arg(
             Arg::new("open_config")
                 .short('O')
@@ -28,6 +39,12 @@ pub fn cli() -> Command {
                 .action(clap::ArgAction::SetTrue)
                 .help("Do not check hidden files"),
         )
+        .arg(
+            Arg::new("one_file_system")
+                .long("one_file_system")
+                .action(clap::ArgAction::SetTrue)
+                .help("Stay on the file system of each path, skipping mount points"),
+        )
         .arg(
             Arg::new("skip_empty")
                 .short('e')
@@ -35,6 +52,13 @@ pub fn cli() -> Command {
                 .action(clap::ArgAction::SetTrue)
                 .help("Do not check empty files"),
         )
+        .arg(
+            Arg::new("disk_usage")
+                .short('u')
+                .long("disk_usage")
+                .action(clap::ArgAction::SetTrue)
+                .help("Browse disk usage instead of searching for duplicates"),
+        )
         .arg(
             Arg::new("check_image")
                 .short('i')
@@ -49,25 +73,194 @@ pub fn cli() -> Command {
                 .action(clap::ArgAction::SetTrue)
                 .help("Compare audio files similarities"),
         )
+        .arg(
+            Arg::new("audio_duration")
+                .long("audio_duration")
+                .value_parser(value_parser!(u64))
+                .help("Seconds of each track to fingerprint, 0 for whole tracks")
+                .num_args(1),
+        )
+        .arg(
+            Arg::new("audio_preset")
+                .long("audio_preset")
+                .value_parser(["test1", "test2", "test3", "test4", "test5"])
+                .help("Chromaprint preset used to fingerprint audio")
+                .num_args(1),
+        )
+        .arg(
+            Arg::new("audio_downsample")
+                .long("audio_downsample")
+                .value_parser(value_parser!(u32))
+                .help("Average this many audio samples into one, faster but less accurate")
+                .num_args(1),
+        )
+        .arg(
+            Arg::new("only")
+                .long("only")
+                .value_parser(["images", "audio", "video", "documents"])
+                .help("Only scan files of this kind, detected by extension and content")
+                .num_args(1),
+        )
         .arg(
             Arg::new("full_hash")
                 .long("full_hash")
                 .action(clap::ArgAction::SetTrue)
                 .help("Compare every byte of the file"),
         )
+        .arg(
+            Arg::new("paranoid")
+                .long("paranoid")
+                .value_name("ALGORITHM")
+                .value_parser(["md5", "sha1", "sha256", "sha512"])
+                .help("Also fully hash every file with a second algorithm, files only match when both hashes do")
+                .num_args(1),
+        )
+        .arg(
+            Arg::new("parallel_hash_size")
+                .long("parallel_hash_size")
+                .value_parser(value_parser!(u64))
+                .help("Fully hash files of at least this many bytes on all threads, 0 to disable")
+                .num_args(1),
+        )
+        .arg(
+            Arg::new("nice_io")
+                .long("nice_io")
+                .action(clap::ArgAction::SetTrue)
+                .help("Scan at low priority with a capped read bandwidth"),
+        )
         .arg(
             Arg::new("include_filter")
                 .short('f')
                 .long("include_filter")
                 .value_parser(value_parser!(String))
-                .help("Include files that contain filter in their file name"),
+                .help("Include files whose name contains the filter or that match a glob"),
         )
         .arg(
             Arg::new("exclude_filter")
                 .short('x')
                 .long("exclude_filter")
                 .value_parser(value_parser!(String))
-                .help("Exclude files that contain filter in their file name"),
+                .help("Exclude files whose name contains the filter or that match a glob"),
+        )
+        .arg(
+            Arg::new("adaptive_threads")
+                .long("adaptive_threads")
+                .action(clap::ArgAction::SetTrue)
+                .help("Adjust the number of parallel readers to the storage throughput"),
+        )
+        .arg(
+            Arg::new("cache")
+                .long("cache")
+                .action(clap::ArgAction::SetTrue)
+                .help("Reuse the hashes of unchanged files from previous scans"),
+        )
+        .arg(
+            Arg::new("xattr_cache")
+                .long("xattr_cache")
+                .action(clap::ArgAction::SetTrue)
+                .help("Store the hashes in an extended attribute of each file and reuse them while it is unchanged"),
+        )
+        .arg(
+            Arg::new("notify")
+                .long("notify")
+                .action(clap::ArgAction::SetTrue)
+                .help("Send a desktop notification when the scan finishes"),
+        )
+        .arg(
+            Arg::new("progress_interval")
+                .long("progress_interval")
+                .value_parser(value_parser!(u64))
+                .help("Shortest time in milliseconds between two progress updates")
+                .num_args(1),
+        )
+        .arg(
+            Arg::new("scope")
+                .long("scope")
+                .value_parser(["all", "directory", "subdirectory"])
+                .help("Only compare files within the same directory or top-level subdirectory")
+                .num_args(1),
+        )
+        .arg(
+            Arg::new("isolate")
+                .long("isolate")
+                .value_parser(["off", "directory", "root"])
+                .help("Only report duplicates spanning different directories or scan roots")
+                .num_args(1),
+        )
+        .arg(
+            Arg::new("names")
+                .long("names")
+                .value_parser(["any", "same", "different"])
+                .help("Only match files with the same name, or only with different names")
+                .num_args(1),
+        )
+        .arg(
+            Arg::new("grouping")
+                .long("grouping")
+                .value_parser(["transitive", "strict"])
+                .help("Group files through chains of matches, or only files that all match each other")
+                .num_args(1),
+        )
+        .arg(
+            Arg::new("chain_confidence")
+                .long("chain_confidence")
+                .value_parser(value_parser!(u8).range(0..=100))
+                .help("Perceptual matches below this confidence must match their whole group directly, keeping bursts of similar photos apart")
+                .num_args(1),
+        )
+        .arg(
+            Arg::new("read_only")
+                .long("read_only")
+                .action(clap::ArgAction::SetTrue)
+                .help("Never modify the scanned files, refuse every removal and deduplication"),
+        )
+        .arg(
+            Arg::new("dry_run")
+                .long("dry_run")
+                .action(clap::ArgAction::SetTrue)
+                .help("Preview the files and directories that would be removed"),
+        )
+        .arg(
+            Arg::new("keep")
+                .long("keep")
+                .value_name("POLICY")
+                .value_parser(clap::value_parser!(KeepPolicy))
+                .help("Copy to keep: newest, oldest, shortest_path, longest_path, largest_resolution, best_audio, dir:<path> or priority:<path>,<path>")
+                .num_args(1),
+        )
+        .arg(
+            Arg::new("prefer")
+                .long("prefer")
+                .value_name("PATH")
+                .action(clap::ArgAction::Append)
+                .help("Keep the copies under this path first, can be given several times")
+                .num_args(1),
+        )
+        .arg(
+            Arg::new("verify_before_action")
+                .long("verify_before_action")
+                .action(clap::ArgAction::SetTrue)
+                .help("Compare the files byte for byte with the kept copy before removing them, groups that differ are left alone"),
+        )
+        .arg(
+            Arg::new("no_remove_dirs")
+                .long("no_remove_dirs")
+                .action(clap::ArgAction::SetTrue)
+                .help("Keep directories left empty after removing files"),
+        )
+        .arg(
+            Arg::new("read_order")
+                .long("read_order")
+                .value_parser(["auto", "parallel", "sequential"])
+                .help("Order in which files are read, sequential is faster on spinning disks")
+                .num_args(1),
+        )
+        .arg(
+            Arg::new("comparison")
+                .long("comparison")
+                .value_parser(["pairwise", "buckets", "hybrid"])
+                .help("Compare every pair of files, only files with the same hash, or both by kind of file")
+                .num_args(1),
         )
         .arg(
             Arg::new("threads")
@@ -81,10 +274,15 @@ pub fn cli() -> Command {
 
 pub fn get_config() -> SearchConfig {
     let args = cli().get_matches();
-    let mut config = deckard::config::SearchConfig::load("deckard-cli");
+    let config = deckard::config::SearchConfig::load("deckard-cli");
 
     debug!("loaded {:#?}", config);
 
+    augment_config(config, &args)
+}
+
+/// Override the loaded config with the flags given on the command line
+pub fn augment_config(mut config: SearchConfig, args: &ArgMatches) -> SearchConfig {
     let include_filter = args
         .get_one::<String>("include_filter")
         .map(|v| v.to_owned());
@@ -104,11 +302,21 @@ pub fn get_config() -> SearchConfig {
         config.skip_hidden = skip_hidden
     }
 
+    let one_file_system = args.get_flag("one_file_system");
+    if one_file_system {
+        config.same_file_system = one_file_system
+    }
+
     let skip_empty = args.get_flag("skip_empty");
     if skip_empty {
         config.skip_empty = skip_empty
     }
 
+    let disk_usage = args.get_flag("disk_usage");
+    if disk_usage {
+        config.disk_usage_mode = disk_usage
+    }
+
     let check_image = args.get_flag("check_image");
     if check_image {
         config.image_config.compare = check_image
@@ -119,11 +327,159 @@ pub fn get_config() -> SearchConfig {
         config.audio_config.compare = check_audio
     }
 
+    if let Some(d) = args.get_one::<u64>("audio_duration") {
+        config.audio_config.max_duration = *d;
+    }
+
+    if let Some(preset) = args.get_one::<String>("audio_preset") {
+        config.audio_config.preset = match preset.as_str() {
+            "test2" => ChromaPreset::Test2,
+            "test3" => ChromaPreset::Test3,
+            "test4" => ChromaPreset::Test4,
+            "test5" => ChromaPreset::Test5,
+            _ => ChromaPreset::Test1,
+        };
+    }
+
+    if let Some(d) = args.get_one::<u32>("audio_downsample") {
+        config.audio_config.downsample = *d;
+    }
+
+    if let Some(only) = args.get_one::<String>("only") {
+        config.media = match only.as_str() {
+            "images" => Some(MediaClass::Images),
+            "audio" => Some(MediaClass::Audio),
+            "video" => Some(MediaClass::Video),
+            "documents" => Some(MediaClass::Documents),
+            _ => None,
+        };
+    }
+
     let full_hash = args.get_flag("full_hash");
     if full_hash {
         config.hasher_config.full_hash = full_hash
     }
 
+    if let Some(algorithm) = args.get_one::<String>("paranoid") {
+        config.hasher_config.full_hash = true;
+        config.hasher_config.paranoid_algorithm = match algorithm.as_str() {
+            "md5" => Some(HashAlgorithm::MD5),
+            "sha1" => Some(HashAlgorithm::SHA1),
+            "sha256" => Some(HashAlgorithm::SHA256),
+            _ => Some(HashAlgorithm::SHA512),
+        };
+    }
+
+    if let Some(s) = args.get_one::<u64>("parallel_hash_size") {
+        config.hasher_config.parallel_size = *s;
+    }
+
+    let nice_io = args.get_flag("nice_io");
+    if nice_io {
+        config.nice_io = nice_io
+    }
+
+    let adaptive_threads = args.get_flag("adaptive_threads");
+    if adaptive_threads {
+        config.adaptive_threads = adaptive_threads
+    }
+
+    let cache = args.get_flag("cache");
+    if cache {
+        config.cache = cache
+    }
+
+    let xattr_cache = args.get_flag("xattr_cache");
+    if xattr_cache {
+        config.xattr_cache = xattr_cache
+    }
+    let notify = args.get_flag("notify");
+    if notify {
+        config.notify = notify
+    }
+    if let Some(interval) = args.get_one::<u64>("progress_interval") {
+        config.progress_interval = *interval;
+    }
+
+    if let Some(scope) = args.get_one::<String>("scope") {
+        config.compare_scope = match scope.as_str() {
+            "directory" => CompareScope::Directory,
+            "subdirectory" => CompareScope::Subdirectory,
+            _ => CompareScope::All,
+        };
+    }
+
+    if let Some(isolate) = args.get_one::<String>("isolate") {
+        config.isolate = match isolate.as_str() {
+            "directory" => Isolate::Directory,
+            "root" => Isolate::Root,
+            _ => Isolate::Off,
+        };
+    }
+
+    if let Some(names) = args.get_one::<String>("names") {
+        config.name_match = match names.as_str() {
+            "same" => NameMatch::Same,
+            "different" => NameMatch::Different,
+            _ => NameMatch::Any,
+        };
+    }
+
+    if let Some(grouping) = args.get_one::<String>("grouping") {
+        config.transitivity = match grouping.as_str() {
+            "strict" => Transitivity::Strict,
+            _ => Transitivity::Transitive,
+        };
+    }
+
+    if let Some(c) = args.get_one::<u8>("chain_confidence") {
+        config.chain_confidence = *c;
+    }
+
+    let read_only = args.get_flag("read_only");
+    if read_only {
+        config.action_config.read_only = read_only
+    }
+
+    let dry_run = args.get_flag("dry_run");
+    if dry_run {
+        config.action_config.dry_run = dry_run
+    }
+
+    let verify_before_action = args.get_flag("verify_before_action");
+    if verify_before_action {
+        config.action_config.verify_before_action = verify_before_action
+    }
+
+    let no_remove_dirs = args.get_flag("no_remove_dirs");
+    if no_remove_dirs {
+        config.action_config.no_remove_dirs = no_remove_dirs
+    }
+
+    if let Some(paths) = args.get_many::<String>("prefer") {
+        config.action_config.prefer_paths = paths.map(PathBuf::from).collect();
+    }
+
+    if let Some(policy) = args.get_one::<KeepPolicy>("keep") {
+        config.action_config.keep_policy = policy.clone();
+    }
+
+    if let Some(order) = args.get_one::<String>("read_order") {
+        config.read_order = match order.as_str() {
+            "parallel" => ReadOrder::Parallel,
+            "sequential" => ReadOrder::Sequential,
+            _ => ReadOrder::Auto,
+        };
+    }
+
+    if let Some(strategy) = args.get_one::<String>("comparison") {
+        config.comparison_strategy = match strategy.as_str() {
+            "pairwise" => ComparisonStrategy::Pairwise,
+            "buckets" => ComparisonStrategy::Buckets,
+            _ => ComparisonStrategy::Hybrid,
+        };
+    }
+
     if let Some(t) = args.get_one::<usize>("threads") {
         config.threads = *t;
     }
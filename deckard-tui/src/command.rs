@@ -0,0 +1,180 @@
+/// Commands typed in the command line after pressing `:`
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    MarkRoot(String),
+    /// Mark the duplicates of every group with the given keep policy
+    MarkKeep(String),
+    /// Only list files whose MIME type starts with the prefix, empty lists all
+    FilterType(String),
+    /// Only list files whose path matches the regex
+    FilterRegex(String),
+    /// Only list files whose size compares as given, like `>10M`
+    FilterSize(String),
+    FilterExtension(String),
+    /// Only list files whose number of duplicates compares as given, like `>=3`
+    FilterClones(String),
+    /// Mark the files whose number of duplicates compares as given
+    MarkClones(String),
+    /// Mark the files matching a glob, or whose name contains the text
+    MarkFilter(String),
+    /// Drop every filter
+    FilterClear,
+    DirStats,
+    Denied,
+    /// Save the marked files to a selection file for `deckard --clean_from`
+    SaveSelection(String),
+    /// View and edit the config
+    Settings,
+    /// Open the selected file with the application
+    OpenWith(String),
+    /// Rename the selected file within its directory
+    Rename(String),
+    /// Show or hide a column of the tables
+    Column(String),
+    /// Show these columns in this order, separated by commas or spaces
+    Columns(String),
+}
+
+#[derive(Debug)]
+pub struct CommandSpec {
+    pub name: &'static str,
+    pub usage: &'static str,
+    pub description: &'static str,
+}
+
+pub const COMMANDS: &[CommandSpec] = &[
+    CommandSpec {
+        name: "mark_root",
+        usage: "mark_root <path|label>",
+        description: "Mark the shown files under a scan root that have copies elsewhere",
+    },
+    CommandSpec {
+        name: "mark_keep",
+        usage: "mark_keep <policy>",
+        description: "Mark the duplicates of every group, keeping the copy the policy picks",
+    },
+    CommandSpec {
+        name: "filter_type",
+        usage: "filter_type [image|audio|video|text|...]",
+        description: "Only list files of a MIME type, without a type drops the type filter",
+    },
+    CommandSpec {
+        name: "filter_re",
+        usage: "filter_re <regex>",
+        description: "Only list files whose path matches the regex",
+    },
+    CommandSpec {
+        name: "filter_size",
+        usage: "filter_size <op><size>",
+        description: "Only list files of a size, like >10M or <=1k",
+    },
+    CommandSpec {
+        name: "filter_ext",
+        usage: "filter_ext <ext>",
+        description: "Only list files with the extension",
+    },
+    CommandSpec {
+        name: "filter_clones",
+        usage: "filter_clones <op><count>",
+        description: "Only list files with a number of duplicates, like >=3 or =1",
+    },
+    CommandSpec {
+        name: "mark_clones",
+        usage: "mark_clones <op><count>",
+        description: "Mark the files with a number of duplicates, like >=3",
+    },
+    CommandSpec {
+        name: "mark_filter",
+        usage: "mark_filter <pattern>",
+        description: "Mark the files matching a glob like *.tmp or **/Downloads/**",
+    },
+    CommandSpec {
+        name: "filter_clear",
+        usage: "filter_clear",
+        description: "Drop every filter",
+    },
+    CommandSpec {
+        name: "dir_stats",
+        usage: "dir_stats",
+        description: "Show how much of the selected file's directory is duplicated",
+    },
+    CommandSpec {
+        name: "denied",
+        usage: "denied",
+        description: "List the paths skipped for lacking permission",
+    },
+    CommandSpec {
+        name: "save_selection",
+        usage: "save_selection <file>",
+        description: "Save the marked files for deckard --clean_from",
+    },
+    CommandSpec {
+        name: "settings",
+        usage: "settings",
+        description: "View and edit the config, then save it or rescan with it",
+    },
+    CommandSpec {
+        name: "open_with",
+        usage: "open_with <app>",
+        description: "Open the selected file with the application",
+    },
+    CommandSpec {
+        name: "rename",
+        usage: "rename <name>",
+        description: "Rename the selected file within its directory",
+    },
+    CommandSpec {
+        name: "column",
+        usage: "column <date|size|clones|mime|extension|parent>",
+        description: "Show or hide a column of the tables",
+    },
+    CommandSpec {
+        name: "columns",
+        usage: "columns <column,...>",
+        description: "Show these columns in this order, like size,date,mime",
+    },
+];
+
+impl Command {
+    pub fn parse(input: &str) -> Result<Self, String> {
+        let input = input.trim();
+        let (name, arg) = match input.split_once(char::is_whitespace) {
+            Some((name, arg)) => (name, arg.trim()),
+            None => (input, ""),
+        };
+
+        match name {
+            "mark_root" => Ok(Command::MarkRoot(required(name, arg)?)),
+            "mark_keep" => Ok(Command::MarkKeep(required(name, arg)?)),
+            "filter_type" => Ok(Command::FilterType(arg.to_string())),
+            "filter_re" => Ok(Command::FilterRegex(required(name, arg)?)),
+            "filter_size" => Ok(Command::FilterSize(required(name, arg)?)),
+            "filter_ext" => Ok(Command::FilterExtension(required(name, arg)?)),
+            "filter_clones" => Ok(Command::FilterClones(required(name, arg)?)),
+            "mark_clones" => Ok(Command::MarkClones(required(name, arg)?)),
+            "mark_filter" => Ok(Command::MarkFilter(required(name, arg)?)),
+            "filter_clear" => Ok(Command::FilterClear),
+            "dir_stats" => Ok(Command::DirStats),
+            "denied" => Ok(Command::Denied),
+            "save_selection" => Ok(Command::SaveSelection(required(name, arg)?)),
+            "settings" => Ok(Command::Settings),
+            "open_with" => Ok(Command::OpenWith(required(name, arg)?)),
+            "rename" => Ok(Command::Rename(required(name, arg)?)),
+            "column" => Ok(Command::Column(required(name, arg)?)),
+            "columns" => Ok(Command::Columns(required(name, arg)?)),
+            _ => Err(format!("unknown command: {}", name)),
+        }
+    }
+}
+
+fn required(name: &str, arg: &str) -> Result<String, String> {
+    if arg.is_empty() {
+        let usage = COMMANDS
+            .iter()
+            .find(|c| c.name == name)
+            .map(|c| c.usage)
+            .unwrap_or(name);
+        return Err(format!("usage: {}", usage));
+    }
+    Ok(arg.to_string())
+}
@@ -0,0 +1,149 @@
+use std::cmp::Ordering;
+use std::fmt;
+
+use deckard::file::FileEntry;
+use deckard::pattern::Pattern;
+use regex::Regex;
+
+/// Condition the listed files have to meet, filters are combined with AND
+#[derive(Debug, Clone)]
+pub enum Filter {
+    /// MIME type starts with the prefix
+    Type(String),
+    /// Path matches the regular expression
+    Regex(Regex),
+    Size(Comparison),
+    /// Extension, ignoring case
+    Extension(String),
+    /// Number of duplicates of the file
+    Clones(Comparison),
+    /// File name containing the text or path matching the glob
+    Pattern(Pattern),
+}
+
+impl Filter {
+    pub fn regex(pattern: &str) -> Result<Self, String> {
+        Regex::new(pattern)
+            .map(Filter::Regex)
+            .map_err(|e| format!("invalid regex: {}", e))
+    }
+
+    /// Parse a comparison like `>10M`, `<=1k` or `=0`, sizes are decimal
+    pub fn size(arg: &str) -> Result<Self, String> {
+        Comparison::parse(arg, parse_size)
+            .map(Filter::Size)
+            .ok_or_else(|| "usage: filter_size <op><size>, like >10M or <=1k".to_string())
+    }
+
+    /// Parse a comparison of the number of duplicates like `>=3` or `=1`
+    pub fn clones(arg: &str) -> Result<Self, String> {
+        Comparison::parse(arg, |n| n.parse().ok())
+            .map(Filter::Clones)
+            .ok_or_else(|| "expected a count comparison, like >=3 or =1".to_string())
+    }
+
+    /// Parse a glob like `*.tmp` or `**/Downloads/**`, or text found in file names
+    pub fn pattern(pattern: &str) -> Result<Self, String> {
+        Pattern::new(pattern)
+            .map(Filter::Pattern)
+            .map_err(|e| e.to_string())
+    }
+
+    pub fn extension(ext: &str) -> Self {
+        Filter::Extension(ext.trim_start_matches('.').to_lowercase())
+    }
+
+    pub fn matches(&self, file: &FileEntry, clones: usize) -> bool {
+        match self {
+            Filter::Type(prefix) => file
+                .mime_type
+                .as_ref()
+                .is_some_and(|m| m.starts_with(prefix.as_str())),
+            Filter::Regex(regex) => regex.is_match(&file.path.to_string_lossy()),
+            Filter::Size(size) => size.holds(file.size),
+            Filter::Extension(ext) => file
+                .path
+                .extension()
+                .is_some_and(|e| e.to_string_lossy().to_lowercase() == *ext),
+            Filter::Clones(count) => count.holds(clones as u64),
+            Filter::Pattern(pattern) => pattern.matches(&file.path),
+        }
+    }
+
+    /// Filters of the same kind replace each other in the chain
+    pub fn same_kind(&self, other: &Self) -> bool {
+        std::mem::discriminant(self) == std::mem::discriminant(other)
+    }
+}
+
+impl fmt::Display for Filter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Filter::Type(prefix) => write!(f, "type {}", prefix),
+            Filter::Regex(regex) => write!(f, "re /{}/", regex),
+            Filter::Size(size) => write!(
+                f,
+                "size {}{}",
+                size.operator(),
+                humansize::format_size(size.value, humansize::DECIMAL)
+            ),
+            Filter::Extension(ext) => write!(f, "ext {}", ext),
+            Filter::Clones(count) => write!(f, "clones {}{}", count.operator(), count.value),
+            Filter::Pattern(pattern) => write!(f, "pattern {}", pattern),
+        }
+    }
+}
+
+/// A number compared with `>`, `<`, `>=`, `<=` or `=`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Comparison {
+    /// Orderings of a number against the value that pass
+    orderings: Vec<Ordering>,
+    pub value: u64,
+}
+
+impl Comparison {
+    pub fn parse(arg: &str, value: impl Fn(&str) -> Option<u64>) -> Option<Self> {
+        let operators = [
+            (">=", vec![Ordering::Greater, Ordering::Equal]),
+            ("<=", vec![Ordering::Less, Ordering::Equal]),
+            (">", vec![Ordering::Greater]),
+            ("<", vec![Ordering::Less]),
+            ("=", vec![Ordering::Equal]),
+        ];
+        let arg = arg.trim();
+        operators.into_iter().find_map(|(operator, orderings)| {
+            let value = value(arg.strip_prefix(operator)?.trim())?;
+            Some(Self { orderings, value })
+        })
+    }
+
+    pub fn holds(&self, number: u64) -> bool {
+        self.orderings.contains(&number.cmp(&self.value))
+    }
+
+    fn operator(&self) -> &'static str {
+        match self.orderings.as_slice() {
+            [Ordering::Greater, Ordering::Equal] => ">=",
+            [Ordering::Less, Ordering::Equal] => "<=",
+            [Ordering::Greater] => ">",
+            [Ordering::Less] => "<",
+            _ => "=",
+        }
+    }
+}
+
+/// Number of bytes with an optional k, M, G or T suffix
+fn parse_size(size: &str) -> Option<u64> {
+    let lower = size.to_lowercase();
+    let digits = lower.trim_end_matches(|c: char| c.is_alphabetic());
+    let multiplier: u64 = match lower[digits.len()..].trim_end_matches('b') {
+        "" => 1,
+        "k" => 1000,
+        "m" => 1_000_000,
+        "g" => 1_000_000_000,
+        "t" => 1_000_000_000_000,
+        _ => return None,
+    };
+    digits.trim().parse::<u64>().ok()?.checked_mul(multiplier)
+}
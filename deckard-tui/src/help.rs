@@ -0,0 +1,170 @@
+use ratatui::{
+    buffer::Buffer,
+    crossterm::event::KeyCode,
+    layout::Rect,
+    style::{Style, Stylize},
+    text::{Line, Text},
+    widgets::{Block, BorderType, Clear, Paragraph, Widget},
+};
+
+use crate::app::centered_rect;
+use crate::command::COMMANDS;
+
+/// Keys of the main screen and what they do
+const KEYS: &[(&str, &str)] = &[
+    ("j k", "Select the next or previous file"),
+    ("h l", "Focus the files or the clones"),
+    (
+        "gg G",
+        "Go to the first or last row, after a count to that row",
+    ),
+    ("Home End", "Go to the first or last row"),
+    ("Ctrl-d Ctrl-u", "Scroll half a page down or up"),
+    ("space", "Mark the selected file"),
+    ("x", "Mark or unmark the selected file"),
+    (
+        "v",
+        "Select a range of rows, then mark or unmark them with space",
+    ),
+    ("n N", "Go to the next or previous marked file"),
+    (
+        "a",
+        "Mark the duplicates of every group with the keep policy",
+    ),
+    ("D", "Delete the selected file"),
+    ("s", "Sort the focused table by the next column"),
+    ("S", "Reverse the sort of the focused table"),
+    ("click", "Sort by the clicked column, again to reverse it"),
+    ("< >", "Narrow or widen the files"),
+    (
+        "- +",
+        "Shrink or grow the tables above the marked files and the info",
+    ),
+    ("c", "Show or hide the clones"),
+    ("i", "Show or hide the file info"),
+    (
+        "o",
+        "Open the selected file, with the opener of its type when set",
+    ),
+    ("p", "Open the directory of the selected file"),
+    ("r", "Rename the selected file"),
+    (":", "Type a command"),
+    ("?", "Show this help"),
+    ("q", "Quit"),
+];
+
+/// Scrollable list of the keys and commands, narrowed down by a search
+#[derive(Debug, Default)]
+pub struct Help {
+    scroll: usize,
+    query: String,
+    searching: bool,
+}
+
+impl Help {
+    /// Every entry as its key or usage and its description
+    fn entries() -> Vec<(String, &'static str)> {
+        let keys = KEYS
+            .iter()
+            .map(|(key, description)| (key.to_string(), *description));
+        let commands = COMMANDS
+            .iter()
+            .map(|c| (format!(":{}", c.usage), c.description));
+        keys.chain(commands).collect()
+    }
+
+    /// Entries matching the search, all of them without one
+    fn matching(&self) -> Vec<(String, &'static str)> {
+        let query = self.query.to_lowercase();
+        Self::entries()
+            .into_iter()
+            .filter(|(name, description)| {
+                name.to_lowercase().contains(&query) || description.to_lowercase().contains(&query)
+            })
+            .collect()
+    }
+
+    /// Handle a key, returns false when the help is closed
+    pub fn handle_key(&mut self, key: KeyCode) -> bool {
+        if self.searching {
+            match key {
+                KeyCode::Char(c) => self.query.push(c),
+                KeyCode::Backspace if self.query.is_empty() => self.searching = false,
+                KeyCode::Backspace => _ = self.query.pop(),
+                KeyCode::Enter => self.searching = false,
+                KeyCode::Esc => {
+                    self.query.clear();
+                    self.searching = false;
+                }
+                _ => {}
+            }
+            self.scroll = 0;
+            return true;
+        }
+
+        match key {
+            KeyCode::Char('j') | KeyCode::Down => {
+                self.scroll = (self.scroll + 1).min(self.matching().len().saturating_sub(1))
+            }
+            KeyCode::Char('k') | KeyCode::Up => self.scroll = self.scroll.saturating_sub(1),
+            KeyCode::Char('/') => self.searching = true,
+            KeyCode::Char('q') | KeyCode::Char('?') | KeyCode::Esc => return false,
+            _ => {}
+        }
+        true
+    }
+
+    pub fn render(&self, buf: &mut Buffer, area: Rect) {
+        let matching = self.matching();
+        let width = matching
+            .iter()
+            .map(|(name, _)| name.len())
+            .max()
+            .unwrap_or(0);
+        let mut lines: Vec<Line> = matching
+            .iter()
+            .skip(self.scroll)
+            .map(|(name, description)| {
+                Line::from(vec![
+                    format!("{:width$}  ", name).blue().bold(),
+                    (*description).into(),
+                ])
+            })
+            .collect();
+        if lines.is_empty() {
+            lines.push(Line::from("nothing matches the search".dark_gray()));
+        }
+
+        let search = if self.searching || !self.query.is_empty() {
+            let cursor = if self.searching { "_ " } else { " " };
+            Line::from(vec![
+                " /".into(),
+                self.query.clone().yellow(),
+                cursor.into(),
+            ])
+        } else {
+            Line::from(vec![
+                " Scroll ".into(),
+                "<J/K>".blue().bold(),
+                " Search ".into(),
+                "</>".blue().bold(),
+                " Close ".into(),
+                "<Esc> ".blue().bold(),
+            ])
+        };
+
+        // fits small terminals, the rest is reached by scrolling
+        let height = (matching.len().max(1) as u16 + 2).min(area.height);
+        let area = centered_rect(area, 80, height);
+        Clear.render(area, buf);
+        Paragraph::new(Text::from(lines))
+            .block(
+                Block::bordered()
+                    .title(" Help ")
+                    .title_bottom(search.centered())
+                    .border_type(BorderType::Thick)
+                    .border_style(Style::new().green()),
+            )
+            .render(area, buf);
+    }
+}
@@ -2,18 +2,41 @@ use color_eyre::eyre::Result;
 
 mod app;
 mod cli;
+mod command;
+mod filter;
+mod help;
+mod settings;
 mod table;
+mod tree;
 mod tui;
+mod wizard;
 
 fn main() -> Result<()> {
     color_eyre::install()?;
     env_logger::init();
 
     let args = cli::cli().get_matches();
-    let config = cli::get_config();
+    // loading the config writes the defaults when there is no file yet
+    let first_run = !deckard::config::SearchConfig::get_config_path("deckard-cli").exists();
+    let mut config = cli::get_config();
+
+    if args.get_flag("save_config") {
+        config.save("deckard-cli");
+    }
+
+    if args.get_flag("open_config") {
+        deckard::config::SearchConfig::edit_config("deckard-cli")?;
+        return Ok(());
+    }
 
     let mut terminal = tui::init()?;
 
+    if first_run && !args.get_flag("save_config") {
+        let answers = wizard::Wizard::new(Default::default()).run(&mut terminal)?;
+        answers.save("deckard-cli");
+        config = cli::augment_config(answers, &args);
+    }
+
     let target_dirs = match args.get_many::<String>("params") {
         Some(values) => values.map(|v| v.as_str()).collect::<Vec<&str>>(),
         None => vec!["."],
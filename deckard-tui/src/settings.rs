@@ -0,0 +1,163 @@
+use deckard::config::SearchConfig;
+use ratatui::crossterm::event::KeyCode;
+
+/// Value of a setting while it's edited
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Value {
+    Toggle(bool),
+    Number(u64),
+}
+
+impl Value {
+    /// Edit the value with a key, returns false when the key doesn't apply
+    pub fn edit(&mut self, key: KeyCode) -> bool {
+        match (self, key) {
+            (Value::Toggle(on), KeyCode::Char(' ')) => *on = !*on,
+            (Value::Toggle(on), KeyCode::Char('y')) => *on = true,
+            (Value::Toggle(on), KeyCode::Char('n')) => *on = false,
+            (Value::Number(n), KeyCode::Char(c)) if c.is_ascii_digit() => {
+                *n = n
+                    .saturating_mul(10)
+                    .saturating_add(c.to_digit(10).unwrap_or_default() as u64)
+            }
+            (Value::Number(n), KeyCode::Backspace) => *n /= 10,
+            _ => return false,
+        }
+        true
+    }
+
+    pub fn label(&self) -> String {
+        match self {
+            Value::Toggle(true) => "yes".to_string(),
+            Value::Toggle(false) => "no".to_string(),
+            Value::Number(n) => n.to_string(),
+        }
+    }
+}
+
+/// Config values that can be edited from inside the TUI
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Setting {
+    SkipHidden,
+    SkipEmpty,
+    SameFileSystem,
+    FullHash,
+    CompareImages,
+    ImageThreshold,
+    CompareAudio,
+    Threads,
+    Cache,
+    Notify,
+    DryRun,
+    NoRemoveDirs,
+    VerifyBeforeAction,
+    ReadOnly,
+}
+
+impl Setting {
+    /// Asked on the first launch
+    pub const WIZARD: [Setting; 5] = [
+        Setting::SkipHidden,
+        Setting::CompareImages,
+        Setting::CompareAudio,
+        Setting::Threads,
+        Setting::DryRun,
+    ];
+
+    /// Listed in the settings screen
+    pub const ALL: [Setting; 14] = [
+        Setting::SkipHidden,
+        Setting::SkipEmpty,
+        Setting::SameFileSystem,
+        Setting::FullHash,
+        Setting::CompareImages,
+        Setting::ImageThreshold,
+        Setting::CompareAudio,
+        Setting::Threads,
+        Setting::Cache,
+        Setting::Notify,
+        Setting::DryRun,
+        Setting::NoRemoveDirs,
+        Setting::VerifyBeforeAction,
+        Setting::ReadOnly,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Setting::SkipHidden => "Skip hidden files",
+            Setting::SkipEmpty => "Skip empty files",
+            Setting::SameFileSystem => "Stay on one file system",
+            Setting::FullHash => "Hash whole files",
+            Setting::CompareImages => "Find similar images",
+            Setting::ImageThreshold => "Image distance threshold",
+            Setting::CompareAudio => "Find similar audio",
+            Setting::Threads => "Threads",
+            Setting::Cache => "Cache hashes",
+            Setting::Notify => "Notify when done",
+            Setting::DryRun => "Only preview removals",
+            Setting::NoRemoveDirs => "Keep emptied directories",
+            Setting::VerifyBeforeAction => "Verify before removing",
+            Setting::ReadOnly => "Read only",
+        }
+    }
+
+    pub fn help(self) -> &'static str {
+        match self {
+            Setting::SkipHidden => "Files and directories starting with a dot are not scanned",
+            Setting::SkipEmpty => "Empty files are not reported as duplicates",
+            Setting::SameFileSystem => "Don't descend into other mounted file systems",
+            Setting::FullHash => "Slower, but never mistakes files sharing samples for copies",
+            Setting::CompareImages => "Compares images by their content, slower",
+            Setting::ImageThreshold => "Highest hamming distance of similar images",
+            Setting::CompareAudio => "Compares audio by its fingerprint, much slower",
+            Setting::Threads => "0 uses every core",
+            Setting::Cache => "Reuse the hashes of unchanged files from earlier scans",
+            Setting::Notify => "Send a desktop notification when the scan finishes",
+            Setting::DryRun => "Removing files only shows what would be removed",
+            Setting::NoRemoveDirs => "Directories left empty after removing files are kept",
+            Setting::VerifyBeforeAction => "Compare copies byte for byte before removing them",
+            Setting::ReadOnly => "Refuse every removal",
+        }
+    }
+
+    pub fn get(self, config: &SearchConfig) -> Value {
+        match self {
+            Setting::SkipHidden => Value::Toggle(config.skip_hidden),
+            Setting::SkipEmpty => Value::Toggle(config.skip_empty),
+            Setting::SameFileSystem => Value::Toggle(config.same_file_system),
+            Setting::FullHash => Value::Toggle(config.hasher_config.full_hash),
+            Setting::CompareImages => Value::Toggle(config.image_config.compare),
+            Setting::ImageThreshold => Value::Number(config.image_config.threshold),
+            Setting::CompareAudio => Value::Toggle(config.audio_config.compare),
+            Setting::Threads => Value::Number(config.threads as u64),
+            Setting::Cache => Value::Toggle(config.cache),
+            Setting::Notify => Value::Toggle(config.notify),
+            Setting::DryRun => Value::Toggle(config.action_config.dry_run),
+            Setting::NoRemoveDirs => Value::Toggle(config.action_config.no_remove_dirs),
+            Setting::VerifyBeforeAction => Value::Toggle(config.action_config.verify_before_action),
+            Setting::ReadOnly => Value::Toggle(config.action_config.read_only),
+        }
+    }
+
+    pub fn set(self, config: &mut SearchConfig, value: Value) {
+        match (self, value) {
+            (Setting::SkipHidden, Value::Toggle(on)) => config.skip_hidden = on,
+            (Setting::SkipEmpty, Value::Toggle(on)) => config.skip_empty = on,
+            (Setting::SameFileSystem, Value::Toggle(on)) => config.same_file_system = on,
+            (Setting::FullHash, Value::Toggle(on)) => config.hasher_config.full_hash = on,
+            (Setting::CompareImages, Value::Toggle(on)) => config.image_config.compare = on,
+            (Setting::ImageThreshold, Value::Number(n)) => config.image_config.threshold = n,
+            (Setting::CompareAudio, Value::Toggle(on)) => config.audio_config.compare = on,
+            (Setting::Threads, Value::Number(n)) => config.threads = n as usize,
+            (Setting::Cache, Value::Toggle(on)) => config.cache = on,
+            (Setting::Notify, Value::Toggle(on)) => config.notify = on,
+            (Setting::DryRun, Value::Toggle(on)) => config.action_config.dry_run = on,
+            (Setting::NoRemoveDirs, Value::Toggle(on)) => config.action_config.no_remove_dirs = on,
+            (Setting::VerifyBeforeAction, Value::Toggle(on)) => {
+                config.action_config.verify_before_action = on
+            }
+            (Setting::ReadOnly, Value::Toggle(on)) => config.action_config.read_only = on,
+            _ => {}
+        }
+    }
+}
@@ -1,24 +1,142 @@
+use std::collections::HashMap;
+use std::ops::RangeInclusive;
 use std::path::Path;
 use std::path::PathBuf;
 
 use crate::app::format_path;
 use color_eyre::eyre::Result;
+use deckard::config::TableColumn;
 use deckard::index::FileIndex;
 use ratatui::{
     buffer::Buffer,
-    crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind},
-    layout::{Alignment, Constraint, Direction, Layout, Margin, Rect, Rows},
-    style::{Modifier, Style, Stylize},
-    symbols::border,
-    text::{Line, Text},
+    layout::{Constraint, Layout, Margin, Rect},
+    style::{Color, Modifier, Style, Stylize},
+    text::Text,
     widgets::{
-        block::{Position, Title},
-        Block, BorderType, Borders, Cell, HighlightSpacing, Paragraph, Row, Scrollbar,
-        ScrollbarOrientation, ScrollbarState, StatefulWidget, Table, TableState, Widget,
+        Block, BorderType, Cell, Row, Scrollbar, ScrollbarOrientation, ScrollbarState,
+        StatefulWidget, Table, TableState,
     },
-    Frame,
 };
 
+/// Accent colors telling the scan roots apart
+const ROOT_COLORS: [Color; 6] = [
+    Color::Cyan,
+    Color::Magenta,
+    Color::Yellow,
+    Color::Green,
+    Color::Blue,
+    Color::Red,
+];
+
+/// Moves of the selection past the next or previous row
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Jump {
+    First,
+    Last,
+    /// Row counted from 0
+    Row(usize),
+    HalfPageDown,
+    HalfPageUp,
+}
+
+/// Columns the rows can be sorted by
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Column {
+    Path,
+    Field(TableColumn),
+}
+
+/// Name of the column in commands and the config
+pub fn column_name(column: TableColumn) -> &'static str {
+    match column {
+        TableColumn::Date => "date",
+        TableColumn::Size => "size",
+        TableColumn::Clones => "clones",
+        TableColumn::Mime => "mime",
+        TableColumn::Extension => "extension",
+        TableColumn::Parent => "parent",
+    }
+}
+
+pub fn parse_column(name: &str) -> Result<TableColumn, String> {
+    let name = name.trim().to_lowercase();
+    TableColumn::ALL
+        .into_iter()
+        .find(|c| column_name(*c) == name || (name == "ext" && *c == TableColumn::Extension))
+        .ok_or_else(|| {
+            let names: Vec<&str> = TableColumn::ALL.into_iter().map(column_name).collect();
+            format!(
+                "unknown column {}, expected one of {}",
+                name,
+                names.join(", ")
+            )
+        })
+}
+
+fn column_title(column: TableColumn) -> &'static str {
+    match column {
+        TableColumn::Date => "Date",
+        TableColumn::Size => "Size",
+        TableColumn::Clones => "Clones",
+        TableColumn::Mime => "MIME",
+        TableColumn::Extension => "Ext",
+        TableColumn::Parent => "Parent",
+    }
+}
+
+fn column_width(column: TableColumn) -> Constraint {
+    match column {
+        TableColumn::Date => Constraint::Max(10),
+        TableColumn::Size => Constraint::Max(12),
+        TableColumn::Clones => Constraint::Max(6),
+        TableColumn::Mime => Constraint::Max(20),
+        TableColumn::Extension => Constraint::Max(6),
+        TableColumn::Parent => Constraint::Max(30),
+    }
+}
+
+fn column_text(column: TableColumn, path: &Path, file_index: &FileIndex) -> String {
+    let file = file_index.file_entry(path);
+    match column {
+        TableColumn::Date => file.map(|f| f.modified.to_string()).unwrap_or_default(),
+        TableColumn::Size => humansize::format_size(
+            file_index.file_size(path).unwrap_or_default(),
+            humansize::DECIMAL,
+        ),
+        TableColumn::Clones => clone_count(path, file_index).to_string(),
+        TableColumn::Mime => file.and_then(|f| f.mime_type.clone()).unwrap_or_default(),
+        TableColumn::Extension => path
+            .extension()
+            .map(|e| e.to_string_lossy().to_string())
+            .unwrap_or_default(),
+        TableColumn::Parent => path
+            .parent()
+            .map(|p| format_path(&p.to_path_buf(), &file_index.dirs))
+            .unwrap_or_default(),
+    }
+}
+
+/// Key ordering the rows by the column, numbers first then text
+fn column_key(column: TableColumn, path: &Path, file_index: &FileIndex) -> (i64, String) {
+    let file = file_index.file_entry(path);
+    match column {
+        TableColumn::Date => (file.map_or(0, |f| f.modified.timestamp()), String::new()),
+        TableColumn::Size => (
+            file_index.file_size(path).unwrap_or_default() as i64,
+            String::new(),
+        ),
+        TableColumn::Clones => (clone_count(path, file_index) as i64, String::new()),
+        _ => (0, column_text(column, path, file_index).to_lowercase()),
+    }
+}
+
+fn clone_count(path: &Path, file_index: &FileIndex) -> usize {
+    file_index
+        .duplicates
+        .get(path)
+        .map_or(0, |copies| copies.len())
+}
+
 #[derive(Debug, Default)]
 pub struct FileTable {
     pub table_state: TableState,
@@ -26,26 +144,154 @@ pub struct FileTable {
     paths: Vec<PathBuf>,
     selected_path: Option<PathBuf>,
     scroll_state: ScrollbarState,
-    header: Vec<&'static str>,
+    /// Header of the path column, no header is shown when empty
+    title: &'static str,
+    columns: Vec<TableColumn>,
+    /// Match confidence of each path, shown in its own column when set
+    scores: HashMap<PathBuf, u8>,
+    /// Rows visible when last rendered
+    page_height: usize,
+    /// Column the rows are sorted by and whether it's descending, the order
+    /// the paths were given in when not set
+    sort: Option<(Column, bool)>,
+    /// Header cell of each sortable column when last rendered, for mouse clicks
+    header_areas: Vec<(Rect, Column)>,
+    /// Row the visual selection started at, the range runs to the selected row
+    visual_anchor: Option<PathBuf>,
     // callback function that populates rows
 }
 
 impl FileTable {
-    pub fn new(header: Vec<&'static str>) -> Self {
+    pub fn new(title: &'static str, columns: Vec<TableColumn>) -> Self {
         Self {
             table_state: TableState::new(),
             table_len: 0,
             paths: Vec::new(),
             selected_path: None,
             scroll_state: ScrollbarState::new(0),
-            header: header,
+            title,
+            columns,
+            scores: HashMap::new(),
+            page_height: 0,
+            sort: None,
+            header_areas: Vec::new(),
+            visual_anchor: None,
+        }
+    }
+
+    /// Start selecting a range of rows at the selected row
+    pub fn start_visual(&mut self) {
+        self.visual_anchor = self.selected_path.clone();
+    }
+
+    pub fn end_visual(&mut self) {
+        self.visual_anchor = None;
+    }
+
+    pub fn in_visual(&self) -> bool {
+        self.visual_anchor.is_some()
+    }
+
+    /// Rows between the visual anchor and the selected row, both included
+    fn visual_rows(&self) -> Option<RangeInclusive<usize>> {
+        let anchor = self.visual_anchor.as_ref()?;
+        let anchor = self.paths.iter().position(|p| p == anchor)?;
+        let selected = self.table_state.selected()?;
+        Some(anchor.min(selected)..=anchor.max(selected))
+    }
+
+    /// Paths of the visual selection, empty outside of visual mode
+    pub fn visual_paths(&self) -> Vec<PathBuf> {
+        self.visual_rows()
+            .map(|rows| self.paths[rows].to_vec())
+            .unwrap_or_default()
+    }
+
+    /// Sort by the column, sorting by it again flips the direction
+    pub fn sort_by(&mut self, column: Column) {
+        self.sort = match self.sort {
+            Some((current, descending)) if current == column => Some((column, !descending)),
+            _ => Some((column, false)),
+        };
+    }
+
+    /// Show these columns after the path, a sort by a hidden column is dropped
+    pub fn set_columns(&mut self, columns: Vec<TableColumn>) {
+        if let Some((Column::Field(field), _)) = self.sort {
+            if !columns.contains(&field) {
+                self.sort = None;
+            }
+        }
+        self.columns = columns;
+    }
+
+    /// Columns that can be sorted by, in the order they are shown
+    fn sortable(&self) -> Vec<Column> {
+        std::iter::once(Column::Path)
+            .chain(self.columns.iter().map(|c| Column::Field(*c)))
+            .collect()
+    }
+
+    /// Sort by the next column, after the last one the given order is kept
+    pub fn cycle_sort(&mut self) {
+        let sortable = self.sortable();
+        let next = match self.sort {
+            None => sortable.first().copied(),
+            Some((current, _)) => sortable
+                .iter()
+                .skip_while(|c| **c != current)
+                .nth(1)
+                .copied(),
+        };
+        self.sort = next.map(|column| (column, false));
+    }
+
+    /// Flip the direction of the current sort
+    pub fn toggle_sort_direction(&mut self) {
+        if let Some((_, descending)) = self.sort.as_mut() {
+            *descending = !*descending;
         }
     }
 
-    pub fn update_table(&mut self, paths: &Vec<PathBuf>) {
-        self.paths = paths.clone();
+    /// Column whose header was rendered at the position
+    pub fn column_at(&self, x: u16, y: u16) -> Option<Column> {
+        self.header_areas
+            .iter()
+            .find(|(area, _)| area.contains((x, y).into()))
+            .map(|(_, column)| *column)
+    }
+
+    /// Order the paths by the sort column, keeping the selected path selected
+    pub fn sort_paths(&mut self, file_index: &FileIndex) {
+        let Some((column, descending)) = self.sort else {
+            return;
+        };
+        match column {
+            Column::Path => self.paths.sort(),
+            Column::Field(field) => self
+                .paths
+                .sort_by_cached_key(|p| column_key(field, p, file_index)),
+        }
+        if descending {
+            self.paths.reverse();
+        }
+        if let Some(selected) = &self.selected_path {
+            if let Some(i) = self.paths.iter().position(|p| p == selected) {
+                self.select_entry(i);
+            }
+        }
+    }
+
+    pub fn update_table(&mut self, paths: Vec<PathBuf>) {
+        self.paths = paths;
+        self.scores.clear();
+        self.visual_anchor = None;
         self.table_len = self.paths.len();
-        self.scroll_state = ScrollbarState::new(self.table_len - 1);
+        self.scroll_state = ScrollbarState::new(self.table_len.saturating_sub(1));
+    }
+
+    pub fn set_scores(&mut self, scores: HashMap<PathBuf, u8>) {
+        self.scores = scores;
     }
 
     pub fn select_entry(&mut self, index: usize) {
@@ -53,7 +299,7 @@ impl FileTable {
             return;
         }
         self.table_state.select(Some(index));
-        self.selected_path = self.paths.get(index).map(|p| p.clone());
+        self.selected_path = self.paths.get(index).cloned();
         self.scroll_state = self.scroll_state.position(index);
     }
 
@@ -91,10 +337,59 @@ impl FileTable {
         self.select_entry(i);
     }
 
+    /// Select the row of the path, returns false when it's not listed
+    pub fn select_path(&mut self, path: &Path) -> bool {
+        match self.paths.iter().position(|p| p == path) {
+            Some(i) => {
+                self.select_entry(i);
+                true
+            }
+            None => false,
+        }
+    }
+
     pub fn select_first(&mut self) {
         self.select_entry(0);
     }
 
+    /// Select the next row after the selected one whose path passes, wrapping
+    /// around, or the previous one going backwards. Returns false when no row passes.
+    pub fn select_next_where(&mut self, forward: bool, pass: impl Fn(&Path) -> bool) -> bool {
+        let len = self.table_len;
+        let current = self.table_state.selected();
+        let found = (1..=len)
+            .map(|step| match (current, forward) {
+                (Some(i), true) => (i + step) % len,
+                (Some(i), false) => (i + len - step % len) % len,
+                (None, true) => step - 1,
+                (None, false) => len - step,
+            })
+            .find(|&i| pass(&self.paths[i]));
+        match found {
+            Some(i) => {
+                self.select_entry(i);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn jump(&mut self, jump: Jump) {
+        if self.table_len == 0 {
+            return;
+        }
+        let current = self.table_state.selected().unwrap_or(0);
+        let half_page = (self.page_height / 2).max(1);
+        let index = match jump {
+            Jump::First => 0,
+            Jump::Last => self.table_len - 1,
+            Jump::Row(row) => row,
+            Jump::HalfPageDown => current + half_page,
+            Jump::HalfPageUp => current.saturating_sub(half_page),
+        };
+        self.select_entry(index.min(self.table_len - 1));
+    }
+
     pub fn select_none(&mut self) {
         self.table_state.select(None);
         self.selected_path = None;
@@ -107,55 +402,122 @@ impl FileTable {
     pub fn render(&mut self, buf: &mut Buffer, area: Rect, focused: bool, file_index: &FileIndex) {
         let header_style = Style::default();
         let selected_style = Style::default().add_modifier(Modifier::REVERSED);
+        // the borders and the header take three rows
+        self.page_height = area.height.saturating_sub(3) as usize;
 
-        let header = self
-            .header
-            .clone()
+        // only worth a column when comparing multiple roots
+        let show_root = file_index.dirs.len() > 1;
+        let mut roots: Vec<&PathBuf> = file_index.dirs.iter().collect();
+        roots.sort();
+
+        let show_scores = !self.scores.is_empty();
+        let show_header = !self.title.is_empty();
+        let mut header: Vec<String> = std::iter::once(self.title)
+            .chain(self.columns.iter().map(|c| column_title(*c)))
+            .chain([" "])
+            .map(|h| h.to_string())
+            .collect();
+        let mut columns: Vec<Option<Column>> = self.sortable().into_iter().map(Some).collect();
+        columns.push(None);
+        if let Some((column, descending)) = self.sort {
+            if let Some(i) = columns.iter().position(|c| *c == Some(column)) {
+                if let Some(label) = header.get_mut(i) {
+                    label.push_str(if descending { " ▼" } else { " ▲" });
+                }
+            }
+        }
+        if show_scores {
+            header.insert(header.len() - 1, "Match".to_string());
+            columns.insert(columns.len() - 1, None);
+        }
+        if show_root {
+            header.insert(1, "Root".to_string());
+            columns.insert(1, None);
+        }
+        if !show_header {
+            header.clear();
+        }
+        let header = header
             .into_iter()
             .map(Cell::from)
             .collect::<Row>()
             .style(header_style);
 
-        let rows = &self.paths.clone().into_iter().map(|p| {
-            let path = format_path(&p, &file_index.dirs);
-            let size = humansize::format_size(
-                file_index.file_size(&p).unwrap_or_default(),
-                humansize::DECIMAL,
+        let visual = self.visual_rows();
+        let rows = self.paths.iter().enumerate().map(|(i, p)| {
+            let path = format_path(p, &file_index.dirs);
+
+            let mut cells = vec![Cell::from(Text::from(path))];
+            cells.extend(
+                self.columns
+                    .iter()
+                    .map(|c| Cell::from(Text::from(column_text(*c, p, file_index)))),
             );
-            let date = file_index.files[&p].modified;
-
-            let cells = vec![
-                Cell::from(Text::from(format!("{path}"))),
-                Cell::from(Text::from(format!("{date}"))),
-                Cell::from(Text::from(format!("{size}"))),
-                Cell::from(Text::from(format!(" "))),
-            ];
-            cells.into_iter().collect::<Row>().style(Style::new())
+            cells.push(Cell::from(Text::from(" ")));
+            if show_scores {
+                let score = self
+                    .scores
+                    .get(p)
+                    .map(|s| format!("{s}%"))
+                    .unwrap_or_default();
+                cells.insert(cells.len() - 1, Cell::from(Text::from(score)));
+            }
+            if show_root {
+                let color = file_index
+                    .root_of(p)
+                    .and_then(|root| roots.iter().position(|r| *r == root))
+                    .map(|i| ROOT_COLORS[i % ROOT_COLORS.len()])
+                    .unwrap_or(Color::Reset);
+                let label = file_index.root_label(p).unwrap_or_default();
+                cells.insert(
+                    1,
+                    Cell::from(Text::from(label).style(Style::new().fg(color))),
+                );
+            }
+            let style = if visual.as_ref().is_some_and(|rows| rows.contains(&i)) {
+                Style::new().bg(Color::DarkGray)
+            } else {
+                Style::new()
+            };
+            cells.into_iter().collect::<Row>().style(style)
         });
-        let block;
-        if focused {
-            block = Block::bordered()
+        let block = if focused {
+            Block::bordered()
                 // .title(" Clones ")
                 .border_type(BorderType::Thick)
-                .border_style(Style::new().green());
+                .border_style(Style::new().green())
         } else {
-            block = Block::bordered()
+            Block::bordered()
                 .border_type(BorderType::Plain)
-                .border_style(Style::new().dark_gray());
+                .border_style(Style::new().dark_gray())
         };
-        let table = Table::new(
-            rows.clone(),
-            [
-                // + 1 is for padding.
-                Constraint::Min(10),
-                Constraint::Max(10),
-                Constraint::Max(12),
-                Constraint::Max(1),
-            ],
-        )
-        .header(header)
-        .highlight_style(selected_style)
-        .block(block);
+        // + 1 is for padding.
+        let mut widths = vec![Constraint::Min(10)];
+        widths.extend(self.columns.iter().map(|c| column_width(*c)));
+        widths.push(Constraint::Max(1));
+        if show_scores {
+            widths.insert(widths.len() - 1, Constraint::Max(5));
+        }
+        if show_root {
+            widths.insert(1, Constraint::Max(12));
+        }
+        self.header_areas.clear();
+        if show_header {
+            let inner = block.inner(area);
+            let header_row = Rect { height: 1, ..inner };
+            let cells = Layout::horizontal(widths.clone())
+                .spacing(1)
+                .split(header_row);
+            self.header_areas = cells
+                .iter()
+                .zip(&columns)
+                .filter_map(|(cell, column)| Some((*cell, (*column)?)))
+                .collect();
+        }
+        let table = Table::new(rows, widths)
+            .header(header)
+            .highlight_style(selected_style)
+            .block(block);
 
         StatefulWidget::render(table, area, buf, &mut self.table_state);
 
@@ -0,0 +1,351 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::table::Jump;
+use deckard::index::FileIndex;
+use ratatui::{
+    buffer::Buffer,
+    layout::{Constraint, Margin, Rect},
+    style::{Modifier, Style, Stylize},
+    text::Text,
+    widgets::{
+        Block, BorderType, Cell, Row, Scrollbar, ScrollbarOrientation, ScrollbarState,
+        StatefulWidget, Table, TableState,
+    },
+};
+
+const USAGE_BAR_WIDTH: usize = 10;
+
+/// Directory tree with aggregated sizes, used in disk usage mode.
+/// Every path is allocated once and shared between the maps.
+#[derive(Debug, Default)]
+pub struct FileTree {
+    pub table_state: TableState,
+    pub table_len: usize,
+    roots: HashSet<PathBuf>,
+    sizes: HashMap<Arc<Path>, u64>,
+    children: HashMap<Arc<Path>, HashSet<Arc<Path>>>,
+    // directory currently shown, None lists the scan roots
+    current_dir: Option<PathBuf>,
+    paths: Vec<Arc<Path>>,
+    selected_path: Option<Arc<Path>>,
+    scroll_state: ScrollbarState,
+    /// Rows visible when last rendered
+    page_height: usize,
+}
+
+impl FileTree {
+    pub fn new() -> Self {
+        Self {
+            table_state: TableState::new(),
+            table_len: 0,
+            roots: HashSet::new(),
+            sizes: HashMap::new(),
+            children: HashMap::new(),
+            current_dir: None,
+            paths: Vec::new(),
+            selected_path: None,
+            scroll_state: ScrollbarState::new(0),
+            page_height: 0,
+        }
+    }
+
+    /// Build the tree from the indexed files, aggregating sizes up to the scan roots
+    pub fn update_tree(&mut self, file_index: &FileIndex) {
+        self.roots = file_index.dirs.clone();
+        self.sizes.clear();
+        self.children.clear();
+
+        for (path, file) in &file_index.files {
+            let Some(root) = self.roots.iter().find(|r| path.starts_with(r)) else {
+                continue;
+            };
+
+            let mut child: Arc<Path> = Arc::from(path.as_path());
+            self.sizes.insert(child.clone(), file.size);
+            while let Some(parent) = child.parent() {
+                if !parent.starts_with(root) {
+                    break;
+                }
+                let parent = match self.children.get_key_value(parent) {
+                    Some((known, _)) => known.clone(),
+                    None => Arc::from(parent),
+                };
+                *self.sizes.entry(parent.clone()).or_default() += file.size;
+                self.children
+                    .entry(parent.clone())
+                    .or_default()
+                    .insert(child);
+                child = parent;
+            }
+        }
+
+        // a missing current directory falls back to the roots listing
+        if let Some(dir) = &self.current_dir {
+            if !self.children.contains_key(dir.as_path()) {
+                self.current_dir = None;
+            }
+        }
+        self.update_entries();
+    }
+
+    fn update_entries(&mut self) {
+        let mut paths: Vec<Arc<Path>> = match &self.current_dir {
+            Some(dir) => self
+                .children
+                .get(dir.as_path())
+                .map(|c| c.iter().cloned().collect())
+                .unwrap_or_default(),
+            None => self.roots.iter().map(|r| Arc::from(r.as_path())).collect(),
+        };
+
+        paths.sort_by(|a, b| {
+            let a_size = self.size(a);
+            let b_size = self.size(b);
+            b_size.cmp(&a_size).then(a.cmp(b))
+        });
+
+        self.paths = paths;
+        self.table_len = self.paths.len();
+        self.scroll_state = ScrollbarState::new(self.table_len.saturating_sub(1));
+    }
+
+    pub fn size(&self, path: &Path) -> u64 {
+        self.sizes.get(path).copied().unwrap_or_default()
+    }
+
+    pub fn is_dir(&self, path: &Path) -> bool {
+        self.children.contains_key(path)
+    }
+
+    /// Descend into the selected directory
+    pub fn enter(&mut self) {
+        if let Some(selected) = self.selected_path.clone() {
+            if self.is_dir(&selected) {
+                self.current_dir = Some(selected.to_path_buf());
+                self.update_entries();
+                self.select_first();
+            }
+        }
+    }
+
+    /// Go up to the parent directory, keeping the directory we came from selected
+    pub fn leave(&mut self) {
+        let Some(current) = self.current_dir.take() else {
+            return;
+        };
+        if !self.roots.contains(&current) {
+            self.current_dir = current.parent().map(|p| p.to_path_buf());
+        }
+        self.update_entries();
+        let index = self.paths.iter().position(|p| **p == *current).unwrap_or(0);
+        self.select_entry(index);
+    }
+
+    /// Remove a file or a directory from the tree, updating the sizes of its parents
+    pub fn remove(&mut self, path: &Path) {
+        let size = self.size(path);
+
+        let mut stack: Vec<Arc<Path>> = vec![Arc::from(path)];
+        while let Some(p) = stack.pop() {
+            self.sizes.remove(&p);
+            if let Some(children) = self.children.remove(&p) {
+                stack.extend(children);
+            }
+        }
+
+        let mut child = path.to_path_buf();
+        while let Some(parent) = child.parent() {
+            let parent = parent.to_path_buf();
+            if let Some(parent_size) = self.sizes.get_mut(parent.as_path()) {
+                *parent_size = parent_size.saturating_sub(size);
+            }
+            if child == *path {
+                if let Some(children) = self.children.get_mut(parent.as_path()) {
+                    children.remove(path);
+                }
+            }
+            if self.roots.contains(&parent) {
+                break;
+            }
+            child = parent;
+        }
+
+        self.roots.remove(path);
+        if let Some(dir) = &self.current_dir {
+            if dir.starts_with(path) {
+                self.current_dir = path.parent().map(|p| p.to_path_buf());
+            }
+        }
+
+        let index = self.table_state.selected().unwrap_or(0);
+        self.update_entries();
+        self.select_entry(index.min(self.table_len.saturating_sub(1)));
+    }
+
+    pub fn select_entry(&mut self, index: usize) {
+        if self.table_len == 0 {
+            self.select_none();
+            return;
+        }
+        self.table_state.select(Some(index));
+        self.selected_path = self.paths.get(index).cloned();
+        self.scroll_state = self.scroll_state.position(index);
+    }
+
+    pub fn select_next(&mut self) {
+        if self.table_len == 0 {
+            return;
+        }
+        let i = match self.table_state.selected() {
+            Some(i) => {
+                if i >= self.table_len - 1 {
+                    0
+                } else {
+                    i + 1
+                }
+            }
+            None => 0,
+        };
+        self.select_entry(i);
+    }
+
+    pub fn select_previous(&mut self) {
+        if self.table_len == 0 {
+            return;
+        }
+        let i = match self.table_state.selected() {
+            Some(i) => {
+                if i == 0 {
+                    self.table_len - 1
+                } else {
+                    i - 1
+                }
+            }
+            None => 0,
+        };
+        self.select_entry(i);
+    }
+
+    pub fn select_first(&mut self) {
+        self.select_entry(0);
+    }
+
+    pub fn jump(&mut self, jump: Jump) {
+        if self.table_len == 0 {
+            return;
+        }
+        let current = self.table_state.selected().unwrap_or(0);
+        let half_page = (self.page_height / 2).max(1);
+        let index = match jump {
+            Jump::First => 0,
+            Jump::Last => self.table_len - 1,
+            Jump::Row(row) => row,
+            Jump::HalfPageDown => current + half_page,
+            Jump::HalfPageUp => current.saturating_sub(half_page),
+        };
+        self.select_entry(index.min(self.table_len - 1));
+    }
+
+    pub fn select_none(&mut self) {
+        self.table_state.select(None);
+        self.selected_path = None;
+    }
+
+    pub fn selected_path(&self) -> Option<PathBuf> {
+        self.selected_path.as_ref().map(|p| p.to_path_buf())
+    }
+
+    pub fn render(&mut self, buf: &mut Buffer, area: Rect, focused: bool) {
+        let header_style = Style::default();
+        let selected_style = Style::default().add_modifier(Modifier::REVERSED);
+        // the borders and the header take three rows
+        self.page_height = area.height.saturating_sub(3) as usize;
+
+        let title = match &self.current_dir {
+            Some(dir) => format!(" {} ", dir.to_string_lossy()),
+            None => " / ".to_string(),
+        };
+
+        let header = vec!["Name", "Size", "Usage", " "]
+            .into_iter()
+            .map(Cell::from)
+            .collect::<Row>()
+            .style(header_style);
+
+        let total: u64 = self.paths.iter().map(|p| self.size(p)).sum();
+
+        let rows = self.paths.iter().map(|p| {
+            let size = self.size(p);
+            let name = if self.current_dir.is_none() {
+                p.to_string_lossy().to_string()
+            } else {
+                p.file_name()
+                    .map(|n| n.to_string_lossy().to_string())
+                    .unwrap_or_default()
+            };
+            let name = if self.is_dir(p) {
+                format!("{name}/")
+            } else {
+                name
+            };
+
+            let filled = if total > 0 {
+                (size as f64 / total as f64 * USAGE_BAR_WIDTH as f64).round() as usize
+            } else {
+                0
+            };
+            let bar = format!(
+                "{}{}",
+                "#".repeat(filled),
+                " ".repeat(USAGE_BAR_WIDTH - filled)
+            );
+
+            let cells = vec![
+                Cell::from(Text::from(name)),
+                Cell::from(Text::from(humansize::format_size(size, humansize::DECIMAL))),
+                Cell::from(Text::from(format!("[{bar}]"))),
+                Cell::from(Text::from(" ")),
+            ];
+            cells.into_iter().collect::<Row>().style(Style::new())
+        });
+
+        let block = if focused {
+            Block::bordered()
+                .title(title)
+                .border_type(BorderType::Thick)
+                .border_style(Style::new().green())
+        } else {
+            Block::bordered()
+                .title(title)
+                .border_type(BorderType::Plain)
+                .border_style(Style::new().dark_gray())
+        };
+        let table = Table::new(
+            rows,
+            [
+                Constraint::Min(10),
+                Constraint::Max(12),
+                Constraint::Length(USAGE_BAR_WIDTH as u16 + 2),
+                Constraint::Max(1),
+            ],
+        )
+        .header(header)
+        .highlight_style(selected_style)
+        .block(block);
+
+        StatefulWidget::render(table, area, buf, &mut self.table_state);
+
+        let scrollbar = Scrollbar::default().orientation(ScrollbarOrientation::VerticalRight);
+        StatefulWidget::render(
+            scrollbar,
+            area.inner(Margin {
+                vertical: 1,
+                horizontal: 0,
+            }),
+            buf,
+            &mut self.scroll_state,
+        );
+    }
+}
@@ -0,0 +1,108 @@
+use std::time::Duration;
+
+use color_eyre::eyre::Result;
+use deckard::config::SearchConfig;
+use ratatui::{
+    buffer::Buffer,
+    crossterm::event::{self, Event, KeyCode, KeyEventKind},
+    layout::Rect,
+    style::{Style, Stylize},
+    text::{Line, Text},
+    widgets::{Block, BorderType, Clear, Paragraph, Widget},
+};
+
+use crate::app::centered_rect;
+use crate::settings::Setting;
+
+/// Questions asked on the first launch, before any config file exists
+#[derive(Debug)]
+pub struct Wizard {
+    config: SearchConfig,
+    step: usize,
+    done: bool,
+}
+
+impl Wizard {
+    pub fn new(config: SearchConfig) -> Self {
+        Self {
+            config,
+            step: 0,
+            done: false,
+        }
+    }
+
+    /// Ask every question, skipping leaves the remaining answers at their defaults
+    pub fn run(mut self, terminal: &mut crate::tui::Tui) -> Result<SearchConfig> {
+        while !self.done {
+            terminal.draw(|frame| self.render(frame.area(), frame.buffer_mut()))?;
+            if !event::poll(Duration::from_millis(100))? {
+                continue;
+            }
+            match event::read()? {
+                Event::Key(key_event) if key_event.kind == KeyEventKind::Press => {
+                    self.handle_key(key_event.code)
+                }
+                _ => {}
+            }
+        }
+        Ok(self.config)
+    }
+
+    fn setting(&self) -> Setting {
+        Setting::WIZARD[self.step]
+    }
+
+    fn handle_key(&mut self, key: KeyCode) {
+        let setting = self.setting();
+        let mut value = setting.get(&self.config);
+        if value.edit(key) {
+            setting.set(&mut self.config, value);
+            return;
+        }
+        match key {
+            KeyCode::Enter | KeyCode::Tab | KeyCode::Down => {
+                if self.step + 1 < Setting::WIZARD.len() {
+                    self.step += 1;
+                } else {
+                    self.done = true;
+                }
+            }
+            KeyCode::BackTab | KeyCode::Up => self.step = self.step.saturating_sub(1),
+            KeyCode::Esc => self.done = true,
+            _ => {}
+        }
+    }
+
+    fn render(&self, area: Rect, buf: &mut Buffer) {
+        let setting = self.setting();
+        let lines = vec![
+            Line::from(setting.name().bold()),
+            Line::from(setting.get(&self.config).label().yellow()),
+            Line::from(setting.help().dark_gray()),
+            Line::from(""),
+            Line::from(vec![
+                "Change ".into(),
+                "<Space|0-9>".blue().bold(),
+                " Next ".into(),
+                "<Enter>".blue().bold(),
+                " Back ".into(),
+                "<Up>".blue().bold(),
+                " Skip ".into(),
+                "<Esc>".blue().bold(),
+            ]),
+        ];
+
+        let title = format!(" Setup {}/{} ", self.step + 1, Setting::WIZARD.len());
+        let area = centered_rect(area, 60, lines.len() as u16 + 2);
+        Clear.render(area, buf);
+        Paragraph::new(Text::from(lines))
+            .centered()
+            .block(
+                Block::bordered()
+                    .title(title)
+                    .border_type(BorderType::Thick)
+                    .border_style(Style::new().green()),
+            )
+            .render(area, buf);
+    }
+}
@@ -0,0 +1,92 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use deckard::collect_paths;
+use deckard::config::{ChromaPreset, SearchConfig};
+use deckard::index::FileIndex;
+use std::fs;
+use std::path::PathBuf;
+
+const SAMPLE_RATE: u32 = 44100;
+
+/// Write a sweeping stereo tone as a 16 bit WAV file, returns its directory
+fn write_tone(seconds: u32) -> PathBuf {
+    let dir = std::env::temp_dir().join("deckard_audio_bench");
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("tone.wav");
+
+    let frames = SAMPLE_RATE * seconds;
+    let data_len = frames * 2 * 2;
+    let mut wav = Vec::with_capacity(44 + data_len as usize);
+    wav.extend(b"RIFF");
+    wav.extend((36 + data_len).to_le_bytes());
+    wav.extend(b"WAVEfmt ");
+    wav.extend(16u32.to_le_bytes());
+    wav.extend(1u16.to_le_bytes());
+    wav.extend(2u16.to_le_bytes());
+    wav.extend(SAMPLE_RATE.to_le_bytes());
+    wav.extend((SAMPLE_RATE * 4).to_le_bytes());
+    wav.extend(4u16.to_le_bytes());
+    wav.extend(16u16.to_le_bytes());
+    wav.extend(b"data");
+    wav.extend(data_len.to_le_bytes());
+    for i in 0..frames {
+        let t = i as f64 / SAMPLE_RATE as f64;
+        let sample = ((t * (220.0 + 220.0 * t)).sin() * 8000.0) as i16;
+        wav.extend(sample.to_le_bytes());
+        wav.extend(sample.to_le_bytes());
+    }
+    fs::write(&path, wav).unwrap();
+    dir
+}
+
+fn fingerprint(dir: &PathBuf, config: SearchConfig) {
+    let mut index = black_box(FileIndex::new(black_box(collect_paths(vec![dir])), config));
+    black_box(index.index_dirs());
+    index.process_files(None, None);
+}
+
+pub fn criterion_benchmark(c: &mut Criterion) {
+    let dir = write_tone(30);
+
+    let mut g = c.benchmark_group("audio");
+    g.sample_size(10);
+
+    for i in [
+        ChromaPreset::Test1,
+        ChromaPreset::Test2,
+        ChromaPreset::Test3,
+        ChromaPreset::Test4,
+        ChromaPreset::Test5,
+    ]
+    .iter()
+    {
+        g.bench_with_input(
+            BenchmarkId::new("preset", format!("{:?}", i)),
+            i,
+            |b, &i| {
+                b.iter(|| {
+                    let mut config = black_box(SearchConfig::default());
+                    config.audio_config.compare = true;
+                    config.audio_config.preset = i;
+                    fingerprint(&dir, config);
+                })
+            },
+        );
+    }
+
+    for i in [1, 2, 4].iter() {
+        g.bench_with_input(BenchmarkId::new("downsample", i), i, |b, &i| {
+            b.iter(|| {
+                let mut config = black_box(SearchConfig::default());
+                config.audio_config.compare = true;
+                config.audio_config.downsample = i;
+                fingerprint(&dir, config);
+            })
+        });
+    }
+
+    g.finish();
+    let _ = fs::remove_dir_all(&dir);
+}
+
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
@@ -21,7 +21,7 @@ pub fn criterion_benchmark(c: &mut Criterion) {
                     config,
                 ));
                 black_box(index.index_dirs());
-                index.process_files();
+                index.process_files(None, None);
             })
         });
     }
@@ -47,7 +47,7 @@ pub fn criterion_benchmark(c: &mut Criterion) {
                     config,
                 ));
                 black_box(index.index_dirs());
-                index.process_files();
+                index.process_files(None, None);
             })
         });
     }
@@ -75,7 +75,7 @@ pub fn criterion_benchmark(c: &mut Criterion) {
                         config,
                     ));
                     black_box(index.index_dirs());
-                    index.process_files();
+                    index.process_files(None, None);
                 })
             },
         );
@@ -1,7 +1,9 @@
 use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
 use deckard::collect_paths;
-use deckard::config::SearchConfig;
+use deckard::config::{ReadOrder, SearchConfig};
 use deckard::index::FileIndex;
+use deckard::progress::{Progress, ProgressCallback};
+use std::sync::Arc;
 
 pub fn criterion_benchmark(c: &mut Criterion) {
     let mut g = c.benchmark_group("process");
@@ -14,7 +16,21 @@ pub fn criterion_benchmark(c: &mut Criterion) {
                 SearchConfig::default(),
             ));
             black_box(index.index_dirs());
-            index.process_files();
+            index.process_files(None, None);
+        })
+    });
+
+    g.bench_function("callbacks", |b| {
+        b.iter(|| {
+            let mut index = black_box(FileIndex::new(
+                black_box(collect_paths(vec!["../test_files/"])),
+                SearchConfig::default(),
+            ));
+            black_box(index.index_dirs());
+            let callback: ProgressCallback = Arc::new(|progress: &Progress| {
+                black_box(progress);
+            });
+            index.process_files(None, Some(callback));
         })
     });
 
@@ -28,7 +44,7 @@ pub fn criterion_benchmark(c: &mut Criterion) {
                 config,
             ));
             black_box(index.index_dirs());
-            index.process_files();
+            index.process_files(None, None);
         })
     });
 
@@ -43,7 +59,7 @@ pub fn criterion_benchmark(c: &mut Criterion) {
                     config,
                 ));
                 black_box(index.index_dirs());
-                index.process_files();
+                index.process_files(None, None);
             })
         });
     }
@@ -59,11 +75,32 @@ pub fn criterion_benchmark(c: &mut Criterion) {
                     config,
                 ));
                 black_box(index.index_dirs());
-                index.process_files();
+                index.process_files(None, None);
             })
         });
     }
 
+    for i in [ReadOrder::Parallel, ReadOrder::Sequential].iter() {
+        g.bench_with_input(
+            BenchmarkId::new("read_order", format!("{:?}", i)),
+            i,
+            |b, &i| {
+                b.iter(|| {
+                    let mut config = SearchConfig::default();
+                    config.hasher_config.full_hash = true;
+                    config.read_order = i;
+
+                    let mut index = black_box(FileIndex::new(
+                        black_box(collect_paths(vec!["../test_files/"])),
+                        config,
+                    ));
+                    black_box(index.index_dirs());
+                    index.process_files(None, None);
+                })
+            },
+        );
+    }
+
     g.bench_function("dont_exist", |b| {
         b.iter(|| {
             let mut index = black_box(FileIndex::new(
@@ -71,7 +108,7 @@ pub fn criterion_benchmark(c: &mut Criterion) {
                 SearchConfig::default(),
             ));
             black_box(index.index_dirs());
-            index.process_files();
+            index.process_files(None, None);
         })
     });
 
@@ -0,0 +1,221 @@
+use crate::config::SearchConfig;
+use crate::error::DeckardError;
+use crate::file::{AudioInfo, FileEntry, ImageInfo};
+use chrono::{DateTime, Local};
+use image_hasher::ImageHash;
+use log::debug;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// Processing results of one file
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct CacheEntry {
+    size: u64,
+    modified: DateTime<Local>,
+    mime_type: Option<String>,
+    hash: Option<String>,
+    full_hash: Option<String>,
+    #[serde(default)]
+    paranoid_hash: Option<String>,
+    #[serde(with = "crate::file::image_hash_base64")]
+    image_hash: Option<ImageHash>,
+    audio_hash: Option<Vec<u32>>,
+    #[serde(default)]
+    image_info: Option<ImageInfo>,
+    #[serde(default)]
+    audio_info: Option<AudioInfo>,
+}
+
+/// Hashes of previous scans, an entry is reused while the size and
+/// modification time of its file are unchanged
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct HashCache {
+    /// Settings the hashes were computed with
+    settings: String,
+    entries: HashMap<PathBuf, CacheEntry>,
+    #[serde(skip)]
+    hits: Arc<AtomicUsize>,
+}
+
+impl HashCache {
+    pub fn new(config: &SearchConfig) -> Self {
+        Self {
+            settings: config.hash_settings(),
+            ..Default::default()
+        }
+    }
+
+    /// Location of the cache next to the configuration files
+    pub fn default_path() -> PathBuf {
+        SearchConfig::get_config_path("cache").with_extension("json")
+    }
+
+    /// Location of the checkpoint of an interrupted scan
+    pub fn checkpoint_path() -> PathBuf {
+        SearchConfig::get_config_path("checkpoint").with_extension("json")
+    }
+
+    /// Add the entries of a cache computed with the same settings,
+    /// replacing the entries of the same files
+    pub fn merge(&mut self, other: HashCache) {
+        if other.settings == self.settings {
+            self.entries.extend(other.entries);
+        }
+    }
+
+    /// Load the cache, starting over when it is missing or was computed with other settings
+    pub fn load<P: AsRef<Path>>(path: P, config: &SearchConfig) -> Self {
+        match read_cache(path.as_ref()) {
+            Ok(cache) if cache.settings == config.hash_settings() => {
+                debug!("loaded {} cached hashes", cache.len());
+                cache
+            }
+            Ok(_) => {
+                debug!("hash settings changed, starting a new cache");
+                Self::new(config)
+            }
+            Err(e) => {
+                debug!("no hash cache loaded: {}", e);
+                Self::new(config)
+            }
+        }
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), DeckardError> {
+        if let Some(parent) = path.as_ref().parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let writer = BufWriter::new(fs::File::create(path)?);
+        serde_json::to_writer(writer, self)?;
+        Ok(())
+    }
+
+    /// Fill in the cached results of an unchanged file, returns false on a miss
+    pub fn apply(&self, file: &mut FileEntry) -> bool {
+        match self.entries.get(&file.path) {
+            Some(entry) if entry.size == file.size && entry.modified == file.modified => {
+                file.mime_type = entry.mime_type.clone();
+                file.hash = entry.hash.clone();
+                file.full_hash = entry.full_hash.clone();
+                file.paranoid_hash = entry.paranoid_hash.clone();
+                file.image_hash = entry.image_hash.clone();
+                file.audio_hash = entry.audio_hash.clone();
+                file.image_info = entry.image_info.clone();
+                file.audio_info = entry.audio_info.clone();
+                file.processed = true;
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Store the results of a processed file
+    pub fn insert(&mut self, file: &FileEntry) {
+        if !file.processed {
+            return;
+        }
+        self.entries.insert(
+            file.path.clone(),
+            CacheEntry {
+                size: file.size,
+                modified: file.modified,
+                mime_type: file.mime_type.clone(),
+                hash: file.hash.clone(),
+                full_hash: file.full_hash.clone(),
+                paranoid_hash: file.paranoid_hash.clone(),
+                image_hash: file.image_hash.clone(),
+                audio_hash: file.audio_hash.clone(),
+                image_info: file.image_info.clone(),
+                audio_info: file.audio_info.clone(),
+            },
+        );
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Number of files served from the cache
+    pub fn hits(&self) -> usize {
+        self.hits.load(Ordering::Relaxed)
+    }
+}
+
+fn read_cache(path: &Path) -> Result<HashCache, DeckardError> {
+    let reader = BufReader::new(fs::File::open(path)?);
+    Ok(serde_json::from_reader(reader)?)
+}
+
+/// Hashes computed with different settings can't be compared
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::collect_paths;
+    use crate::index::FileIndex;
+    use crate::progress::{Progress, ProgressCallback};
+    use crate::test_dir::TestDir;
+    use std::sync::atomic::AtomicBool;
+
+    #[test]
+    fn reuse_unchanged_files() {
+        let config = SearchConfig::default();
+        let dir = TestDir::new("cache");
+        let path = dir.join("cache.json");
+
+        let mut index = FileIndex::new(collect_paths(vec!["../test_files/same_files"]), config);
+        index.cache = Some(HashCache::new(&index.config));
+        index.index_dirs();
+        let first = index.process_files(None, None);
+        assert_eq!(first.cached, 0);
+        index.cache.as_ref().unwrap().save(&path).unwrap();
+
+        let mut index = FileIndex::new(index.dirs.clone(), index.config.clone());
+        index.cache = Some(HashCache::load(&path, &index.config));
+        index.index_dirs();
+        let second = index.process_files(None, None);
+
+        assert_eq!(second.cached, second.files);
+        assert_eq!(index.cache.as_ref().unwrap().hits(), second.files);
+    }
+
+    #[test]
+    fn resume_from_checkpoint() {
+        let config = SearchConfig::default();
+        let dir = TestDir::new("checkpoint");
+        let checkpoint = dir.join("checkpoint.json");
+
+        let cancel = Arc::new(AtomicBool::new(false));
+        let cancelled = cancel.clone();
+        let callback: ProgressCallback = Arc::new(move |_: &Progress| {
+            cancelled.store(true, Ordering::Relaxed);
+        });
+        let mut index = FileIndex::new(collect_paths(vec!["../test_files/same_files"]), config);
+        index.checkpoint = Some(checkpoint.clone());
+        index.index_dirs();
+        index.process_files(Some(cancel), Some(callback));
+        assert!(checkpoint.exists());
+
+        let mut index = FileIndex::new(index.dirs.clone(), index.config.clone());
+        let mut cache = HashCache::new(&index.config);
+        cache.merge(HashCache::load(&checkpoint, &index.config));
+        let resumed = cache.len();
+        index.cache = Some(cache);
+        index.checkpoint = Some(checkpoint.clone());
+        index.index_dirs();
+        let summary = index.process_files(None, None);
+
+        assert!(resumed > 0);
+        assert_eq!(summary.cached, resumed);
+        assert!(!checkpoint.exists());
+    }
+}
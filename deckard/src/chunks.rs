@@ -0,0 +1,175 @@
+use crate::error::DeckardError;
+use crate::hasher::is_cancelled;
+use crate::index::FileIndex;
+use fastcdc::v2020::StreamCDC;
+use log::{debug, warn};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::hash::{Hash, Hasher};
+use std::io::{self, BufReader};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+
+/// Two files sharing a large part of their content-defined chunks
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SimilarFiles {
+    pub a: PathBuf,
+    pub b: PathBuf,
+    /// Percentage of the chunks of the smaller file found in the other
+    pub shared: f64,
+}
+
+/// Hashes of the content-defined chunks of the file, boundaries follow the
+/// content so an insertion only changes the chunks around it
+pub fn chunk_hashes<P: AsRef<Path>>(
+    path: P,
+    avg_size: u32,
+    cancel: &Option<Arc<AtomicBool>>,
+) -> Result<HashSet<u64>, DeckardError> {
+    let reader = BufReader::new(File::open(path)?);
+    let chunker = StreamCDC::new(reader, avg_size / 4, avg_size, avg_size * 4);
+
+    let mut hashes = HashSet::new();
+    for chunk in chunker {
+        if is_cancelled(cancel) {
+            return Err(DeckardError::Cancelled);
+        }
+        let chunk = chunk.map_err(|e| io::Error::other(e.to_string()))?;
+        let mut hasher = DefaultHasher::new();
+        chunk.data.hash(&mut hasher);
+        hashes.insert(hasher.finish());
+    }
+    Ok(hashes)
+}
+
+/// Find pairs of files sharing at least the configured part of their chunks,
+/// like re-saved archives or grown log files. Exact duplicates are left out.
+pub fn find_similar_files(
+    index: &FileIndex,
+    cancel: &Option<Arc<AtomicBool>>,
+) -> Vec<SimilarFiles> {
+    let config = &index.config.chunk_config;
+    let candidates: Vec<&PathBuf> = index
+        .files
+        .values()
+        .filter(|f| f.size >= config.avg_size as u64)
+        .map(|f| &f.path)
+        .collect();
+
+    let chunked: Vec<(&PathBuf, HashSet<u64>)> = candidates
+        .par_iter()
+        .filter_map(|path| match chunk_hashes(path, config.avg_size, cancel) {
+            Ok(hashes) => Some((*path, hashes)),
+            Err(e) => {
+                warn!("failed chunking {}: {}", path.to_string_lossy(), e);
+                None
+            }
+        })
+        .collect();
+    if is_cancelled(cancel) {
+        return Vec::new();
+    }
+
+    // count the chunks each pair of files has in common
+    let mut owners: HashMap<u64, Vec<usize>> = HashMap::new();
+    for (i, (_, hashes)) in chunked.iter().enumerate() {
+        for hash in hashes {
+            owners.entry(*hash).or_default().push(i);
+        }
+    }
+    let mut shared: HashMap<(usize, usize), usize> = HashMap::new();
+    for files in owners.values().filter(|o| o.len() > 1) {
+        for (n, a) in files.iter().enumerate() {
+            for b in &files[n + 1..] {
+                *shared.entry((*a, *b)).or_default() += 1;
+            }
+        }
+    }
+
+    let mut similar: Vec<SimilarFiles> = shared
+        .into_iter()
+        .filter_map(|((a, b), count)| {
+            let (path_a, hashes_a) = &chunked[a];
+            let (path_b, hashes_b) = &chunked[b];
+            let exact = index
+                .duplicates
+                .get(*path_a)
+                .is_some_and(|d| d.contains(*path_b));
+            let percent = 100.0 * count as f64 / hashes_a.len().min(hashes_b.len()) as f64;
+            (!exact && percent >= config.threshold).then(|| {
+                debug!(
+                    "{} and {} share {:.1}% of their chunks",
+                    path_a.to_string_lossy(),
+                    path_b.to_string_lossy(),
+                    percent
+                );
+                let (a, b) = if path_a < path_b {
+                    (path_a, path_b)
+                } else {
+                    (path_b, path_a)
+                };
+                SimilarFiles {
+                    a: (*a).clone(),
+                    b: (*b).clone(),
+                    shared: percent,
+                }
+            })
+        })
+        .collect();
+    similar.sort_by(|x, y| {
+        y.shared
+            .total_cmp(&x.shared)
+            .then_with(|| x.a.cmp(&y.a))
+            .then_with(|| x.b.cmp(&y.b))
+    });
+    similar
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::collect_paths;
+    use crate::config::SearchConfig;
+    use crate::test_dir::TestDir;
+    use std::fs;
+
+    /// Deterministic bytes without repeating patterns
+    fn noise(len: usize, seed: u64) -> Vec<u8> {
+        let mut state = seed;
+        (0..len)
+            .map(|_| {
+                state = state
+                    .wrapping_mul(6364136223846793005)
+                    .wrapping_add(1442695040888963407);
+                (state >> 33) as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn appended_files() {
+        let root = TestDir::new("chunks");
+
+        let log = noise(512 * 1024, 1);
+        let mut grown = log.clone();
+        grown.extend(noise(64 * 1024, 2));
+        fs::write(root.join("log"), &log).unwrap();
+        fs::write(root.join("grown"), &grown).unwrap();
+        fs::write(root.join("other"), noise(512 * 1024, 3)).unwrap();
+
+        let mut config = SearchConfig::default();
+        config.chunk_config.compare = true;
+        let mut index = FileIndex::new(collect_paths(vec![&root]), config);
+        index.index_dirs();
+        let similar = find_similar_files(&index, &None);
+
+        assert_eq!(similar.len(), 1);
+        assert!(similar[0].a.ends_with("grown"));
+        assert!(similar[0].b.ends_with("log"));
+        assert!(similar[0].shared > 90.0);
+    }
+}
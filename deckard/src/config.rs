@@ -1,17 +1,67 @@
 use image_hasher::{FilterType, HashAlg};
-use log::{debug, error};
+use log::{debug, error, warn};
+use rusty_chromaprint::Configuration;
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use std::collections::BTreeMap;
+use std::env;
+use std::path::{Path, PathBuf};
+
+use crate::error::DeckardError;
+use crate::platform;
+use crate::policy::KeepPolicy;
+pub use deckard_core::Transitivity;
 
 #[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+#[serde(default)]
 pub struct HasherConfig {
     pub full_hash: bool,
     pub hash_algorithm: HashAlgorithm,
     pub size: u64,
     pub splits: u64,
+    /// Files of at least this size are fully hashed in slices on all threads,
+    /// 0 hashes every file on a single thread
+    pub parallel_size: u64,
+    /// Fully hash every file a second time with this algorithm,
+    /// files only match when both full hashes are equal
+    pub paranoid_algorithm: Option<HashAlgorithm>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+/// Hasher settings overriding the defaults for some files, like more quick
+/// hash splits for disk images or always fully hashing photos
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct HashRule {
+    /// Extension pattern like `*.iso`, or MIME class like `image/*`
+    pub pattern: String,
+    pub full_hash: Option<bool>,
+    pub size: Option<u64>,
+    pub splits: Option<u64>,
+}
+
+impl HashRule {
+    pub fn matches(&self, path: &Path, mime_type: Option<&str>) -> bool {
+        if let Some(extension) = self.pattern.strip_prefix("*.") {
+            return path
+                .extension()
+                .is_some_and(|e| e.to_string_lossy().eq_ignore_ascii_case(extension));
+        }
+        let class = self.pattern.trim_end_matches('*');
+        !class.is_empty() && mime_type.is_some_and(|m| m.starts_with(class))
+    }
+
+    fn apply(&self, config: &mut HasherConfig) {
+        if let Some(full_hash) = self.full_hash {
+            config.full_hash = full_hash;
+        }
+        if let Some(size) = self.size {
+            config.size = size;
+        }
+        if let Some(splits) = self.splits {
+            config.splits = splits;
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
 #[serde(rename_all = "lowercase")]
 pub enum HashAlgorithm {
     MD5,
@@ -20,6 +70,20 @@ pub enum HashAlgorithm {
     SHA512,
 }
 
+impl HasherConfig {
+    /// Whether files are fully hashed, a paranoid algorithm always needs the
+    /// full hash besides its own
+    pub fn hashes_fully(&self) -> bool {
+        self.full_hash || self.paranoid_algorithm.is_some()
+    }
+
+    /// Whether a file of the size is fully hashed in parallel slices, its
+    /// full hash then differs from the digest of checksum tools
+    pub fn hashes_in_slices(&self, size: u64) -> bool {
+        self.parallel_size > 0 && size >= self.parallel_size
+    }
+}
+
 impl Default for HasherConfig {
     fn default() -> Self {
         Self {
@@ -27,11 +91,14 @@ impl Default for HasherConfig {
             hash_algorithm: HashAlgorithm::SHA1,
             size: 1024,
             splits: 8,
+            parallel_size: 1024 * 1024 * 1024,
+            paranoid_algorithm: None,
         }
     }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+#[serde(default)]
 pub struct ImageConfig {
     pub compare: bool,
     pub hash_algorithm: ImageHashAlgorithm,
@@ -99,10 +166,40 @@ impl Default for ImageConfig {
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+#[serde(default)]
 pub struct AudioConfig {
     pub compare: bool,
     pub segments_limit: u64,
     pub threshold: f64,
+    /// Seconds decoded from the start of each track, 0 decodes whole tracks
+    pub max_duration: u64,
+    pub preset: ChromaPreset,
+    /// Average this many samples into one before fingerprinting,
+    /// higher is faster but less accurate
+    pub downsample: u32,
+}
+
+/// Chromaprint configuration presets
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ChromaPreset {
+    Test1,
+    Test2,
+    Test3,
+    Test4,
+    Test5,
+}
+
+impl ChromaPreset {
+    pub fn to_configuration(&self) -> Configuration {
+        match self {
+            ChromaPreset::Test1 => Configuration::preset_test1(),
+            ChromaPreset::Test2 => Configuration::preset_test2(),
+            ChromaPreset::Test3 => Configuration::preset_test3(),
+            ChromaPreset::Test4 => Configuration::preset_test4(),
+            ChromaPreset::Test5 => Configuration::preset_test5(),
+        }
+    }
 }
 
 impl Default for AudioConfig {
@@ -111,20 +208,315 @@ impl Default for AudioConfig {
             compare: false,
             segments_limit: 2,
             threshold: 5.0,
+            max_duration: 120,
+            preset: ChromaPreset::Test1,
+            downsample: 1,
+        }
+    }
+}
+
+/// Experimental near-duplicate detection with content-defined chunking
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+#[serde(default)]
+pub struct ChunkConfig {
+    pub compare: bool,
+    /// Average chunk size in bytes, smaller files are not compared
+    pub avg_size: u32,
+    /// Percentage of shared chunks to report two files
+    pub threshold: f64,
+}
+
+impl Default for ChunkConfig {
+    fn default() -> Self {
+        Self {
+            compare: false,
+            avg_size: 16 * 1024,
+            threshold: 50.0,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct ActionConfig {
+    /// Only preview what would be removed
+    pub dry_run: bool,
+    /// Keep directories left empty after removing files
+    pub no_remove_dirs: bool,
+    /// Copy kept when removing or marking duplicates automatically
+    pub keep_policy: KeepPolicy,
+    /// Copies under these paths are kept before the policy is applied,
+    /// the first listed path wins
+    pub prefer_paths: Vec<PathBuf>,
+    /// Compare the removed copies byte for byte with the kept file before acting
+    pub verify_before_action: bool,
+    /// Never modify the scanned files, every removal and deduplication is refused
+    pub read_only: bool,
+}
+
+/// Access to the object storage scanned for `s3://bucket/prefix` paths
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct RemoteConfig {
+    /// Endpoint of an S3-compatible service like MinIO, AWS when not set
+    pub s3_endpoint: Option<String>,
+    pub s3_region: String,
+    /// Credentials are read from the environment and the AWS profile when not set
+    pub s3_access_key: Option<String>,
+    pub s3_secret_key: Option<String>,
+    /// Part size the objects were uploaded with, to match multipart ETags
+    pub multipart_part_size: u64,
+}
+
+impl Default for RemoteConfig {
+    fn default() -> Self {
+        Self {
+            s3_endpoint: None,
+            s3_region: "us-east-1".to_string(),
+            s3_access_key: None,
+            s3_secret_key: None,
+            // the default of the AWS CLI
+            multipart_part_size: 8 * 1024 * 1024,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ReadOrder {
+    /// Read sequentially when the files are on a rotational disk
+    Auto,
+    /// Read files in parallel in any order
+    Parallel,
+    /// Read files one by one in on-disk order
+    Sequential,
+}
+
+/// Which pairs of files are compared when looking for duplicates
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ComparisonStrategy {
+    /// Compare every file with every other file, slow for large scans
+    Pairwise,
+    /// Only compare files with the same size and quick hash, finds exact
+    /// copies only
+    Buckets,
+    /// Compare files within their size and hash bucket, and every image or
+    /// audio file with the others of its kind when comparing those. Finds
+    /// the same matches as pairwise.
+    #[default]
+    Hybrid,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum CompareScope {
+    /// Compare every file with every other file
+    All,
+    /// Only compare files in the same directory
+    Directory,
+    /// Only compare files under the same top-level subdirectory of a scan root
+    Subdirectory,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Isolate {
+    /// Report all duplicates
+    #[default]
+    Off,
+    /// Skip copies living in the same directory
+    Directory,
+    /// Skip copies found under the same scan root
+    Root,
+}
+
+/// Order of the duplicate groups in every report, ties are broken by path
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum GroupOrder {
+    /// Groups freeing the most space first
+    #[default]
+    Reclaimable,
+    /// Largest groups by total size first
+    Size,
+    /// Groups with the most copies first
+    Count,
+    /// By the first path of the group
+    Path,
+    /// Least certain perceptual matches first, for reviewing them
+    Confidence,
+}
+
+/// Column of the file tables in the TUI, besides the path
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum TableColumn {
+    Date,
+    Size,
+    /// Number of duplicates of the file
+    Clones,
+    Mime,
+    Extension,
+    /// Directory the file is in
+    Parent,
+}
+
+impl TableColumn {
+    pub const ALL: [TableColumn; 6] = [
+        TableColumn::Date,
+        TableColumn::Size,
+        TableColumn::Clones,
+        TableColumn::Mime,
+        TableColumn::Extension,
+        TableColumn::Parent,
+    ];
+}
+
+/// Layout of the TUI
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct TuiConfig {
+    /// Columns shown after the path, in this order
+    pub columns: Vec<TableColumn>,
+    /// Percentage of the width taken by the files, the clones and the info
+    /// get the rest
+    pub files_width: u16,
+    /// Percentage of the height taken by the tables above the marked files
+    /// and the info
+    pub tables_height: u16,
+    /// Application opening files by MIME type, like `"image/*" = "feh"`,
+    /// the system default opens the rest
+    pub openers: BTreeMap<String, String>,
+}
+
+impl TuiConfig {
+    /// Opener of the most specific MIME type or prefix matching
+    pub fn opener_for(&self, mime_type: &str) -> Option<&str> {
+        self.openers
+            .iter()
+            .map(|(mime, app)| (mime.trim_end_matches('*'), app))
+            .filter(|(mime, _)| mime_type.starts_with(mime))
+            .max_by_key(|(mime, _)| mime.len())
+            .map(|(_, app)| app.as_str())
+    }
+}
+
+impl Default for TuiConfig {
+    fn default() -> Self {
+        Self {
+            columns: vec![TableColumn::Date, TableColumn::Size],
+            files_width: 50,
+            tables_height: 60,
+            openers: BTreeMap::new(),
+        }
+    }
+}
+
+/// Kind of media a scan can be restricted to
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum MediaClass {
+    Images,
+    Audio,
+    Video,
+    Documents,
+}
+
+/// Document types besides plain text
+const DOCUMENT_MIME_TYPES: [&str; 9] = [
+    "application/pdf",
+    "application/rtf",
+    "application/epub+zip",
+    "application/msword",
+    "application/vnd.ms-excel",
+    "application/vnd.ms-powerpoint",
+    "application/vnd.openxmlformats-officedocument",
+    "application/vnd.oasis.opendocument",
+    "application/x-latex",
+];
+
+impl MediaClass {
+    /// Whether a file of the MIME type belongs to the class
+    pub fn matches(&self, mime: &str) -> bool {
+        match self {
+            MediaClass::Images => mime.starts_with("image/"),
+            MediaClass::Audio => mime.starts_with("audio/"),
+            MediaClass::Video => mime.starts_with("video/"),
+            MediaClass::Documents => {
+                mime.starts_with("text/") || DOCUMENT_MIME_TYPES.iter().any(|d| mime.starts_with(d))
+            }
         }
     }
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum NameMatch {
+    /// Match files regardless of their names
+    #[default]
+    Any,
+    /// Only match files sharing the file name, like backup copies
+    Same,
+    /// Only match files with different names, like renamed copies
+    Different,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
 pub struct SearchConfig {
     pub skip_empty: bool,
     pub skip_hidden: bool,
+    /// Don't descend into directories mounted from other file systems
+    pub same_file_system: bool,
+    pub disk_usage_mode: bool,
     pub threads: usize,
+    pub adaptive_threads: bool,
+    pub read_order: ReadOrder,
+    pub comparison_strategy: ComparisonStrategy,
+    pub compare_scope: CompareScope,
+    pub isolate: Isolate,
+    pub name_match: NameMatch,
+    /// How chains of similar files are grouped
+    pub transitivity: Transitivity,
+    /// With transitive grouping, perceptual matches below this confidence
+    /// don't chain and must match every file of their group directly
+    pub chain_confidence: u8,
+    pub group_order: GroupOrder,
+    /// Only report groups whose confidence falls in this range
+    pub min_confidence: u8,
+    pub max_confidence: u8,
+    /// Only scan files of this kind
+    pub media: Option<MediaClass>,
+    /// Also look for files that are the start of a larger file
+    pub partial: bool,
+    /// Scan at low priority with the read bandwidth capped to `io_limit`
+    pub nice_io: bool,
+    /// Keep the indexed files on disk and process them one size at a time,
+    /// only finds exact duplicates. Needs the `sqlite` feature
+    pub low_memory: bool,
+    /// Read bandwidth in bytes per second when scanning with `nice_io`
+    pub io_limit: u64,
+    pub cache: bool,
+    /// Store the hashes in the `user.deckard.hash` extended attribute of each
+    /// file and reuse them while the file is unchanged
+    pub xattr_cache: bool,
+    /// Send a desktop notification when comparing finishes, needs the
+    /// `notify` feature
+    pub notify: bool,
+    /// Shortest time in milliseconds between two progress reports
+    pub progress_interval: u64,
     pub include_filter: Option<String>,
     pub exclude_filter: Option<String>,
     pub hasher_config: HasherConfig,
     pub image_config: ImageConfig,
     pub audio_config: AudioConfig,
+    pub chunk_config: ChunkConfig,
+    pub action_config: ActionConfig,
+    pub remote: RemoteConfig,
+    pub tui: TuiConfig,
+    /// Hasher overrides, the first rule matching a file applies
+    pub hash_rules: Vec<HashRule>,
 }
 
 impl Default for SearchConfig {
@@ -132,35 +524,76 @@ impl Default for SearchConfig {
         Self {
             skip_empty: false,
             skip_hidden: false,
+            same_file_system: false,
+            disk_usage_mode: false,
             threads: 0,
+            adaptive_threads: false,
+            read_order: ReadOrder::Auto,
+            comparison_strategy: ComparisonStrategy::Hybrid,
+            compare_scope: CompareScope::All,
+            isolate: Isolate::Off,
+            name_match: NameMatch::Any,
+            transitivity: Transitivity::Transitive,
+            chain_confidence: 0,
+            group_order: GroupOrder::Reclaimable,
+            min_confidence: 0,
+            max_confidence: 100,
+            media: None,
+            partial: false,
+            nice_io: false,
+            low_memory: false,
+            io_limit: 32 * 1024 * 1024,
+            cache: false,
+            xattr_cache: false,
+            notify: false,
+            progress_interval: 100,
             include_filter: None,
             exclude_filter: None,
             hasher_config: HasherConfig::default(),
             image_config: ImageConfig::default(),
             audio_config: AudioConfig::default(),
+            chunk_config: ChunkConfig::default(),
+            action_config: ActionConfig::default(),
+            remote: RemoteConfig::default(),
+            tui: TuiConfig::default(),
+            hash_rules: Vec::new(),
         }
     }
 }
 
 impl SearchConfig {
+    /// Settings the hashes are computed with, files processed with different
+    /// settings can't be compared
+    pub fn hash_settings(&self) -> String {
+        serde_json::to_string(&(
+            self.hasher_config,
+            self.image_config,
+            self.audio_config,
+            &self.hash_rules,
+        ))
+        .unwrap_or_default()
+    }
+
+    /// Hasher settings of a file after applying the first matching rule
+    pub fn hasher_config_for(&self, path: &Path, mime_type: Option<&str>) -> HasherConfig {
+        let mut config = self.hasher_config;
+        if let Some(rule) = self.hash_rules.iter().find(|r| r.matches(path, mime_type)) {
+            rule.apply(&mut config);
+        }
+        config
+    }
+
     pub fn load(config_name: &str) -> Self {
         debug!(
             "load config path {:?}",
             confy::get_configuration_file_path("deckard", config_name).unwrap()
         );
+        // missing values take their defaults, a file that still fails to
+        // parse is left alone for the user to fix
         match confy::load("deckard", config_name) {
             Ok(c) => c,
             Err(e) => {
-                match &e {
-                    confy::ConfyError::BadTomlData(_) => {
-                        std::fs::remove_file(
-                            confy::get_configuration_file_path("deckard", config_name).unwrap(),
-                        )
-                        .unwrap();
-                    }
-                    _ => {}
-                }
-                error!("failed loading config {:?}", e);
+                warn!("failed loading config, using the defaults: {}", e);
                 Self::default()
             }
         }
@@ -171,10 +604,57 @@ impl SearchConfig {
             "save config path {:?}",
             confy::get_configuration_file_path("deckard", config_name).unwrap()
         );
-        confy::store("deckard", config_name, &self).unwrap();
+        confy::store("deckard", config_name, self).unwrap();
     }
 
     pub fn get_config_path(config_name: &str) -> PathBuf {
         confy::get_configuration_file_path("deckard", config_name).unwrap()
     }
+
+    /// Open the configuration in `$VISUAL` or `$EDITOR` and wait for the
+    /// editor to exit, without either use the platform opener
+    pub fn edit_config(config_name: &str) -> Result<(), DeckardError> {
+        let config_path = Self::get_config_path(config_name);
+        if !config_path.exists() {
+            Self::load(config_name).save(config_name);
+        }
+
+        let editor = ["VISUAL", "EDITOR"]
+            .iter()
+            .filter_map(|var| env::var(var).ok())
+            .find(|editor| !editor.trim().is_empty());
+        match editor {
+            Some(editor) => {
+                debug!("editing {:?} with {}", config_path, editor);
+                // the variable may carry arguments, like `code --wait`
+                let status =
+                    platform::shell(&format!("{} {}", editor, platform::quote(&config_path)))
+                        .status()?;
+                if !status.success() {
+                    error!("{} exited with {}", editor, status);
+                }
+            }
+            None => open::that(&config_path)?,
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn load_partial_config() {
+        let config: SearchConfig = serde_json::from_str(
+            r#"{"skip_hidden": true, "image_config": {"threshold": 20}, "tui": {}}"#,
+        )
+        .unwrap();
+
+        assert!(config.skip_hidden);
+        assert_eq!(config.image_config.threshold, 20);
+        assert_eq!(config.image_config.size, ImageConfig::default().size);
+        assert_eq!(config.max_confidence, 100);
+        assert_eq!(config.tui.files_width, 50);
+    }
 }
@@ -0,0 +1,371 @@
+use crate::cache::HashCache;
+use crate::config::SearchConfig;
+use crate::error::DeckardError;
+use crate::index::FileIndex;
+use chrono::{DateTime, Local};
+use log::{debug, error, info};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write as _;
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Query sent by a client, one JSON object per line
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum Request {
+    Duplicates,
+    Stats,
+    Rescan,
+}
+
+/// Answer to a request, one JSON object per line
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "response", rename_all = "snake_case")]
+pub enum Response {
+    Duplicates {
+        groups: Vec<Vec<PathBuf>>,
+    },
+    Stats {
+        files: usize,
+        duplicates: usize,
+        reclaimable: u64,
+        errors: usize,
+        scanned: Option<DateTime<Local>>,
+    },
+    Rescanned {
+        changed: bool,
+    },
+    Error {
+        message: String,
+    },
+}
+
+/// Keeps an index of the paths warm and answers queries about it
+pub struct Daemon {
+    dirs: HashSet<PathBuf>,
+    config: SearchConfig,
+    index: Mutex<FileIndex>,
+    scanned: Mutex<Option<DateTime<Local>>>,
+    /// Number of scans that found changes and how long the last one took
+    scans: Mutex<(u64, Duration)>,
+    cache: Mutex<HashCache>,
+    // one scan at a time, between the watcher and rescan requests
+    scanning: Mutex<()>,
+}
+
+impl Daemon {
+    pub fn new(dirs: HashSet<PathBuf>, config: SearchConfig) -> Self {
+        Self {
+            index: Mutex::new(FileIndex::new(dirs.clone(), config.clone())),
+            scanned: Mutex::new(None),
+            scans: Mutex::new((0, Duration::ZERO)),
+            cache: Mutex::new(HashCache::new(&config)),
+            scanning: Mutex::new(()),
+            dirs,
+            config,
+        }
+    }
+
+    /// Scan the paths again when any file was added, removed or modified,
+    /// unchanged files are served from the in-memory hash cache
+    pub fn rescan(&self) -> bool {
+        let _scanning = self.scanning.lock().unwrap();
+
+        let start = Instant::now();
+        let mut index = FileIndex::new(self.dirs.clone(), self.config.clone());
+        index.index_dirs();
+        let scanned = self.scanned.lock().unwrap().is_some();
+        if scanned && snapshot(&index) == snapshot(&self.index.lock().unwrap()) {
+            debug!("no changes in the watched paths");
+            return false;
+        }
+
+        index.cache = Some(self.cache.lock().unwrap().clone());
+        index.process_files(None, None);
+        index.find_duplicates(None, None);
+        if let Some(cache) = index.cache.take() {
+            *self.cache.lock().unwrap() = cache;
+        }
+        info!(
+            "indexed {} files, {} with duplicates",
+            index.files_len(),
+            index.duplicates_len()
+        );
+
+        *self.index.lock().unwrap() = index;
+        *self.scanned.lock().unwrap() = Some(Local::now());
+        let mut scans = self.scans.lock().unwrap();
+        *scans = (scans.0 + 1, start.elapsed());
+        true
+    }
+
+    /// State of the index in the Prometheus text format
+    pub fn metrics(&self) -> String {
+        let (scans, duration) = *self.scans.lock().unwrap();
+        let scanned = self.scanned.lock().unwrap().map_or(0, |t| t.timestamp());
+        let index = self.index.lock().unwrap();
+        let metrics: [(&str, &str, &str, String); 8] = [
+            (
+                "deckard_scans_total",
+                "counter",
+                "Scans that found changes in the watched paths",
+                scans.to_string(),
+            ),
+            (
+                "deckard_scan_duration_seconds",
+                "gauge",
+                "Duration of the last scan",
+                duration.as_secs_f64().to_string(),
+            ),
+            (
+                "deckard_last_scan_timestamp_seconds",
+                "gauge",
+                "Unix time the last scan finished",
+                scanned.to_string(),
+            ),
+            (
+                "deckard_files_indexed",
+                "gauge",
+                "Files in the index",
+                index.files_len().to_string(),
+            ),
+            (
+                "deckard_duplicate_files",
+                "gauge",
+                "Files with at least one duplicate",
+                index.duplicates_len().to_string(),
+            ),
+            (
+                "deckard_duplicate_groups",
+                "gauge",
+                "Groups of identical files",
+                index.duplicate_groups().len().to_string(),
+            ),
+            (
+                "deckard_reclaimable_bytes",
+                "gauge",
+                "Bytes freed by keeping one file of every group",
+                index.reclaimable_size().to_string(),
+            ),
+            (
+                "deckard_errors",
+                "gauge",
+                "Files that could not be read",
+                index.errors_len().to_string(),
+            ),
+        ];
+
+        let mut text = String::new();
+        for (name, kind, help, value) in metrics {
+            let _ = writeln!(text, "# HELP {} {}", name, help);
+            let _ = writeln!(text, "# TYPE {} {}", name, kind);
+            let _ = writeln!(text, "{} {}", name, value);
+        }
+        text
+    }
+
+    /// Serve the metrics over HTTP on `addr` from a background thread,
+    /// returns the bound address
+    pub fn serve_metrics(self: Arc<Self>, addr: &str) -> Result<SocketAddr, DeckardError> {
+        let listener = TcpListener::bind(addr)?;
+        let local = listener.local_addr()?;
+        info!("serving metrics on http://{}/metrics", local);
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                match stream {
+                    Ok(stream) => {
+                        if let Err(e) = self.answer_metrics(stream) {
+                            debug!("failed answering metrics request: {}", e);
+                        }
+                    }
+                    Err(e) => error!("failed accepting connection: {}", e),
+                }
+            }
+        });
+        Ok(local)
+    }
+
+    fn answer_metrics(&self, mut stream: TcpStream) -> Result<(), DeckardError> {
+        let mut request = String::new();
+        let mut reader = BufReader::new(&stream);
+        reader.read_line(&mut request)?;
+        // read the headers, closing with unread data would reset the connection
+        let mut header = String::new();
+        while reader.read_line(&mut header)? > 2 {
+            header.clear();
+        }
+        let path = request.split_whitespace().nth(1).unwrap_or_default();
+        let (status, body) = match path {
+            "/metrics" => ("200 OK", self.metrics()),
+            _ => ("404 Not Found", String::new()),
+        };
+        write!(
+            stream,
+            "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            status,
+            body.len(),
+            body
+        )?;
+        Ok(())
+    }
+
+    pub fn handle(&self, request: Request) -> Response {
+        match request {
+            Request::Duplicates => Response::Duplicates {
+                groups: self.index.lock().unwrap().duplicate_groups(),
+            },
+            Request::Stats => {
+                let index = self.index.lock().unwrap();
+                Response::Stats {
+                    files: index.files_len(),
+                    duplicates: index.duplicates_len(),
+                    reclaimable: index.reclaimable_size(),
+                    errors: index.errors_len(),
+                    scanned: *self.scanned.lock().unwrap(),
+                }
+            }
+            Request::Rescan => Response::Rescanned {
+                changed: self.rescan(),
+            },
+        }
+    }
+
+    /// Scan, then answer requests on the socket while checking the paths
+    /// for changes every `interval`
+    pub fn serve<P: AsRef<Path>>(
+        self: Arc<Self>,
+        socket: P,
+        interval: Duration,
+    ) -> Result<(), DeckardError> {
+        self.rescan();
+
+        let watcher = self.clone();
+        thread::spawn(move || loop {
+            thread::sleep(interval);
+            watcher.rescan();
+        });
+
+        // a socket left behind by a previous run would fail the bind
+        let _ = fs::remove_file(&socket);
+        let listener = UnixListener::bind(&socket)?;
+        info!("listening on {}", socket.as_ref().to_string_lossy());
+
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    let daemon = self.clone();
+                    thread::spawn(move || {
+                        if let Err(e) = daemon.answer(stream) {
+                            debug!("client disconnected: {}", e);
+                        }
+                    });
+                }
+                Err(e) => error!("failed accepting connection: {}", e),
+            }
+        }
+        Ok(())
+    }
+
+    fn answer(&self, stream: UnixStream) -> Result<(), DeckardError> {
+        let mut writer = stream.try_clone()?;
+        for line in BufReader::new(stream).lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let response = match serde_json::from_str(&line) {
+                Ok(request) => self.handle(request),
+                Err(e) => Response::Error {
+                    message: e.to_string(),
+                },
+            };
+            serde_json::to_writer(&mut writer, &response)?;
+            writer.write_all(b"\n")?;
+        }
+        Ok(())
+    }
+}
+
+/// Size and modification time of every indexed file
+fn snapshot(index: &FileIndex) -> HashMap<&PathBuf, (u64, DateTime<Local>)> {
+    index
+        .files
+        .iter()
+        .map(|(path, file)| (path, (file.size, file.modified)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::collect_paths;
+
+    #[test]
+    fn answer_queries() {
+        let daemon = Daemon::new(
+            collect_paths(vec!["../test_files/same_files"]),
+            SearchConfig::default(),
+        );
+        assert_eq!(
+            daemon.handle(Request::Rescan),
+            Response::Rescanned { changed: true }
+        );
+        assert_eq!(
+            daemon.handle(Request::Rescan),
+            Response::Rescanned { changed: false }
+        );
+
+        let Response::Duplicates { groups } = daemon.handle(Request::Duplicates) else {
+            panic!("expected duplicates");
+        };
+        assert_eq!(groups.len(), 1);
+
+        let Response::Stats {
+            duplicates,
+            scanned,
+            ..
+        } = daemon.handle(Request::Stats)
+        else {
+            panic!("expected stats");
+        };
+        assert_eq!(duplicates, 3);
+        assert!(scanned.is_some());
+    }
+
+    #[test]
+    fn serve_prometheus_metrics() {
+        let daemon = Arc::new(Daemon::new(
+            collect_paths(vec!["../test_files/same_files"]),
+            SearchConfig::default(),
+        ));
+        daemon.rescan();
+        let addr = daemon.clone().serve_metrics("127.0.0.1:0").unwrap();
+
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream
+            .write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .unwrap();
+        let mut response = String::new();
+        std::io::Read::read_to_string(&mut stream, &mut response).unwrap();
+
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.contains("# TYPE deckard_scans_total counter\ndeckard_scans_total 1\n"));
+        assert!(response.contains("\ndeckard_duplicate_files 3\n"));
+        assert!(response.contains("\ndeckard_duplicate_groups 1\n"));
+    }
+
+    #[test]
+    fn parse_requests() {
+        let request: Request = serde_json::from_str(r#"{"command":"stats"}"#).unwrap();
+        assert_eq!(request, Request::Stats);
+        let response = serde_json::to_string(&Response::Rescanned { changed: false }).unwrap();
+        assert_eq!(response, r#"{"response":"rescanned","changed":false}"#);
+    }
+}
@@ -0,0 +1,24 @@
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum DeckardError {
+    #[error("{0}")]
+    Io(#[from] std::io::Error),
+    #[error("{0}")]
+    Audio(String),
+    #[error("{0}")]
+    Json(#[from] serde_json::Error),
+    #[cfg(feature = "sqlite")]
+    #[error("{0}")]
+    Sqlite(#[from] rusqlite::Error),
+    #[error("scan cancelled")]
+    Cancelled,
+    #[error("file changed during the scan")]
+    Changed,
+    #[error("{0}")]
+    Telemetry(String),
+    #[error("{0}")]
+    Remote(String),
+    #[error("invalid pattern: {0}")]
+    Pattern(String),
+}
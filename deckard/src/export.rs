@@ -0,0 +1,239 @@
+use crate::error::DeckardError;
+use crate::file::FileEntry;
+use crate::hasher;
+use crate::index::FileIndex;
+#[cfg(feature = "sqlite")]
+use chrono::Local;
+use log::warn;
+use rayon::prelude::*;
+#[cfg(feature = "sqlite")]
+use rusqlite::{params, Connection};
+use std::fs;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+#[cfg(feature = "sqlite")]
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS runs (
+    id INTEGER PRIMARY KEY,
+    started TEXT NOT NULL,
+    roots TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS files (
+    run_id INTEGER NOT NULL REFERENCES runs(id),
+    path TEXT NOT NULL,
+    name TEXT NOT NULL,
+    extension TEXT,
+    size INTEGER NOT NULL,
+    modified TEXT NOT NULL,
+    mime_type TEXT,
+    hash TEXT,
+    full_hash TEXT,
+    PRIMARY KEY (run_id, path)
+);
+CREATE TABLE IF NOT EXISTS duplicates (
+    run_id INTEGER NOT NULL REFERENCES runs(id),
+    path TEXT NOT NULL,
+    copy TEXT NOT NULL,
+    PRIMARY KEY (run_id, path, copy)
+);
+CREATE INDEX IF NOT EXISTS files_hash ON files(hash);
+";
+
+/// Append the scan results to a SQLite database as a new run,
+/// returns the id of the run
+#[cfg(feature = "sqlite")]
+pub fn sqlite<P: AsRef<Path>>(index: &FileIndex, path: P) -> Result<i64, DeckardError> {
+    let mut db = Connection::open(path)?;
+    db.execute_batch(SCHEMA)?;
+
+    let mut roots: Vec<String> = index
+        .dirs
+        .iter()
+        .map(|d| d.to_string_lossy().to_string())
+        .collect();
+    roots.sort();
+
+    let tx = db.transaction()?;
+    tx.execute(
+        "INSERT INTO runs (started, roots) VALUES (?1, ?2)",
+        params![Local::now().to_rfc3339(), serde_json::to_string(&roots)?],
+    )?;
+    let run = tx.last_insert_rowid();
+    {
+        let mut insert = tx.prepare(
+            "INSERT INTO files (run_id, path, name, extension, size, modified, mime_type, hash, full_hash)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+        )?;
+        for file in index.files.values() {
+            insert.execute(params![
+                run,
+                file.path.to_string_lossy(),
+                file.name,
+                file.extension,
+                file.size as i64,
+                file.modified.to_rfc3339(),
+                file.mime_type,
+                file.hash,
+                file.full_hash,
+            ])?;
+        }
+
+        let mut insert =
+            tx.prepare("INSERT INTO duplicates (run_id, path, copy) VALUES (?1, ?2, ?3)")?;
+        for (file, copies) in &index.duplicates {
+            for copy in copies {
+                insert.execute(params![run, file.to_string_lossy(), copy.to_string_lossy()])?;
+            }
+        }
+    }
+    tx.commit()?;
+
+    Ok(run)
+}
+
+/// Write the full hashes as `<hash>  <path>` lines, the format of sha256sum
+/// and the other coreutils checksum tools. Returns the number of files written,
+/// files without a full hash are left out.
+///
+/// Files hashed in parallel slices are hashed again as a whole, since their
+/// full hash is not the digest checksum tools compute. Those failing to hash
+/// again are left out with a warning.
+pub fn checksums<P: AsRef<Path>>(index: &FileIndex, path: P) -> Result<usize, DeckardError> {
+    let hashed: Vec<&FileEntry> = index
+        .files
+        .values()
+        .filter(|f| f.full_hash.is_some())
+        .collect();
+    let mut files: Vec<(&PathBuf, String)> = hashed
+        .par_iter()
+        .filter_map(|f| {
+            let config = index
+                .config
+                .hasher_config_for(&f.path, f.mime_type.as_deref());
+            if !config.hashes_in_slices(f.size) {
+                return f.full_hash.clone().map(|hash| (&f.path, hash));
+            }
+            match hasher::get_full_hash(&config.hash_algorithm, &f.path, &None) {
+                Ok(hash) => Some((&f.path, hash)),
+                Err(e) => {
+                    warn!("leaving out {}: {}", f.path.to_string_lossy(), e);
+                    None
+                }
+            }
+        })
+        .collect();
+    files.sort();
+
+    let mut writer = BufWriter::new(fs::File::create(path)?);
+    for (path, hash) in &files {
+        let path = path.to_string_lossy();
+        // coreutils escapes these and marks the line with a leading backslash
+        if path.contains(['\\', '\n', '\r']) {
+            let escaped = path
+                .replace('\\', "\\\\")
+                .replace('\n', "\\n")
+                .replace('\r', "\\r");
+            writeln!(writer, "\\{}  {}", hash, escaped)?;
+        } else {
+            writeln!(writer, "{}  {}", hash, path)?;
+        }
+    }
+    writer.flush()?;
+    Ok(files.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::collect_paths;
+    use crate::config::SearchConfig;
+    use crate::test_dir::TestDir;
+
+    #[test]
+    #[cfg(feature = "sqlite")]
+    fn export_runs() {
+        let dir = TestDir::new("export");
+        let path = dir.join("deckard.db");
+
+        let mut index = FileIndex::new(
+            collect_paths(vec!["../test_files/same_files"]),
+            SearchConfig::default(),
+        );
+        index.index_dirs();
+        index.process_files(None, None);
+        index.find_duplicates(None, None);
+
+        let first = sqlite(&index, &path).unwrap();
+        let second = sqlite(&index, &path).unwrap();
+        assert_ne!(first, second);
+
+        let db = Connection::open(&path).unwrap();
+        let files: i64 = db
+            .query_row(
+                "SELECT COUNT(*) FROM files WHERE run_id = ?1",
+                [second],
+                |row| row.get(0),
+            )
+            .unwrap();
+        let duplicates: i64 = db
+            .query_row(
+                "SELECT COUNT(*) FROM duplicates WHERE run_id = ?1",
+                [second],
+                |row| row.get(0),
+            )
+            .unwrap();
+
+        assert_eq!(files as usize, index.files_len());
+        assert_eq!(duplicates, 6);
+    }
+
+    #[test]
+    fn write_checksums() {
+        let dir = TestDir::new("checksums");
+        let path = dir.join("checksums.sha256");
+        let mut config = SearchConfig::default();
+        config.hasher_config.full_hash = true;
+        config.hasher_config.hash_algorithm = crate::config::HashAlgorithm::SHA256;
+
+        let mut index = FileIndex::new(collect_paths(vec!["../test_files/same_files"]), config);
+        index.index_dirs();
+        index.process_files(None, None);
+
+        let written = checksums(&index, &path).unwrap();
+        let lines = fs::read_to_string(&path).unwrap();
+
+        assert_eq!(written, index.files_len());
+        let first = lines.lines().next().unwrap();
+        let (hash, file) = first.split_once("  ").unwrap();
+        assert_eq!(hash.len(), 64);
+        assert_eq!(
+            Some(&hash.to_string()),
+            index.files[Path::new(file)].full_hash.as_ref()
+        );
+    }
+
+    #[test]
+    fn checksums_of_sliced_files() {
+        let dir = TestDir::new("sliced_checksums");
+        let path = dir.join("checksums.sha256");
+        let mut config = SearchConfig::default();
+        config.hasher_config.full_hash = true;
+        config.hasher_config.hash_algorithm = crate::config::HashAlgorithm::SHA256;
+        // every non-empty test file is hashed in slices
+        config.hasher_config.parallel_size = 1;
+
+        let mut index = FileIndex::new(collect_paths(vec!["../test_files/same_files"]), config);
+        index.index_dirs();
+        index.process_files(None, None);
+        checksums(&index, &path).unwrap();
+        let lines = fs::read_to_string(&path).unwrap();
+
+        for line in lines.lines() {
+            let (hash, file) = line.split_once("  ").unwrap();
+            let whole =
+                hasher::get_full_hash(&crate::config::HashAlgorithm::SHA256, file, &None).unwrap();
+            assert_eq!(hash, whole);
+        }
+    }
+}
@@ -1,28 +1,33 @@
-use base64::prelude::*;
-use chksum::{md5, sha2_256};
 use chrono::prelude::*;
-use image_hasher::{FilterType, HashAlg, HasherConfig};
-use infer::Type;
-use rusty_chromaprint::Configuration;
+use image::io::Reader as ImageReader;
 use std::{
     ffi::OsString,
     fmt::{self, Display},
-    fs::{self, read, DirEntry, File, FileType, Metadata},
-    io::{Read, Seek},
-    os::unix::fs::MetadataExt,
+    fs::{self, DirEntry, File, FileType, Metadata},
+    io::Read,
     path::{Path, PathBuf},
-    u32, u8, usize,
+    sync::{atomic::AtomicBool, Arc},
 };
 
 use image_hasher::ImageHash;
 
-use log::{debug, error, trace, warn};
+use deckard_core::Hashes;
+use log::{debug, trace, warn};
+use serde::{Deserialize, Serialize};
 
-use crate::{config::SearchConfig, hasher};
+use crate::{
+    config::{HashAlgorithm, HasherConfig, SearchConfig},
+    error::DeckardError,
+    hasher,
+    metrics::{timed, Metrics, Stage},
+    platform, xattr,
+};
 
 const MAGIC_SIZE: usize = 8;
+/// The maximum fingerprint score, scores count differing bits so lower is closer
+const MAX_AUDIO_SCORE: f64 = 32.0;
 
-#[derive(Debug, Eq, PartialEq, Clone)]
+#[derive(Debug, Eq, PartialEq, Clone, Serialize, Deserialize)]
 pub enum EntryType {
     File,
     Dir,
@@ -46,6 +51,14 @@ impl EntryType {
         }
         EntryType::Unknown
     }
+
+    /// Like `new`, also treating Windows junctions as links
+    pub fn from_metadata(metadata: &Metadata) -> Self {
+        if platform::is_link(metadata) {
+            return EntryType::Symlink;
+        }
+        Self::new(Ok(metadata.file_type()))
+    }
 }
 
 impl Display for EntryType {
@@ -60,7 +73,7 @@ impl Display for EntryType {
     }
 }
 
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub struct FileEntry {
     pub path: PathBuf,
     pub name: String,
@@ -71,13 +84,50 @@ pub struct FileEntry {
     pub modified: DateTime<Local>,
     pub mime_type: Option<String>,
     pub size: u64,
+    pub inode: u64,
+    /// Device holding the file, with the inode it identifies hardlinks
+    #[serde(default)]
+    pub device: Option<u64>,
     pub hash: Option<String>,
     pub full_hash: Option<String>,
+    /// Full hash with the paranoid algorithm
+    #[serde(default)]
+    pub paranoid_hash: Option<String>,
+    #[serde(with = "image_hash_base64")]
     pub image_hash: Option<ImageHash>,
     pub audio_hash: Option<Vec<u32>>,
+    #[serde(default)]
+    pub image_info: Option<ImageInfo>,
+    #[serde(default)]
+    pub audio_info: Option<AudioInfo>,
     pub processed: bool,
 }
 
+/// Dimensions and format read from the image header
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct ImageInfo {
+    pub width: u32,
+    pub height: u32,
+    pub format: String,
+}
+
+impl ImageInfo {
+    pub fn pixels(&self) -> u64 {
+        self.width as u64 * self.height as u64
+    }
+}
+
+/// Codec and quality of an audio file
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct AudioInfo {
+    pub codec: String,
+    pub lossless: bool,
+    /// Average bitrate in kbit/s
+    pub bitrate: Option<u32>,
+    pub sample_rate: Option<u32>,
+    pub bits_per_sample: Option<u32>,
+}
+
 impl FileEntry {
     pub fn new(path: PathBuf, name: OsString, metadata: Metadata) -> Self {
         Self {
@@ -95,15 +145,67 @@ impl FileEntry {
                 .extension()
                 .and_then(|os_str| os_str.to_str())
                 .map(|s| s.to_string()),
-            file_type: EntryType::new(Ok(metadata.file_type())),
-            created: metadata.created().unwrap().into(),
-            modified: metadata.modified().unwrap().into(),
+            file_type: EntryType::from_metadata(&metadata),
+            created: platform::created(&metadata),
+            modified: platform::modified(&metadata),
             mime_type: None,
-            size: metadata.size(),
+            size: metadata.len(),
+            inode: platform::inode(&metadata),
+            device: platform::device(&metadata),
             hash: None,
             full_hash: None,
+            paranoid_hash: None,
             image_hash: None,
             audio_hash: None,
+            image_info: None,
+            audio_info: None,
+            processed: false,
+        }
+    }
+
+    /// Point the entry at a new path, like after the file was renamed
+    pub fn set_path(&mut self, path: PathBuf) {
+        self.name = path
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default();
+        self.prefix = self.name.split('.').next().unwrap_or_default().to_string();
+        self.extension = path
+            .extension()
+            .and_then(|os_str| os_str.to_str())
+            .map(|s| s.to_string());
+        self.path = path;
+    }
+
+    /// Entry of a file that is not on the local file system, like one on a
+    /// remote host, only the size and modification time are known
+    pub fn detached(path: PathBuf, size: u64, modified: DateTime<Local>) -> Self {
+        let name = path
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default();
+        Self {
+            prefix: name.split('.').next().unwrap_or_default().to_string(),
+            extension: path
+                .extension()
+                .and_then(|os_str| os_str.to_str())
+                .map(|s| s.to_string()),
+            name,
+            path,
+            file_type: EntryType::File,
+            created: modified,
+            modified,
+            mime_type: None,
+            size,
+            inode: 0,
+            device: None,
+            hash: None,
+            full_hash: None,
+            paranoid_hash: None,
+            image_hash: None,
+            audio_hash: None,
+            image_info: None,
+            audio_info: None,
             processed: false,
         }
     }
@@ -127,51 +229,71 @@ impl FileEntry {
                 .extension()
                 .and_then(|os_str| os_str.to_str())
                 .map(|s| s.to_string()),
-            file_type: EntryType::new(entry.file_type()),
-            created: metadata.created().unwrap().into(),
-            modified: metadata.modified().unwrap().into(),
+            file_type: EntryType::from_metadata(&metadata),
+            created: platform::created(&metadata),
+            modified: platform::modified(&metadata),
             mime_type: None,
-            size: metadata.size(),
+            size: metadata.len(),
+            inode: platform::inode(&metadata),
+            device: platform::device(&metadata),
             hash: None,
             full_hash: None,
+            paranoid_hash: None,
             image_hash: None,
             audio_hash: None,
+            image_info: None,
+            audio_info: None,
             processed: false,
         }
     }
 
-    pub fn process(&mut self, config: &SearchConfig) {
+    pub fn process(
+        &mut self,
+        config: &SearchConfig,
+        cancel: &Option<Arc<AtomicBool>>,
+        metrics: Option<&Metrics>,
+    ) -> Result<(), DeckardError> {
         if self.file_type != EntryType::File {
             warn!("process: {} is not a file!", self.path.to_string_lossy());
-            return;
+            return Ok(());
         }
 
-        self.mime_type = Some(get_mime_type(&self.path));
-        trace!("{} found mime type {:?}", self.name, self.mime_type);
+        // the file may have been written to since it was indexed, its size
+        // no longer matches the files it was grouped with
+        if self.restat()? {
+            return Err(DeckardError::Changed);
+        }
 
-        self.hash = Some(hasher::get_quick_hash(
-            &config.hasher_config.hash_algorithm,
-            config.hasher_config.size,
-            config.hasher_config.splits,
-            &self.path,
-        ));
+        self.mime_type = Some(get_mime_type(&self.path)?);
+        trace!("{} found mime type {:?}", self.name, self.mime_type);
+        if self.mime_type.as_ref().is_some_and(|m| m.contains("image")) {
+            self.image_info = get_image_info(&self.path);
+        }
+        if self.mime_type.as_ref().is_some_and(|m| m.contains("audio")) {
+            match hasher::get_audio_info(&self.path, self.size) {
+                Ok(info) => self.audio_info = Some(info),
+                Err(e) => warn!("Reading audio {:?} failed: {}", self.path, e),
+            }
+        }
 
-        if config.hasher_config.full_hash {
-            self.full_hash = Some(hasher::get_full_hash(
-                &config.hasher_config.hash_algorithm,
-                &self.path,
-            ))
+        let hasher_config = config.hasher_config_for(&self.path, self.mime_type.as_deref());
+        let stamped = config.xattr_cache && xattr::apply(self, &hasher_config);
+        if !stamped {
+            self.hash_contents(&hasher_config, cancel, metrics)?;
         }
 
         if config.image_config.compare {
             if let Some(mime) = self.mime_type.as_ref() {
                 if mime.contains("image") {
-                    self.image_hash = hasher::get_image_hash(
-                        &config.image_config.hash_algorithm,
-                        &config.image_config.filter_algorithm,
-                        config.image_config.size,
-                        &self.path,
-                    );
+                    self.image_hash = timed(metrics, Stage::ImageHashing, &self.path, || {
+                        hasher::get_image_hash(
+                            &config.image_config.hash_algorithm,
+                            &config.image_config.filter_algorithm,
+                            config.image_config.size,
+                            &self.path,
+                            cancel,
+                        )
+                    });
                 }
             } else {
                 warn!("No MIME type for file {}", self.path.to_string_lossy())
@@ -181,24 +303,114 @@ impl FileEntry {
         if config.audio_config.compare {
             if let Some(mime) = self.mime_type.as_ref() {
                 if mime.contains("audio") {
-                    let chroma_config = Configuration::preset_test1();
-                    self.audio_hash = hasher::get_audio_hash(&self.path, &chroma_config);
+                    let audio_hash = timed(metrics, Stage::AudioFingerprinting, &self.path, || {
+                        hasher::get_audio_hash(&self.path, &config.audio_config, cancel)
+                    });
+                    match audio_hash {
+                        Ok(audio_hash) => self.audio_hash = Some(audio_hash),
+                        // not decodable audio is not comparable, but the file is fine
+                        Err(DeckardError::Audio(e)) => {
+                            warn!("Audio {} failed: {}", self.path.to_string_lossy(), e)
+                        }
+                        Err(e) => return Err(e),
+                    }
                 }
             } else {
                 warn!("No MIME type for file {}", self.path.to_string_lossy())
             }
         }
 
+        if hasher::is_cancelled(cancel) {
+            return Err(DeckardError::Cancelled);
+        }
+        if self.restat()? {
+            return Err(DeckardError::Changed);
+        }
+        if config.xattr_cache && !stamped && !config.action_config.read_only {
+            xattr::store(self, &hasher_config);
+        }
         self.processed = true;
+        Ok(())
+    }
+
+    fn hash_contents(
+        &mut self,
+        config: &HasherConfig,
+        cancel: &Option<Arc<AtomicBool>>,
+        metrics: Option<&Metrics>,
+    ) -> Result<(), DeckardError> {
+        self.hash = Some(timed(metrics, Stage::Hashing, &self.path, || {
+            hasher::get_quick_hash(
+                &config.hash_algorithm,
+                config.size,
+                config.splits,
+                &self.path,
+            )
+        })?);
+
+        let full_hash = |algorithm: &HashAlgorithm| {
+            timed(metrics, Stage::Hashing, &self.path, || {
+                if config.hashes_in_slices(self.size) {
+                    hasher::get_parallel_hash(algorithm, &self.path, cancel)
+                } else {
+                    hasher::get_full_hash(algorithm, &self.path, cancel)
+                }
+            })
+        };
+        if config.hashes_fully() {
+            self.full_hash = Some(full_hash(&config.hash_algorithm)?);
+        }
+        if let Some(algorithm) = &config.paranoid_algorithm {
+            self.paranoid_hash = Some(full_hash(algorithm)?);
+        }
+        Ok(())
+    }
+
+    /// Refresh the size and modification time, returns true when they changed
+    fn restat(&mut self) -> Result<bool, DeckardError> {
+        let metadata = fs::metadata(&self.path)?;
+        let modified = platform::modified(&metadata);
+        if metadata.len() == self.size && modified == self.modified {
+            return Ok(false);
+        }
+        debug!("{} changed since indexing", self.path.to_string_lossy());
+        self.size = metadata.len();
+        self.modified = modified;
+        Ok(true)
+    }
+
+    /// The file is gone or was modified while it was processed
+    pub(crate) fn changed_during_scan(error: &DeckardError) -> bool {
+        match error {
+            DeckardError::Changed => true,
+            DeckardError::Io(e) => e.kind() == std::io::ErrorKind::NotFound,
+            _ => false,
+        }
+    }
+
+    /// Size and content hashes compared to find exact copies
+    pub fn hashes(&self) -> Hashes<'_> {
+        Hashes {
+            size: self.size,
+            hash: self.hash.as_deref(),
+            full_hash: self.full_hash.as_deref(),
+            paranoid_hash: self.paranoid_hash.as_deref(),
+        }
     }
 
     pub fn compare(&self, other: &Self, config: &SearchConfig) -> bool {
+        self.confidence(other, config).is_some()
+    }
+
+    /// How alike the two files are from 0 to 100, 100 for the same content
+    /// and lower for perceptual matches, None when they don't match
+    pub fn confidence(&self, other: &Self, config: &SearchConfig) -> Option<u8> {
         if self.file_type != EntryType::File {
             warn!(
                 "compare self: {} is not a file!",
                 self.path.to_string_lossy()
             );
-            return false;
+            return None;
         }
 
         if other.file_type != EntryType::File {
@@ -206,87 +418,90 @@ impl FileEntry {
                 "compare other: {} is not a file!",
                 other.path.to_string_lossy()
             );
-            return false;
-        }
-
-        if self.size == other.size {
-            if self.hash.is_some() && self.hash == other.hash && other.hash.is_some() {
-                // check the full file
-                if config.hasher_config.full_hash {
-                    if self.full_hash.is_some()
-                        && other.full_hash.is_some()
-                        && self.full_hash == other.full_hash
-                    {
-                        return true;
-                    }
-                } else {
-                    return true;
-                }
-            }
+            return None;
+        }
+
+        // a hash rule may fully hash only some files, those never match on the quick hash alone
+        let full_hash = config.hasher_config.hashes_fully()
+            || self.full_hash.is_some()
+            || other.full_hash.is_some();
+        if self.hashes().same_content(
+            &other.hashes(),
+            full_hash,
+            config.hasher_config.paranoid_algorithm.is_some(),
+        ) {
+            return Some(100);
         }
 
-        if config.image_config.compare && self.mime_type.is_some() && other.mime_type.is_some() {
-            if self.mime_type.as_ref().unwrap().contains("image")
-                && other.mime_type.as_ref().unwrap().contains("image")
-                && self.image_hash.is_some()
-                && other.image_hash.is_some()
-            {
-                let this_image = self.image_hash.as_ref().unwrap();
-                let other_image = other.image_hash.as_ref().unwrap();
-
-                let distance = this_image.dist(&other_image);
-                debug!(
-                    "{} and {} hamming distance: {}",
-                    self.name, other.name, distance
-                );
+        if config.image_config.compare {
+            if let Some((distance, bits)) = self.image_distance(other) {
                 if distance <= config.image_config.threshold as u32 {
-                    return true;
+                    return Some(100 - (distance * 100 / bits.max(1)).min(100) as u8);
                 }
             }
         }
 
-        if config.audio_config.compare && self.mime_type.is_some() && other.mime_type.is_some() {
-            if self.mime_type.as_ref().unwrap().contains("audio")
-                && other.mime_type.as_ref().unwrap().contains("audio")
-                && self.audio_hash.is_some()
-                && other.audio_hash.is_some()
-            {
-                let this_audio = self.audio_hash.clone().unwrap();
-                let other_audio = other.audio_hash.clone().unwrap();
-                let chroma_config = Configuration::preset_test1();
-
-                let segments = rusty_chromaprint::match_fingerprints(
-                    &this_audio,
-                    &other_audio,
-                    &chroma_config,
-                )
-                .unwrap();
-
-                // find average score
-                let score = if !segments.is_empty() {
-                    segments.iter().map(|s| s.score).sum::<f64>() / segments.len() as f64
-                } else {
-                    32.0 // is the maximum fingerprint score
-                };
-
-                debug!(
-                    "{} and {} matching segments {} with score {}",
-                    self.name,
-                    other.name,
-                    segments.len(),
-                    score
-                );
-
-                if !segments.is_empty()
-                    && segments.len() <= config.audio_config.segments_limit as usize
+        if config.audio_config.compare {
+            if let Some((segments, score)) = self.audio_match(other, config) {
+                if segments > 0
+                    && segments <= config.audio_config.segments_limit as usize
                     && score <= config.audio_config.threshold
                 {
-                    return true;
+                    let similarity = 1.0 - score / MAX_AUDIO_SCORE;
+                    return Some((similarity * 100.0).clamp(0.0, 100.0).round() as u8);
                 }
             }
         }
 
-        false
+        None
+    }
+
+    /// Hamming distance between the perceptual hashes of two images and the hash length in bits
+    fn image_distance(&self, other: &Self) -> Option<(u32, u32)> {
+        let is_image = |f: &Self| f.mime_type.as_ref().is_some_and(|m| m.contains("image"));
+        if !is_image(self) || !is_image(other) {
+            return None;
+        }
+        let this_image = self.image_hash.as_ref()?;
+        let other_image = other.image_hash.as_ref()?;
+
+        let distance = this_image.dist(other_image);
+        debug!(
+            "{} and {} hamming distance: {}",
+            self.name, other.name, distance
+        );
+        Some((distance, this_image.as_bytes().len() as u32 * 8))
+    }
+
+    /// Number of matching segments of two audio fingerprints and their
+    /// average score, lower scores are more alike
+    fn audio_match(&self, other: &Self, config: &SearchConfig) -> Option<(usize, f64)> {
+        let is_audio = |f: &Self| f.mime_type.as_ref().is_some_and(|m| m.contains("audio"));
+        if !is_audio(self) || !is_audio(other) {
+            return None;
+        }
+        let this_audio = self.audio_hash.as_ref()?;
+        let other_audio = other.audio_hash.as_ref()?;
+        let chroma_config = config.audio_config.preset.to_configuration();
+
+        let segments =
+            rusty_chromaprint::match_fingerprints(this_audio, other_audio, &chroma_config).unwrap();
+
+        // find average score
+        let score = if !segments.is_empty() {
+            segments.iter().map(|s| s.score).sum::<f64>() / segments.len() as f64
+        } else {
+            MAX_AUDIO_SCORE
+        };
+
+        debug!(
+            "{} and {} matching segments {} with score {}",
+            self.name,
+            other.name,
+            segments.len(),
+            score
+        );
+        Some((segments.len(), score))
     }
 }
 
@@ -294,13 +509,10 @@ impl Display for FileEntry {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(
             f,
-            "{} {} {} : {}",
-            if self.file_type == EntryType::Dir {
-                format!("{}  {}", self.file_type, self.name)
-            } else {
-                format!("{}  {}", self.file_type, self.name)
-            },
-            format!("{} B", self.size),
+            "{}  {} {} B {} : {}",
+            self.file_type,
+            self.name,
+            self.size,
             self.created.format("%Y-%m-%d %H:%M:%S"),
             self.path.to_string_lossy(),
         )
@@ -308,23 +520,71 @@ impl Display for FileEntry {
 }
 
 #[inline]
-pub fn get_mime_type<P: AsRef<Path> + std::fmt::Debug>(path: P) -> String {
+pub fn get_mime_type<P: AsRef<Path> + std::fmt::Debug>(path: P) -> std::io::Result<String> {
     let mime = mime_guess::from_path(&path).first();
     match mime {
-        Some(mime_type) => {
-            return mime_type.to_string();
-        }
+        Some(mime_type) => Ok(mime_type.to_string()),
         None => {
-            let mut file = File::open(&path).unwrap();
+            let mut file = File::open(&path)?;
 
             let mut magic = [0; MAGIC_SIZE];
-            if file.metadata().unwrap().size() >= MAGIC_SIZE as u64 {
+            if file.metadata()?.len() >= MAGIC_SIZE as u64 {
                 file.read_exact(&mut magic)
                     .unwrap_or_else(|e| warn!("read magic: {:?} for {:?}", e, path));
             }
             // Find the MIME type
-            let mime_type = tree_magic::from_u8(&magic);
-            return mime_type;
+            Ok(tree_magic::from_u8(&magic))
         }
     }
 }
+
+/// Read the dimensions and format from the image header without decoding it
+pub fn get_image_info<P: AsRef<Path> + std::fmt::Debug>(path: P) -> Option<ImageInfo> {
+    let reader = match ImageReader::open(&path).and_then(|r| r.with_guessed_format()) {
+        Ok(reader) => reader,
+        Err(e) => {
+            warn!("Reading image {:?} failed: {}", path, e);
+            return None;
+        }
+    };
+    let format = reader.format()?;
+    match reader.into_dimensions() {
+        Ok((width, height)) => Some(ImageInfo {
+            width,
+            height,
+            format: format!("{:?}", format).to_lowercase(),
+        }),
+        Err(e) => {
+            warn!("Reading image {:?} dimensions failed: {}", path, e);
+            None
+        }
+    }
+}
+
+/// Store image hashes as base64 strings
+pub(crate) mod image_hash_base64 {
+    use image_hasher::ImageHash;
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(
+        hash: &Option<ImageHash>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match hash {
+            Some(hash) => serializer.serialize_some(&hash.to_base64()),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<ImageHash>, D::Error> {
+        let encoded: Option<String> = Option::deserialize(deserializer)?;
+        encoded
+            .map(|e| {
+                ImageHash::from_base64(&e)
+                    .map_err(|e| D::Error::custom(format!("invalid image hash: {:?}", e)))
+            })
+            .transpose()
+    }
+}
@@ -0,0 +1,290 @@
+use std::collections::{BinaryHeap, HashSet};
+use std::fs;
+use std::io::{self, BufReader, Read};
+use std::path::{Path, PathBuf};
+
+use log::error;
+
+use crate::platform;
+
+/// What removing a set of files would do
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct RemovalPreview {
+    pub files: Vec<PathBuf>,
+    /// Directories left empty, removed along with the files
+    pub dirs: Vec<PathBuf>,
+    pub bytes: u64,
+}
+
+/// Preview the removal of the files, scan roots are never removed
+pub fn preview_removal(
+    files: &[PathBuf],
+    roots: &HashSet<PathBuf>,
+    remove_empty_dirs: bool,
+) -> RemovalPreview {
+    let mut files = files.to_vec();
+    files.sort();
+    files.dedup();
+
+    let bytes = files
+        .iter()
+        .filter_map(|f| fs::symlink_metadata(f).ok())
+        .map(|m| m.len())
+        .sum();
+    let dirs = if remove_empty_dirs {
+        emptied_dirs(&files, roots)
+    } else {
+        Vec::new()
+    };
+
+    RemovalPreview { files, dirs, bytes }
+}
+
+/// Files and directories actually removed
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct RemovalOutcome {
+    pub files: Vec<PathBuf>,
+    pub dirs: Vec<PathBuf>,
+    pub failed: Vec<(PathBuf, String)>,
+}
+
+/// Remove the previewed files, then the emptied directories deepest first
+pub fn remove(preview: &RemovalPreview) -> RemovalOutcome {
+    let mut outcome = RemovalOutcome::default();
+    for file in &preview.files {
+        match fs::remove_file(file) {
+            Ok(_) => outcome.files.push(file.clone()),
+            Err(e) => {
+                error!("failed deleting {:?}: {}", file, e);
+                outcome.failed.push((file.clone(), e.to_string()));
+            }
+        }
+    }
+    // a parent is only empty once its children are gone
+    for dir in preview.dirs.iter().rev() {
+        match fs::remove_dir(dir) {
+            Ok(_) => outcome.dirs.push(dir.clone()),
+            Err(e) => {
+                error!("failed removing directory {:?}: {}", dir, e);
+                outcome.failed.push((dir.clone(), e.to_string()));
+            }
+        }
+    }
+    outcome
+}
+
+/// A group that was left whole because a copy differs from the kept file
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mismatch {
+    pub keep: PathBuf,
+    pub file: PathBuf,
+    pub reason: String,
+}
+
+/// Compare the copies of every group with its kept file right before they
+/// are removed. A group is dropped entirely when any copy differs or can't be
+/// read, returns the files that are safe to remove.
+pub fn verify_groups(groups: &[(PathBuf, Vec<PathBuf>)]) -> (Vec<PathBuf>, Vec<Mismatch>) {
+    let mut verified = Vec::new();
+    let mut mismatches = Vec::new();
+    for (keep, files) in groups {
+        let mismatch = files.iter().find_map(|file| {
+            let reason = match same_content(keep, file) {
+                Ok(true) => return None,
+                Ok(false) => "contents differ".to_string(),
+                Err(e) => e.to_string(),
+            };
+            Some(Mismatch {
+                keep: keep.clone(),
+                file: file.clone(),
+                reason,
+            })
+        });
+        match mismatch {
+            Some(mismatch) => {
+                error!(
+                    "not removing the copies of {:?}, {:?}: {}",
+                    keep, mismatch.file, mismatch.reason
+                );
+                mismatches.push(mismatch);
+            }
+            None => verified.extend(files.iter().cloned()),
+        }
+    }
+    (verified, mismatches)
+}
+
+/// Compare two files byte for byte
+pub fn same_content(a: &Path, b: &Path) -> io::Result<bool> {
+    let (file_a, file_b) = (fs::File::open(a)?, fs::File::open(b)?);
+    if file_a.metadata()?.len() != file_b.metadata()?.len() {
+        return Ok(false);
+    }
+    let (mut a, mut b) = (BufReader::new(file_a), BufReader::new(file_b));
+    let (mut buf_a, mut buf_b) = (vec![0; 64 * 1024], vec![0; 64 * 1024]);
+    loop {
+        let n = a.read(&mut buf_a)?;
+        if n == 0 {
+            // the sizes matched, unless b grew in the meantime
+            return Ok(b.read(&mut buf_b[..1])? == 0);
+        }
+        b.read_exact(&mut buf_b[..n])?;
+        if buf_a[..n] != buf_b[..n] {
+            return Ok(false);
+        }
+    }
+}
+
+/// Largest range submitted to the kernel at once, btrfs caps requests at 16 MiB
+const DEDUPE_CHUNK: u64 = 16 * 1024 * 1024;
+
+/// Share the extents of `file` with `keep` through the kernel dedupe ioctl on
+/// btrfs, XFS and other file systems supporting it. The kernel compares the
+/// bytes while both files are locked, so the files, their inodes and
+/// metadata stay as they were. Returns the bytes deduplicated.
+pub fn dedupe(keep: &Path, file: &Path) -> io::Result<u64> {
+    let src = fs::File::open(keep)?;
+    // the destination has to be writable unless running as root
+    let dest = fs::OpenOptions::new().read(true).write(true).open(file)?;
+    let len = src.metadata()?.len();
+    if dest.metadata()?.len() != len {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "sizes differ"));
+    }
+
+    let mut offset = 0;
+    while offset < len {
+        let chunk = DEDUPE_CHUNK.min(len - offset);
+        match platform::dedupe_range(&src, &dest, offset, chunk)? {
+            Some(0) => break,
+            Some(deduped) => offset += deduped,
+            None => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "contents differ",
+                ))
+            }
+        }
+    }
+    Ok(offset)
+}
+
+/// Directories containing nothing but the removed files and other emptied directories
+fn emptied_dirs(files: &[PathBuf], roots: &HashSet<PathBuf>) -> Vec<PathBuf> {
+    let removed: HashSet<&Path> = files.iter().map(|f| f.as_path()).collect();
+    let mut emptied: HashSet<PathBuf> = HashSet::new();
+    let mut visited: HashSet<PathBuf> = HashSet::new();
+
+    // deepest first so subdirectories are settled before their parents
+    let mut pending: BinaryHeap<(usize, PathBuf)> = BinaryHeap::new();
+    let mut push = |pending: &mut BinaryHeap<(usize, PathBuf)>, dir: Option<&Path>| {
+        if let Some(dir) = dir {
+            if visited.insert(dir.to_path_buf()) {
+                pending.push((dir.components().count(), dir.to_path_buf()));
+            }
+        }
+    };
+    for file in files {
+        push(&mut pending, file.parent());
+    }
+
+    while let Some((_, dir)) = pending.pop() {
+        let inside_root = roots.iter().any(|r| dir.starts_with(r) && dir != *r);
+        if !inside_root {
+            continue;
+        }
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+        let empty = entries.filter_map(|e| e.ok()).all(|e| {
+            let path = e.path();
+            removed.contains(path.as_path()) || emptied.contains(&path)
+        });
+        if empty {
+            push(&mut pending, dir.parent());
+            emptied.insert(dir);
+        }
+    }
+
+    let mut dirs: Vec<PathBuf> = emptied.into_iter().collect();
+    dirs.sort();
+    dirs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_dir::TestDir;
+
+    #[test]
+    fn preview_emptied_dirs() {
+        let root = TestDir::new("preview_removal");
+        fs::create_dir_all(root.join("a/b")).unwrap();
+        fs::create_dir_all(root.join("c")).unwrap();
+        fs::write(root.join("a/b/one"), "one").unwrap();
+        fs::write(root.join("a/two"), "two").unwrap();
+        fs::write(root.join("c/three"), "three").unwrap();
+        fs::write(root.join("c/kept"), "kept").unwrap();
+
+        let files = vec![
+            root.join("a/b/one"),
+            root.join("a/two"),
+            root.join("c/three"),
+        ];
+        let roots = HashSet::from([root.to_path_buf()]);
+
+        let preview = preview_removal(&files, &roots, true);
+        assert_eq!(preview.files.len(), 3);
+        assert_eq!(preview.dirs, vec![root.join("a"), root.join("a/b")]);
+        assert_eq!(preview.bytes, 11);
+
+        let preview = preview_removal(&files, &roots, false);
+        assert!(preview.dirs.is_empty());
+
+        let outcome = remove(&preview_removal(&files, &roots, true));
+        assert_eq!(outcome.files.len(), 3);
+        assert_eq!(outcome.dirs.len(), 2);
+        assert!(outcome.failed.is_empty());
+        assert!(!root.join("a").exists());
+        assert!(root.join("c/kept").exists());
+    }
+
+    #[test]
+    fn dedupe_identical_files() {
+        let root = TestDir::new("dedupe");
+        fs::write(root.join("keep"), "same content").unwrap();
+        fs::write(root.join("copy"), "same content").unwrap();
+        fs::write(root.join("longer"), "same content, longer").unwrap();
+
+        assert!(dedupe(&root.join("keep"), &root.join("longer")).is_err());
+        // only file systems sharing extents support it, like btrfs and XFS
+        if let Ok(deduped) = dedupe(&root.join("keep"), &root.join("copy")) {
+            assert_eq!(deduped, 12);
+        }
+        assert_eq!(fs::read(root.join("copy")).unwrap(), b"same content");
+    }
+
+    #[test]
+    fn verify_before_removing() {
+        let root = TestDir::new("verify_groups");
+        fs::write(root.join("keep"), "same").unwrap();
+        fs::write(root.join("copy"), "same").unwrap();
+        fs::write(root.join("other_keep"), "same").unwrap();
+        fs::write(root.join("collision"), "diff").unwrap();
+        fs::write(root.join("other_copy"), "same").unwrap();
+
+        assert!(same_content(&root.join("keep"), &root.join("copy")).unwrap());
+        assert!(!same_content(&root.join("keep"), &root.join("collision")).unwrap());
+
+        let groups = vec![
+            (root.join("keep"), vec![root.join("copy")]),
+            (
+                root.join("other_keep"),
+                vec![root.join("other_copy"), root.join("collision")],
+            ),
+        ];
+        let (verified, mismatches) = verify_groups(&groups);
+        assert_eq!(verified, vec![root.join("copy")]);
+        assert_eq!(mismatches.len(), 1);
+        assert_eq!(mismatches[0].file, root.join("collision"));
+    }
+}
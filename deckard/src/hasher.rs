@@ -1,34 +1,155 @@
-use crate::config::{HashAlgorithm, ImageFilterAlgorithm, ImageHashAlgorithm};
+use crate::config::{AudioConfig, HashAlgorithm, ImageFilterAlgorithm, ImageHashAlgorithm};
+use crate::error::DeckardError;
+use crate::file::AudioInfo;
 use chksum::{md5, sha1, sha2_256, sha2_512};
 use image::io::Reader as ImageReader;
 use image_hasher::{HasherConfig, ImageHash};
-use log::{debug, error, trace, warn};
-use rusty_chromaprint::{Configuration, Fingerprinter};
+use log::{debug, trace, warn};
+use rayon::prelude::*;
+use rusty_chromaprint::Fingerprinter;
 use std::{
     fs::File,
-    io::{Read, Seek},
+    io::{self, BufReader, Read, Seek, SeekFrom},
     path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
 };
 use symphonia::core::{
     audio::SampleBuffer,
     codecs::{DecoderOptions, CODEC_TYPE_NULL},
     errors::Error,
-    formats::FormatOptions,
+    formats::{FormatReader, Track},
     io::MediaSourceStream,
-    meta::MetadataOptions,
     probe::Hint,
 };
 
+const BUFFER_SIZE: usize = 64 * 1024;
+/// Short names of the lossless codecs besides PCM
+const LOSSLESS_CODECS: [&str; 5] = ["flac", "alac", "wavpack", "ape", "tta"];
+/// Bytes hashed by each task when a huge file is hashed in parallel
+const PARALLEL_SLICE_SIZE: u64 = 64 * 1024 * 1024;
+
+pub fn is_cancelled(cancel: &Option<Arc<AtomicBool>>) -> bool {
+    cancel.as_ref().is_some_and(|c| c.load(Ordering::Relaxed))
+}
+
+/// Reader that fails as soon as the scan is cancelled
+struct CancellableReader<R> {
+    inner: R,
+    cancel: Option<Arc<AtomicBool>>,
+}
+
+impl<R> CancellableReader<R> {
+    fn new(inner: R, cancel: &Option<Arc<AtomicBool>>) -> Self {
+        Self {
+            inner,
+            cancel: cancel.clone(),
+        }
+    }
+}
+
+impl<R: Read> Read for CancellableReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if is_cancelled(&self.cancel) {
+            return Err(io::Error::other("cancelled"));
+        }
+        self.inner.read(buf)
+    }
+}
+
+impl<R: Seek> Seek for CancellableReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.inner.seek(pos)
+    }
+}
+
+/// Feed the whole reader into the hasher, chunk by chunk
+macro_rules! stream_digest {
+    ($algorithm:ident, $reader:expr) => {{
+        let mut hasher = $algorithm::new();
+        let mut buffer = vec![0; BUFFER_SIZE];
+        loop {
+            let n = $reader.read(&mut buffer)?;
+            if n == 0 {
+                break;
+            }
+            hasher.update(&buffer[..n]);
+        }
+        hasher.digest().to_hex_lowercase()
+    }};
+}
+
+/// Hash the whole file
 #[inline]
-pub fn get_full_hash<P: AsRef<Path>>(hash: &HashAlgorithm, path: P) -> String {
-    let file = File::open(path).unwrap();
+pub fn get_full_hash<P: AsRef<Path>>(
+    hash: &HashAlgorithm,
+    path: P,
+    cancel: &Option<Arc<AtomicBool>>,
+) -> Result<String, DeckardError> {
+    let file = File::open(path)?;
+    let reader = CancellableReader::new(file, cancel);
+
+    match stream_hash(hash, reader) {
+        Ok(digest) => Ok(digest),
+        Err(_) if is_cancelled(cancel) => Err(DeckardError::Cancelled),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Hash the first `len` bytes of the file
+pub fn get_prefix_hash<P: AsRef<Path>>(
+    hash: &HashAlgorithm,
+    path: P,
+    len: u64,
+    cancel: &Option<Arc<AtomicBool>>,
+) -> Result<String, DeckardError> {
+    let file = File::open(path)?;
+    let reader = CancellableReader::new(file, cancel).take(len);
+
+    match stream_hash(hash, reader) {
+        Ok(digest) => Ok(digest),
+        Err(_) if is_cancelled(cancel) => Err(DeckardError::Cancelled),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Hash the file in slices on the thread pool, the digest is the hash of the
+/// slice digests so it only matches files hashed the same way
+pub fn get_parallel_hash<P: AsRef<Path> + Sync>(
+    hash: &HashAlgorithm,
+    path: P,
+    cancel: &Option<Arc<AtomicBool>>,
+) -> Result<String, DeckardError> {
+    let len = File::open(&path)?.metadata()?.len();
+    let slices = len.div_ceil(PARALLEL_SLICE_SIZE).max(1);
+
+    let digests = (0..slices)
+        .into_par_iter()
+        .map(|i| {
+            let mut file = File::open(&path)?;
+            file.seek(SeekFrom::Start(i * PARALLEL_SLICE_SIZE))?;
+            let reader = CancellableReader::new(file, cancel).take(PARALLEL_SLICE_SIZE);
+            stream_hash(hash, reader)
+        })
+        .collect::<io::Result<Vec<String>>>();
+
+    match digests {
+        Ok(digests) => Ok(stream_hash(hash, digests.concat().as_bytes())?),
+        Err(_) if is_cancelled(cancel) => Err(DeckardError::Cancelled),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn stream_hash<R: Read>(hash: &HashAlgorithm, mut reader: R) -> io::Result<String> {
     let digest = match hash {
-        HashAlgorithm::MD5 => md5::chksum(file).unwrap().to_hex_lowercase(),
-        HashAlgorithm::SHA1 => sha1::chksum(file).unwrap().to_hex_lowercase(),
-        HashAlgorithm::SHA256 => sha2_256::chksum(file).unwrap().to_hex_lowercase(),
-        HashAlgorithm::SHA512 => sha2_512::chksum(file).unwrap().to_hex_lowercase(),
+        HashAlgorithm::MD5 => stream_digest!(md5, reader),
+        HashAlgorithm::SHA1 => stream_digest!(sha1, reader),
+        HashAlgorithm::SHA256 => stream_digest!(sha2_256, reader),
+        HashAlgorithm::SHA512 => stream_digest!(sha2_512, reader),
     };
-    digest
+    Ok(digest)
 }
 
 #[inline]
@@ -37,22 +158,17 @@ pub fn get_quick_hash<P: AsRef<Path>>(
     size: u64,
     splits: u64,
     path: P,
-) -> String {
+) -> io::Result<String> {
     let mut size = size;
-    let mut file = File::open(path).unwrap();
+    let mut file = File::open(path)?;
     let mut total_buffer = vec![0; 0];
 
-    let file_len = file.metadata().unwrap().len();
-    let mut read_whole_file = false;
-
-    if file_len == 0 || size == 0 || splits == 0 {
-        read_whole_file = true;
-    } else if splits >= file_len || file_len / splits < size {
-        read_whole_file = true;
-    }
+    let file_len = file.metadata()?.len();
+    let read_whole_file =
+        file_len == 0 || size == 0 || splits == 0 || splits >= file_len || file_len / splits < size;
 
     if read_whole_file {
-        file.read_to_end(&mut total_buffer).unwrap();
+        file.read_to_end(&mut total_buffer)?;
     } else {
         let mut index_step = file_len / splits;
         if index_step == 0 {
@@ -71,11 +187,11 @@ pub fn get_quick_hash<P: AsRef<Path>>(
 
         for i in 0..splits {
             let mut buffer = vec![0; size as usize];
-            let index = i as u64 * index_step;
+            let index = i * index_step;
             // println!("reading {} bytes at {} of {}", size, index, file_len);
 
-            file.seek(std::io::SeekFrom::Start(index)).unwrap();
-            file.read_exact(&mut buffer).unwrap();
+            file.seek(SeekFrom::Start(index))?;
+            file.read_exact(&mut buffer)?;
             total_buffer.append(&mut buffer);
         }
         // append size to the hash, otherwise files that start with the same bytes match
@@ -88,7 +204,7 @@ pub fn get_quick_hash<P: AsRef<Path>>(
         HashAlgorithm::SHA256 => sha2_256::chksum(&total_buffer).unwrap().to_hex_lowercase(),
         HashAlgorithm::SHA512 => sha2_512::chksum(&total_buffer).unwrap().to_hex_lowercase(),
     };
-    digest
+    Ok(digest)
 }
 
 #[inline]
@@ -97,8 +213,18 @@ pub fn get_image_hash<P: AsRef<Path> + std::fmt::Debug>(
     filter: &ImageFilterAlgorithm,
     size: u64,
     path: &P,
+    cancel: &Option<Arc<AtomicBool>>,
 ) -> Option<ImageHash> {
-    match ImageReader::open(path) {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) => {
+            warn!("Reading image {:?} failed: {}", path, e);
+            return None;
+        }
+    };
+    let reader = BufReader::new(CancellableReader::new(file, cancel));
+
+    match ImageReader::new(reader).with_guessed_format() {
         Ok(r) => match r.decode() {
             Ok(img) => {
                 let hasher = HasherConfig::new()
@@ -110,6 +236,9 @@ pub fn get_image_hash<P: AsRef<Path> + std::fmt::Debug>(
                 trace!("Image {:?} hash: {}", path, hash.to_base64());
                 return Some(hash);
             }
+            Err(_) if is_cancelled(cancel) => {
+                debug!("Decoding image {:?} cancelled", path);
+            }
             Err(e) => {
                 warn!("Decoding image {:?} failed: {}", path, e);
             }
@@ -124,54 +253,53 @@ pub fn get_image_hash<P: AsRef<Path> + std::fmt::Debug>(
 #[inline]
 pub fn get_audio_hash(
     path: impl AsRef<Path> + std::fmt::Debug,
-    config: &Configuration,
-) -> Option<Vec<u32>> {
-    let file = std::fs::File::open(path.as_ref()).expect("failed to open media");
-
-    let mut hint = Hint::new();
-    // Provide the file extension as a hint.
-    if let Some(extension) = path.as_ref().extension() {
-        if let Some(extension_str) = extension.to_str() {
-            hint.with_extension(extension_str);
-        }
-    }
-
-    let mss = MediaSourceStream::new(Box::new(file), Default::default());
-
-    // guess the format
-    let probe = symphonia::default::get_probe()
-        .format(&hint, mss, &Default::default(), &Default::default())
-        .expect("failed to prove audio format");
-    let mut format = probe.format;
-
-    let track = format
-        .tracks()
-        .iter()
-        .find(|t| t.codec_params.codec != CODEC_TYPE_NULL)
-        .expect("no supported audio tracks");
+    config: &AudioConfig,
+    cancel: &Option<Arc<AtomicBool>>,
+) -> Result<Vec<u32>, DeckardError> {
+    let max_duration = config.max_duration;
+    let downsample = config.downsample.max(1);
+    let mut format = probe_audio(path.as_ref())?;
+    let track = audio_track(format.as_ref())?;
 
     let dec_opts: DecoderOptions = Default::default();
     let mut decoder = symphonia::default::get_codecs()
         .make(&track.codec_params, &dec_opts)
-        .expect("unsupported codec");
+        .map_err(|e| DeckardError::Audio(format!("unsupported codec: {}", e)))?;
 
     let track_id = track.id;
 
-    let sample_rate = 11025;
+    let sample_rate = track
+        .codec_params
+        .sample_rate
+        .ok_or_else(|| DeckardError::Audio("missing sample rate".to_string()))?;
     let channels = track
         .codec_params
         .channels
-        .expect("missing audio channels")
+        .ok_or_else(|| DeckardError::Audio("missing audio channels".to_string()))?
         .count() as u32;
 
-    let mut printer = Fingerprinter::new(&config);
+    let chroma_config = config.preset.to_configuration();
+    let mut printer = Fingerprinter::new(&chroma_config);
     printer
-        .start(sample_rate, channels)
-        .expect("initializing audio fingerprinter");
+        .start(sample_rate / downsample, channels)
+        .map_err(|e| DeckardError::Audio(format!("initializing fingerprinter: {}", e)))?;
+
+    // interleaved samples to decode before stopping, the whole track when not known
+    let sample_limit =
+        (max_duration > 0).then(|| max_duration * sample_rate as u64 * channels as u64);
+    let mut samples = 0;
 
     let mut sample_buf = None;
 
     loop {
+        if sample_limit.is_some_and(|limit| samples >= limit) {
+            trace!("Fingerprinting {:?} stopped after {}s", path, max_duration);
+            break;
+        }
+        if is_cancelled(cancel) {
+            debug!("Fingerprinting {:?} cancelled", path);
+            return Err(DeckardError::Cancelled);
+        }
         let packet = match format.next_packet() {
             Ok(packet) => packet,
             Err(_) => break,
@@ -191,7 +319,12 @@ pub fn get_audio_hash(
 
                 if let Some(buf) = &mut sample_buf {
                     buf.copy_interleaved_ref(audio_buf);
-                    printer.consume(buf.samples());
+                    if downsample > 1 {
+                        printer.consume(&average_frames(buf.samples(), channels, downsample));
+                    } else {
+                        printer.consume(buf.samples());
+                    }
+                    samples += buf.samples().len() as u64;
                 }
             }
             Err(Error::DecodeError(_)) => (),
@@ -201,5 +334,82 @@ pub fn get_audio_hash(
 
     printer.finish();
 
-    Some(printer.fingerprint().to_vec())
+    Ok(printer.fingerprint().to_vec())
+}
+
+/// Open the file and find the audio format from its header
+fn probe_audio(path: &Path) -> Result<Box<dyn FormatReader>, DeckardError> {
+    let file = std::fs::File::open(path)?;
+
+    let mut hint = Hint::new();
+    // Provide the file extension as a hint.
+    if let Some(extension) = path.extension() {
+        if let Some(extension_str) = extension.to_str() {
+            hint.with_extension(extension_str);
+        }
+    }
+
+    let mss = MediaSourceStream::new(Box::new(file), Default::default());
+
+    // guess the format
+    let probe = symphonia::default::get_probe()
+        .format(&hint, mss, &Default::default(), &Default::default())
+        .map_err(|e| DeckardError::Audio(format!("failed to probe audio format: {}", e)))?;
+    Ok(probe.format)
+}
+
+fn audio_track(format: &dyn FormatReader) -> Result<&Track, DeckardError> {
+    format
+        .tracks()
+        .iter()
+        .find(|t| t.codec_params.codec != CODEC_TYPE_NULL)
+        .ok_or_else(|| DeckardError::Audio("no supported audio tracks".to_string()))
+}
+
+/// Codec and quality of the first audio track, read from the headers without decoding
+pub fn get_audio_info(path: &Path, size: u64) -> Result<AudioInfo, DeckardError> {
+    let format = probe_audio(path)?;
+    let params = &audio_track(format.as_ref())?.codec_params;
+
+    let codec = symphonia::default::get_codecs()
+        .get_codec(params.codec)
+        .map(|c| c.short_name.to_string())
+        .unwrap_or_else(|| "unknown".to_string());
+    let lossless = codec.starts_with("pcm") || LOSSLESS_CODECS.contains(&codec.as_str());
+    let duration = params
+        .n_frames
+        .zip(params.sample_rate)
+        .map(|(frames, rate)| frames as f64 / rate as f64);
+    // average over the whole file, headers and cover art included
+    let bitrate = duration
+        .filter(|d| *d > 0.0)
+        .map(|d| (size as f64 * 8.0 / d / 1000.0) as u32);
+
+    Ok(AudioInfo {
+        codec,
+        lossless,
+        bitrate,
+        sample_rate: params.sample_rate,
+        bits_per_sample: params.bits_per_sample,
+    })
+}
+
+/// Average every `factor` interleaved frames into one, dropping an incomplete last group
+fn average_frames(samples: &[i16], channels: u32, factor: u32) -> Vec<i16> {
+    let channels = channels as usize;
+    let factor = factor as usize;
+    samples
+        .chunks_exact(channels * factor)
+        .flat_map(|group| {
+            (0..channels).map(move |channel| {
+                let sum: i32 = group
+                    .iter()
+                    .skip(channel)
+                    .step_by(channels)
+                    .map(|&s| s as i32)
+                    .sum();
+                (sum / factor as i32) as i16
+            })
+        })
+        .collect()
 }
@@ -0,0 +1,99 @@
+use crate::error::DeckardError;
+use crate::platform::{self, quote};
+use std::path::{Path, PathBuf};
+use std::process::ExitStatus;
+
+/// Command for a whole group, `{group}` expands to all of its paths,
+/// `{id}` to the group number and `{keep}` to the file that would be kept
+pub fn group_command(template: &str, id: usize, keep: &Path, files: &[PathBuf]) -> String {
+    expand(template, |name| match name {
+        "group" => Some(files.iter().map(|f| quote(f)).collect::<Vec<_>>().join(" ")),
+        "id" => Some(id.to_string()),
+        "keep" => Some(quote(keep)),
+        _ => None,
+    })
+}
+
+/// Command for one member of a group, `{path}` expands to the member
+pub fn file_command(template: &str, id: usize, keep: &Path, file: &Path) -> String {
+    expand(template, |name| match name {
+        "path" => Some(quote(file)),
+        "id" => Some(id.to_string()),
+        "keep" => Some(quote(keep)),
+        _ => None,
+    })
+}
+
+/// Replace the placeholders in a single pass, the expanded paths are never
+/// searched for placeholders again
+fn expand(template: &str, value: impl Fn(&str) -> Option<String>) -> String {
+    let mut command = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        command.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let expanded = rest
+            .find('}')
+            .and_then(|end| value(&rest[1..end]).map(|v| (v, end)));
+        match expanded {
+            Some((v, end)) => {
+                command.push_str(&v);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                command.push('{');
+                rest = &rest[1..];
+            }
+        }
+    }
+    command.push_str(rest);
+    command
+}
+
+/// Run the command through the shell
+pub fn run(command: &str) -> Result<ExitStatus, DeckardError> {
+    Ok(platform::shell(command).status()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg(unix)]
+    fn expand_placeholders() {
+        let files = vec![PathBuf::from("/a/one"), PathBuf::from("/b/it's")];
+        let keep = &files[0];
+
+        assert_eq!(
+            group_command("echo {id} {keep} {group}", 3, keep, &files),
+            r"echo 3 '/a/one' '/a/one' '/b/it'\''s'"
+        );
+        assert_eq!(
+            file_command("tag {path} --group {id}", 1, keep, &files[1]),
+            r"tag '/b/it'\''s' --group 1"
+        );
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn placeholders_in_paths_stay_quoted() {
+        let keep = PathBuf::from("/d/a;rm -rf ~");
+        let files = vec![keep.clone(), PathBuf::from("/d/x{keep}{id}")];
+
+        assert_eq!(
+            file_command("rm {path} # {keep}", 2, &keep, &files[1]),
+            "rm '/d/x{keep}{id}' # '/d/a;rm -rf ~'"
+        );
+        assert_eq!(
+            group_command("ls {group} {unknown}", 2, &keep, &files),
+            "ls '/d/a;rm -rf ~' '/d/x{keep}{id}' {unknown}"
+        );
+    }
+
+    #[test]
+    fn run_through_shell() {
+        assert!(run("exit 0").unwrap().success());
+        assert!(!run("exit 3").unwrap().success());
+    }
+}
@@ -0,0 +1,81 @@
+use crate::config::SearchConfig;
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use ignore::Match;
+use log::{trace, warn};
+use std::path::{Path, PathBuf};
+
+/// Name of the ignore file, read from the config directory and from each scan root
+pub const IGNORE_FILE: &str = ".deckardignore";
+
+/// Gitignore style patterns of paths skipped while indexing a scan root
+#[derive(Debug, Clone)]
+pub struct DeckardIgnore {
+    matcher: Gitignore,
+}
+
+impl DeckardIgnore {
+    /// Combine the global ignore file with the one in the root
+    pub fn new(root: &Path) -> Self {
+        Self::from_files(root, &[global_path(), root.join(IGNORE_FILE)])
+    }
+
+    pub fn from_files(root: &Path, files: &[PathBuf]) -> Self {
+        let mut builder = GitignoreBuilder::new(root);
+        for file in files.iter().filter(|f| f.is_file()) {
+            if let Some(e) = builder.add(file) {
+                warn!("failed reading {}: {}", file.to_string_lossy(), e);
+            }
+        }
+        let matcher = builder.build().unwrap_or_else(|e| {
+            warn!("invalid ignore patterns: {}", e);
+            Gitignore::empty()
+        });
+        Self { matcher }
+    }
+
+    /// Check the path and its parent directories against the patterns
+    pub fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        if self.matcher.is_empty() || !path.starts_with(self.matcher.path()) {
+            return false;
+        }
+        match self.matcher.matched_path_or_any_parents(path, is_dir) {
+            Match::Ignore(glob) => {
+                trace!(
+                    "'{}' ignored by pattern '{}' in {}",
+                    path.to_string_lossy(),
+                    glob.original(),
+                    glob.from()
+                        .map(|f| f.to_string_lossy().to_string())
+                        .unwrap_or_default()
+                );
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Ignore file applied to every scan
+pub fn global_path() -> PathBuf {
+    SearchConfig::get_config_path("deckard").with_file_name(IGNORE_FILE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_dir::TestDir;
+    use std::fs;
+
+    #[test]
+    fn ignore_patterns() {
+        let root = TestDir::new("ignore");
+        fs::create_dir_all(root.join("build/nested")).unwrap();
+        fs::write(root.join(IGNORE_FILE), "build/\n*.tmp\n!keep.tmp\n").unwrap();
+
+        let ignore = DeckardIgnore::from_files(&root, &[root.join(IGNORE_FILE)]);
+        assert!(ignore.is_ignored(&root.join("build/nested/file"), false));
+        assert!(ignore.is_ignored(&root.join("notes.tmp"), false));
+        assert!(!ignore.is_ignored(&root.join("keep.tmp"), false));
+        assert!(!ignore.is_ignored(&root.join("notes.txt"), false));
+    }
+}
@@ -1,35 +1,142 @@
 use jwalk::Parallelism;
 use rayon::iter::ParallelIterator;
-use rayon::iter::{IntoParallelRefIterator, ParallelBridge};
 use rayon::prelude::*;
-use rayon::ThreadPool;
 
-use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::atomic::AtomicBool;
+use std::sync::mpsc::{channel, Receiver, Sender};
 use std::sync::{Arc, Mutex};
 
-use crate::config::SearchConfig;
-use crate::file::{EntryType, FileEntry};
+use crate::cache::HashCache;
+use crate::config::{
+    CompareScope, ComparisonStrategy, Isolate, NameMatch, ReadOrder, SearchConfig, Transitivity,
+};
+use crate::error::DeckardError;
+use crate::file::{get_mime_type, EntryType, FileEntry};
+use crate::hasher::is_cancelled;
+use crate::ignores::DeckardIgnore;
+use crate::limiter::{IoThrottle, ReaderLimiter};
+use crate::metrics::{Metrics, Stage};
+use crate::pattern::Pattern;
+use crate::platform;
+use crate::progress::{ProgressCallback, ProgressTracker, ScanPhase};
+#[cfg(feature = "sqlite")]
+use crate::spill::SpillStore;
+use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet};
+use std::io::{BufReader, BufWriter};
+use std::time::{Duration, Instant};
 use std::{fs, path::Path, path::PathBuf};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use tracing::{field, info_span};
 
 use log::{debug, error, trace, warn};
 
+/// Files handed to each thread between two checks for requested files
+const PRIORITY_CHUNK_PER_THREAD: usize = 16;
+/// Shortest time between two checkpoints of the processed files
+const CHECKPOINT_INTERVAL: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct ScanStats {
+    /// Number of files read in parallel at the end of processing
+    pub readers: usize,
+    /// Read throughput measured while processing, in bytes per second
+    pub read_throughput: f64,
+    /// Files served from the hash cache instead of being read
+    pub cache_hits: usize,
+    /// Files deleted or modified while they were processed
+    pub changed: usize,
+    /// FIFOs, sockets and device nodes left out of the scan
+    pub special_files: usize,
+    /// The scan was cancelled, the results only cover the files processed until then
+    #[serde(default)]
+    pub cancelled: bool,
+}
+
+/// Outcome of one scan phase
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct PhaseSummary {
+    pub files: usize,
+    pub errors: usize,
+    /// Files served from the hash cache
+    pub cached: usize,
+    /// Files deleted or modified while they were processed, left out of the results
+    pub changed: usize,
+}
+
+/// Requested file and where to send its duplicates
+type PriorityRequest = (PathBuf, Sender<HashSet<PathBuf>>);
+
+/// Files the user wants processed before the rest of the scan
 #[derive(Debug, Default, Clone)]
+pub struct PriorityQueue {
+    requests: Arc<Mutex<Vec<PriorityRequest>>>,
+}
+
+impl PriorityQueue {
+    /// Process the file and the others of the same size next.
+    /// The receiver gets the duplicates of the file as soon as they are known.
+    pub fn request(&self, path: PathBuf) -> Receiver<HashSet<PathBuf>> {
+        let (sender, receiver) = channel();
+        self.requests.lock().unwrap().push((path, sender));
+        receiver
+    }
+
+    fn take(&self) -> Vec<PriorityRequest> {
+        std::mem::take(&mut *self.requests.lock().unwrap())
+    }
+}
+
+/// Progress of a scan started with `FileIndex::scan_stream`
+#[derive(Debug)]
+pub enum ScanEvent {
+    FileIndexed(PathBuf),
+    FileProcessed(PathBuf),
+    /// Processing results taken from the hash cache
+    FileCached(PathBuf),
+    DuplicateFound(PathBuf, PathBuf),
+    Error(PathBuf, String),
+    /// The finished index, always the last event
+    Done(Box<FileIndex>),
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct FileIndex {
     pub dirs: HashSet<PathBuf>,
     // TODO: Try BTreeMap
     pub files: HashMap<PathBuf, FileEntry>,
     pub duplicates: HashMap<PathBuf, HashSet<PathBuf>>,
     pub config: SearchConfig,
+    pub stats: ScanStats,
+    /// Files that could not be read, with the reason
+    pub errors: HashMap<PathBuf, String>,
+    /// Files and directories skipped for lack of permissions, also listed in `errors`
+    #[serde(default)]
+    pub denied: HashSet<PathBuf>,
+    /// Hashes of unchanged files are taken from the cache when set
+    #[serde(skip)]
+    pub cache: Option<HashCache>,
+    /// The processed files are saved to this cache file while processing and
+    /// when cancelled, so an interrupted scan can resume from it. The file is
+    /// removed once every file is processed.
+    #[serde(skip)]
+    pub checkpoint: Option<PathBuf>,
+    #[serde(skip)]
+    metrics: Option<Arc<Metrics>>,
+    #[serde(skip)]
+    events: Option<UnboundedSender<ScanEvent>>,
+    #[serde(skip)]
+    priority: PriorityQueue,
 }
 
 impl FileIndex {
     pub fn new(dirs: HashSet<PathBuf>, config: SearchConfig) -> Self {
         // Define number of threads to use
-        if let Err(e) = rayon::ThreadPoolBuilder::new()
-            .num_threads(config.threads)
-            .build_global()
-        {
+        let mut pool = rayon::ThreadPoolBuilder::new().num_threads(config.threads);
+        if config.nice_io {
+            pool = pool.start_handler(|_| platform::lower_priority());
+        }
+        if let Err(e) = pool.build_global() {
             error!("error building thread pool: {:?}", e);
         }
         debug!(
@@ -42,38 +149,145 @@ impl FileIndex {
             files: HashMap::new(),
             duplicates: HashMap::new(),
             config,
+            stats: ScanStats::default(),
+            errors: HashMap::new(),
+            denied: HashSet::new(),
+            cache: None,
+            checkpoint: None,
+            metrics: None,
+            events: None,
+            priority: PriorityQueue::default(),
         }
     }
 
-    pub fn index_dirs(&mut self) {
+    /// Record the time spent in each stage of the scan
+    pub fn collect_metrics(&mut self) {
+        self.metrics = Some(Arc::new(Metrics::default()));
+    }
+
+    /// Timings of the scan, when collected
+    pub fn metrics(&self) -> Option<&Metrics> {
+        self.metrics.as_deref()
+    }
+
+    /// Handle to request files to be processed first, usable while the scan runs
+    pub fn priority_queue(&self) -> PriorityQueue {
+        self.priority.clone()
+    }
+
+    /// Scan in a background thread and stream the progress as events.
+    /// The receiver can be awaited with `recv().await` or read with `blocking_recv()`.
+    pub fn scan_stream(mut self, cancel: Option<Arc<AtomicBool>>) -> UnboundedReceiver<ScanEvent> {
+        let (sender, receiver) = unbounded_channel();
+
+        std::thread::spawn(move || {
+            self.events = Some(sender.clone());
+            self.index_dirs();
+            self.process_files(cancel.clone(), None);
+            self.find_duplicates(cancel, None);
+            self.events = None;
+            let _ = sender.send(ScanEvent::Done(Box::new(self)));
+        });
+
+        receiver
+    }
+
+    pub fn index_dirs(&mut self) -> PhaseSummary {
+        let span = info_span!(
+            "index_dirs",
+            dirs = self.dirs.len(),
+            files = field::Empty,
+            bytes = field::Empty,
+            errors = field::Empty
+        );
+        let _entered = span.enter();
+        // only writing to a spill store can fail
+        let summary = self.walk_dirs(None).unwrap_or_default();
+        span.record("files", summary.files);
+        span.record("bytes", self.files.values().map(|f| f.size).sum::<u64>());
+        span.record("errors", summary.errors);
+        summary
+    }
+
+    /// Index the files into memory, or into the spill store when given
+    fn walk_dirs(
+        &mut self,
+        mut spill: Option<&mut SpillStore>,
+    ) -> Result<PhaseSummary, DeckardError> {
+        let mut spilled = 0;
+        let mut errors: Vec<(PathBuf, String)> = Vec::new();
+        let mut denied: Vec<PathBuf> = Vec::new();
+        let mut special_files = 0;
+        let exclude_filter = self.config.exclude_filter.as_deref().map(filter_pattern);
+        let include_filter = self.config.include_filter.as_deref().map(filter_pattern);
+
         for dir in &self.dirs {
-            let index: HashMap<PathBuf, FileEntry> = jwalk::WalkDir::new(dir)
+            let start = Instant::now();
+            let ignores = DeckardIgnore::new(dir);
+            // device of the root, when traversal has to stay on its file system
+            let root_dev = self
+                .config
+                .same_file_system
+                .then(|| fs::metadata(dir).ok().and_then(|m| platform::device(&m)))
+                .flatten();
+            let entries = jwalk::WalkDir::new(dir)
                 .parallelism(Parallelism::RayonNewPool(self.config.threads))
                 .sort(false)
                 .skip_hidden(self.config.skip_hidden)
+                .process_read_dir(move |_, _, _, children| {
+                    let Some(root_dev) = root_dev else {
+                        return;
+                    };
+                    for entry in children.iter_mut().flatten() {
+                        if entry.read_children_path.is_none() {
+                            continue;
+                        }
+                        let dev = entry.metadata().ok().and_then(|m| platform::device(&m));
+                        if dev.is_some_and(|dev| dev != root_dev) {
+                            debug!("not crossing into mount point {:?}", entry.path());
+                            entry.read_children_path = None;
+                        }
+                    }
+                })
                 .into_iter()
                 .filter_map(|entry| {
                     match entry {
                         Ok(entry) => {
                             let path = entry.path();
 
+                            if platform::is_special(&entry.file_type()) {
+                                trace!("Skipping special file {}", path.to_string_lossy());
+                                special_files += 1;
+                                return None;
+                            }
                             if path.is_file() && !path.is_symlink() {
+                                if ignores.is_ignored(&path, false) {
+                                    return None;
+                                }
+                                let metadata = match entry.metadata() {
+                                    Ok(metadata) => metadata,
+                                    Err(e) => {
+                                        warn!("failed reading metadata {}", e);
+                                        emit(
+                                            &self.events,
+                                            ScanEvent::Error(path.clone(), e.to_string()),
+                                        );
+                                        if e.io_error().is_some_and(permission_denied) {
+                                            denied.push(path.clone());
+                                        }
+                                        errors.push((path, e.to_string()));
+                                        return None;
+                                    }
+                                };
                                 let file = FileEntry::new(
                                     path.to_owned(),
                                     entry.file_name.to_owned(),
-                                    entry.metadata().unwrap(),
+                                    metadata,
                                 );
                                 if file.file_type == EntryType::File {
                                     // Check filename filter
-                                    if let Some(exclude_filter) =
-                                        self.config.exclude_filter.as_ref()
-                                    {
-                                        if entry
-                                            .file_name()
-                                            .to_string_lossy()
-                                            .to_lowercase()
-                                            .contains(&exclude_filter.to_lowercase())
-                                        {
+                                    if let Some(exclude_filter) = exclude_filter.as_ref() {
+                                        if exclude_filter.matches(&path) {
                                             trace!(
                                                 "File '{}' matches exclude filter pattern '{}'",
                                                 entry.file_name().to_string_lossy(),
@@ -82,15 +296,8 @@ impl FileIndex {
                                             return None;
                                         }
                                     }
-                                    if let Some(include_filter) =
-                                        self.config.include_filter.as_ref()
-                                    {
-                                        if !entry
-                                            .file_name()
-                                            .to_string_lossy()
-                                            .to_lowercase()
-                                            .contains(&include_filter.to_lowercase())
-                                        {
+                                    if let Some(include_filter) = include_filter.as_ref() {
+                                        if !include_filter.matches(&path) {
                                             return None;
                                         } else {
                                             trace!(
@@ -101,94 +308,604 @@ impl FileIndex {
                                         }
                                     }
                                     // Skip empty files
-                                    if self.config.skip_empty
-                                        && entry.metadata().unwrap().len() == 0
-                                    {
+                                    if self.config.skip_empty && file.size == 0 {
                                         trace!(
                                             "Skipping empty file {}",
                                             entry.path().to_string_lossy()
                                         );
                                         return None;
                                     }
+                                    // by extension, then by the leading bytes
+                                    if let Some(media) = self.config.media {
+                                        if !get_mime_type(&path).is_ok_and(|m| media.matches(&m)) {
+                                            trace!(
+                                                "Skipping {} not matching {:?}",
+                                                path.to_string_lossy(),
+                                                media
+                                            );
+                                            return None;
+                                        }
+                                    }
+                                    emit(&self.events, ScanEvent::FileIndexed(path.clone()));
                                     return Some((path, file));
                                 }
                             }
                         }
                         Err(e) => {
                             warn!("failed reading file {}", e);
+                            let path = e.path().map(|p| p.to_path_buf()).unwrap_or_default();
+                            emit(&self.events, ScanEvent::Error(path.clone(), e.to_string()));
+                            if e.io_error().is_some_and(permission_denied) {
+                                denied.push(path.clone());
+                            }
+                            errors.push((path, e.to_string()));
                         }
                     }
                     None
-                })
-                .collect();
-            self.files.extend(index);
+                });
+            match spill.as_deref_mut() {
+                Some(store) => spilled += store.insert(entries.map(|(_, file)| file))?,
+                None => {
+                    let index: HashMap<PathBuf, FileEntry> = entries.collect();
+                    self.files.extend(index);
+                }
+            }
+            if let Some(metrics) = &self.metrics {
+                metrics.record(Stage::Indexing, dir, start.elapsed());
+            }
         }
+
+        let summary = PhaseSummary {
+            files: self.files_len() + spilled,
+            errors: errors.len(),
+            ..Default::default()
+        };
+        self.errors.extend(errors);
+        self.denied.extend(denied);
+        self.stats.special_files += special_files;
+        Ok(summary)
     }
 
-    pub fn process_files(&mut self, callback: Option<Arc<dyn Fn(usize, usize) + Send + Sync>>) {
-        let counter = Arc::new(AtomicUsize::new(0));
-        let total = self.files_len();
+    /// Scan with the indexed files spilled to a temporary database, processing
+    /// the files one size at a time. Only the duplicates are kept in memory, so
+    /// images and audio are not compared across sizes.
+    #[cfg(feature = "sqlite")]
+    pub fn scan_low_memory(
+        &mut self,
+        cancel: Option<Arc<AtomicBool>>,
+    ) -> Result<PhaseSummary, DeckardError> {
+        let mut store = SpillStore::new()?;
+        let mut summary = self.walk_dirs(Some(&mut store))?;
 
-        self.files.values_mut().par_bridge().for_each(|f| {
-            f.process(&self.config);
-            if let Some(ref callback) = callback {
-                let count = counter.fetch_add(1, Ordering::SeqCst) + 1;
-                callback(count, total);
+        for size in store.duplicate_sizes()? {
+            if is_cancelled(&cancel) {
+                debug!("Scan cancelled, keeping partial results");
+                self.stats.cancelled = true;
+                break;
             }
-        });
+            let mut bucket = FileIndex {
+                dirs: self.dirs.clone(),
+                files: store.bucket(size)?,
+                config: self.config.clone(),
+                cache: self.cache.take(),
+                metrics: self.metrics.clone(),
+                events: self.events.clone(),
+                ..Default::default()
+            };
+            let processed = bucket.process_files(cancel.clone(), None);
+            bucket.find_duplicates(cancel.clone(), None);
+            self.cache = bucket.cache.take();
+
+            summary.errors += processed.errors;
+            summary.cached += processed.cached;
+            summary.changed += processed.changed;
+            let duplicates = &bucket.duplicates;
+            bucket.files.retain(|path, _| duplicates.contains_key(path));
+            self.absorb(bucket);
+        }
+        Ok(summary)
     }
 
-    pub fn find_duplicates(&mut self, callback: Option<Arc<dyn Fn(usize, usize) + Send + Sync>>) {
-        let vec_files: Vec<&FileEntry> = self.files.values().into_iter().collect();
+    pub fn process_files(
+        &mut self,
+        cancel: Option<Arc<AtomicBool>>,
+        callback: Option<ProgressCallback>,
+    ) -> PhaseSummary {
+        // files with a unique name can't match when names have to be the same
+        let unique_names: HashSet<String> = if self.config.name_match == NameMatch::Same {
+            let mut names: HashMap<&str, usize> = HashMap::new();
+            for file in self.files.values() {
+                *names.entry(&file.name).or_default() += 1;
+            }
+            names
+                .into_iter()
+                .filter(|(_, count)| *count == 1)
+                .map(|(name, _)| name.to_string())
+                .collect()
+        } else {
+            HashSet::new()
+        };
+        let total = self
+            .files
+            .values()
+            .filter(|f| !unique_names.contains(&f.name))
+            .count();
+        let total_bytes = self
+            .files
+            .values()
+            .filter(|f| !unique_names.contains(&f.name))
+            .map(|f| f.size)
+            .sum();
+        let span = info_span!(
+            "process_files",
+            files = total,
+            bytes = total_bytes,
+            cached = field::Empty,
+            errors = field::Empty
+        );
+        let _entered = span.enter();
+        let progress = ProgressTracker::new(ScanPhase::Processing, total, total_bytes, callback)
+            .with_interval(Duration::from_millis(self.config.progress_interval));
+        // every reader runs on a thread of the pool, more couldn't read at once
+        let limiter = ReaderLimiter::new(rayon::current_num_threads());
+        let throttle = self
+            .config
+            .nice_io
+            .then(|| IoThrottle::new(self.config.io_limit));
+        let errors: Mutex<Vec<(PathBuf, String)>> = Mutex::new(Vec::new());
+        let changed: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+        let denied: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+        let sequential = self.sequential_read();
+
+        let process_file = |f: &mut FileEntry| {
+            if is_cancelled(&cancel) {
+                return;
+            }
+            if self.cache.as_ref().is_some_and(|c| c.apply(f)) {
+                emit(&self.events, ScanEvent::FileCached(f.path.clone()));
+                progress.advance_cached(&f.path, f.size);
+                return;
+            }
+            let result = if self.config.adaptive_threads && !sequential {
+                let _permit = limiter.acquire();
+                let result = f.process(&self.config, &cancel, self.metrics.as_deref());
+                limiter.record(bytes_read(&self.config, f));
+                result
+            } else {
+                f.process(&self.config, &cancel, self.metrics.as_deref())
+            };
+            if let Some(throttle) = &throttle {
+                throttle.consume(bytes_read(&self.config, f));
+            }
+            match result {
+                Ok(()) => emit(&self.events, ScanEvent::FileProcessed(f.path.clone())),
+                Err(DeckardError::Cancelled) => {}
+                Err(e) if FileEntry::changed_during_scan(&e) => {
+                    debug!("dropping {}: {}", f.path.to_string_lossy(), e);
+                    changed.lock().unwrap().push(f.path.clone());
+                }
+                Err(e) => {
+                    warn!("failed processing {}: {}", f.path.to_string_lossy(), e);
+                    if matches!(&e, DeckardError::Io(e) if permission_denied(e)) {
+                        denied.lock().unwrap().push(f.path.clone());
+                    }
+                    emit(
+                        &self.events,
+                        ScanEvent::Error(f.path.clone(), e.to_string()),
+                    );
+                    errors.lock().unwrap().push((f.path.clone(), e.to_string()));
+                }
+            }
+            progress.advance(&f.path, 1, f.size);
+        };
+
+        let mut files: Vec<&mut FileEntry> = self
+            .files
+            .values_mut()
+            .filter(|f| !unique_names.contains(&f.name))
+            .collect();
+        if sequential {
+            // keep the disk head moving in one direction
+            files.sort_by(|a, b| a.inode.cmp(&b.inode).then(a.path.cmp(&b.path)));
+        }
 
-        let counter = Arc::new(AtomicUsize::new(0));
-        let total = vec_files.len() * (vec_files.len() - 1) / 2;
+        // sizes of the files processed ahead of the rest
+        let mut served: HashSet<u64> = HashSet::new();
+        // work in chunks to serve the requested files in between
+        let chunk_size = rayon::current_num_threads() * PRIORITY_CHUNK_PER_THREAD;
+        let mut start = 0;
+        let mut last_checkpoint = Instant::now();
 
-        for i in 0..vec_files.len() {
-            for j in i + 1..vec_files.len() {
-                let this_file = vec_files[i];
-                let other_file = vec_files[j];
+        loop {
+            for (path, reply) in self.priority.take() {
+                let Some(size) = files.iter().find(|f| f.path == path).map(|f| f.size) else {
+                    continue;
+                };
+                // only files of the same size can be exact copies
+                if served.insert(size) {
+                    debug!("Processing {} first", path.to_string_lossy());
+                    files[start..]
+                        .iter_mut()
+                        .filter(|f| f.size == size)
+                        .for_each(|f| process_file(f));
+                }
 
-                // check if the files are matching
-                if this_file.compare(other_file, &self.config) {
-                    match self.duplicates.get_mut(&this_file.path) {
-                        // file already exists, add another duplicate
-                        Some(this) => {
-                            this.insert(other_file.path.clone());
+                let bucket: Vec<&FileEntry> = files
+                    .iter()
+                    .filter(|f| f.size == size)
+                    .map(|f| &**f)
+                    .collect();
+                if let Some(this) = bucket.iter().find(|f| f.path == path) {
+                    let this_scope = scope_of(self.config.compare_scope, &self.dirs, &this.path);
+                    let this_unit = isolation_of(self.config.isolate, &self.dirs, &this.path);
+                    for other in &bucket {
+                        if other.path != this.path
+                            && scope_of(self.config.compare_scope, &self.dirs, &other.path)
+                                == this_scope
+                            && !isolated(
+                                &this_unit,
+                                &isolation_of(self.config.isolate, &self.dirs, &other.path),
+                            )
+                            && names_match(self.config.name_match, this, other)
+                            && this.compare(other, &self.config)
+                        {
+                            deckard_core::link(&mut self.duplicates, &this.path, &other.path);
                         }
-                        // insert a new entry
-                        None => {
-                            self.duplicates.insert(
+                    }
+                }
+                let _ = reply.send(self.duplicates.get(&path).cloned().unwrap_or_default());
+            }
+
+            if start >= files.len() || is_cancelled(&cancel) {
+                break;
+            }
+            if let Some(checkpoint) = &self.checkpoint {
+                if last_checkpoint.elapsed() >= CHECKPOINT_INTERVAL {
+                    save_checkpoint(checkpoint, &self.config, &files);
+                    last_checkpoint = Instant::now();
+                }
+            }
+            let end = (start + chunk_size).min(files.len());
+            if sequential {
+                files[start..end]
+                    .iter_mut()
+                    .filter(|f| !served.contains(&f.size))
+                    .for_each(|f| process_file(f));
+            } else {
+                files[start..end]
+                    .par_iter_mut()
+                    .filter(|f| !served.contains(&f.size))
+                    .for_each(|f| process_file(f));
+            }
+            start = end;
+        }
+
+        if let Some(checkpoint) = &self.checkpoint {
+            if is_cancelled(&cancel) {
+                save_checkpoint(checkpoint, &self.config, &files);
+            } else {
+                let _ = fs::remove_file(checkpoint);
+            }
+        }
+
+        self.stats.readers = if sequential {
+            1
+        } else if self.config.adaptive_threads {
+            limiter.limit()
+        } else {
+            rayon::current_num_threads()
+        };
+        self.stats.read_throughput = limiter.throughput();
+        debug!(
+            "Processed files with {} parallel readers",
+            self.stats.readers
+        );
+
+        // unreadable files can't be compared
+        let errors = errors.into_inner().unwrap();
+        let changed = changed.into_inner().unwrap();
+        let summary = PhaseSummary {
+            files: total - errors.len() - changed.len(),
+            errors: errors.len(),
+            cached: progress.cached(),
+            changed: changed.len(),
+        };
+        for (path, e) in errors {
+            self.files.remove(&path);
+            self.errors.insert(path, e);
+        }
+        for path in changed {
+            self.files.remove(&path);
+        }
+        self.denied.extend(denied.into_inner().unwrap());
+        self.stats.changed += summary.changed;
+        self.stats.cancelled |= is_cancelled(&cancel);
+
+        if let Some(cache) = self.cache.as_mut() {
+            for file in self.files.values() {
+                cache.insert(file);
+            }
+        }
+        self.stats.cache_hits = summary.cached;
+        span.record("cached", summary.cached);
+        span.record("errors", summary.errors);
+        summary
+    }
+
+    /// Find the copies of a single file among the indexed files, only files
+    /// of the same size are processed and no other pairs are compared
+    pub fn find_copies_of(
+        &mut self,
+        target: &Path,
+        cancel: Option<Arc<AtomicBool>>,
+    ) -> Result<Vec<PathBuf>, DeckardError> {
+        let target = fs::canonicalize(target)?;
+        let name = target.file_name().unwrap_or_default().to_os_string();
+        let mut entry = FileEntry::new(target.clone(), name, fs::metadata(&target)?);
+        entry.process(&self.config, &cancel, self.metrics.as_deref())?;
+
+        self.files
+            .retain(|path, f| f.size == entry.size && *path != target);
+        self.process_files(cancel, None);
+
+        let mut copies: Vec<PathBuf> = self
+            .files
+            .values()
+            .filter(|f| f.processed && entry.compare(f, &self.config))
+            .map(|f| f.path.clone())
+            .collect();
+        copies.sort();
+        Ok(copies)
+    }
+
+    /// Compare all processed files, when cancelled the duplicates found so far are kept
+    pub fn find_duplicates(
+        &mut self,
+        cancel: Option<Arc<AtomicBool>>,
+        callback: Option<ProgressCallback>,
+    ) -> PhaseSummary {
+        let span = info_span!(
+            "find_duplicates",
+            files = self.files_len(),
+            duplicates = field::Empty,
+            bytes = field::Empty
+        );
+        let _entered = span.enter();
+        let vec_files: Vec<&FileEntry> = self.files.values().collect();
+        let scopes: Vec<Option<PathBuf>> = vec_files
+            .iter()
+            .map(|f| scope_of(self.config.compare_scope, &self.dirs, &f.path))
+            .collect();
+        let units: Vec<Option<PathBuf>> = vec_files
+            .iter()
+            .map(|f| isolation_of(self.config.isolate, &self.dirs, &f.path))
+            .collect();
+
+        let candidates = comparison_candidates(&vec_files, &self.config);
+        let total = candidates
+            .iter()
+            .map(|(set, _)| set.len() * set.len().saturating_sub(1) / 2)
+            .sum();
+        let progress = ProgressTracker::new(ScanPhase::Comparing, total, 0, callback)
+            .with_interval(Duration::from_millis(self.config.progress_interval));
+
+        'sets: for (set, perceptual) in &candidates {
+            for (n, &i) in set.iter().enumerate() {
+                if is_cancelled(&cancel) {
+                    debug!("Comparison cancelled, keeping partial results");
+                    self.stats.cancelled = true;
+                    break 'sets;
+                }
+                let start = Instant::now();
+                for &j in &set[n + 1..] {
+                    if scopes[i] != scopes[j] || isolated(&units[i], &units[j]) {
+                        continue;
+                    }
+                    let this_file = vec_files[i];
+                    let other_file = vec_files[j];
+                    if !names_match(self.config.name_match, this_file, other_file) {
+                        continue;
+                    }
+                    // exact copies were already compared within their bucket
+                    if *perceptual
+                        && this_file.hash.is_some()
+                        && bucket_of(this_file) == bucket_of(other_file)
+                    {
+                        continue;
+                    }
+
+                    // check if the files are matching
+                    if this_file.compare(other_file, &self.config) {
+                        emit(
+                            &self.events,
+                            ScanEvent::DuplicateFound(
                                 this_file.path.clone(),
-                                HashSet::from([other_file.path.clone()]),
-                            );
-                        }
-                    };
-                    // backlink this to the other file
-                    match self.duplicates.get_mut(&other_file.path) {
-                        // file already exists, add another duplicate
-                        Some(other) => {
-                            other.insert(this_file.path.clone());
-                        }
-                        // insert a new entry
-                        None => {
-                            self.duplicates.insert(
                                 other_file.path.clone(),
-                                HashSet::from([this_file.path.clone()]),
-                            );
-                        }
-                    };
+                            ),
+                        );
+                        deckard_core::link(&mut self.duplicates, &this_file.path, &other_file.path);
+                    }
+                }
+                progress.advance(&vec_files[i].path, set.len() - n - 1, 0);
+                if let Some(metrics) = &self.metrics {
+                    metrics.record(Stage::Comparing, &vec_files[i].path, start.elapsed());
+                }
+            }
+        }
+
+        span.record("duplicates", self.duplicates_len());
+        span.record("bytes", self.duplicates_size());
+        PhaseSummary {
+            files: self.duplicates_len(),
+            ..Default::default()
+        }
+    }
+
+    fn sequential_read(&self) -> bool {
+        match self.config.read_order {
+            ReadOrder::Parallel => false,
+            ReadOrder::Sequential => true,
+            ReadOrder::Auto => {
+                let rotational = self.dirs.iter().any(|d| is_rotational(d));
+                if rotational {
+                    debug!("Rotational disk detected, reading files sequentially");
+                }
+                rotational
+            }
+        }
+    }
+
+    /// Combine with a separately scanned index, like one exported on another
+    /// machine, and find the duplicates across both. The duplicates within
+    /// each index are kept, only pairs of files from different indexes are
+    /// compared. Files of the other index replace the ones with the same path.
+    pub fn merge(&mut self, other: FileIndex) {
+        let ours: Vec<PathBuf> = self
+            .files
+            .keys()
+            .filter(|p| !other.files.contains_key(*p))
+            .cloned()
+            .collect();
+        let theirs: Vec<PathBuf> = other.files.keys().cloned().collect();
+        for path in &theirs {
+            self.forget(path);
+        }
+        self.absorb(other);
+
+        // only files sharing a bucket can match, ours come before theirs
+        let split = ours.len();
+        let scoped = self.scoped(&[ours, theirs].concat());
+        let vec_files: Vec<&FileEntry> = scoped.iter().map(|(f, _, _)| *f).collect();
+        let mut found = Vec::new();
+        for (set, perceptual) in comparison_candidates(&vec_files, &self.config) {
+            let (theirs, ours): (Vec<usize>, Vec<usize>) =
+                set.into_iter().partition(|&i| i >= split);
+            for &i in &ours {
+                for &j in &theirs {
+                    let (a, a_scope, a_unit) = &scoped[i];
+                    let (b, b_scope, b_unit) = &scoped[j];
+                    if a_scope != b_scope
+                        || isolated(a_unit, b_unit)
+                        || !names_match(self.config.name_match, a, b)
+                    {
+                        continue;
+                    }
+                    // exact copies were already compared within their bucket
+                    if perceptual && a.hash.is_some() && bucket_of(a) == bucket_of(b) {
+                        continue;
+                    }
+                    if a.compare(b, &self.config) {
+                        found.push((a.path.clone(), b.path.clone()));
+                    }
                 }
+            }
+        }
+        for (a, b) in found {
+            deckard_core::link(&mut self.duplicates, &a, &b);
+        }
+    }
+
+    /// Entries of the paths with their compare scope and isolation unit
+    fn scoped(&self, paths: &[PathBuf]) -> Vec<(&FileEntry, Option<PathBuf>, Option<PathBuf>)> {
+        paths
+            .iter()
+            .filter_map(|p| self.files.get(p))
+            .map(|f| {
+                (
+                    f,
+                    scope_of(self.config.compare_scope, &self.dirs, &f.path),
+                    isolation_of(self.config.isolate, &self.dirs, &f.path),
+                )
+            })
+            .collect()
+    }
 
-                // Update the progress counter
-                if let Some(ref callback) = callback {
-                    let count = counter.fetch_add(1, Ordering::SeqCst) + 1;
-                    callback(count, total);
+    /// Drop the links of a file replaced by a merged index
+    fn forget(&mut self, path: &Path) {
+        if let Some(copies) = self.duplicates.remove(path) {
+            for copy in copies {
+                if let Some(links) = self.duplicates.get_mut(&copy) {
+                    links.remove(path);
+                    if links.is_empty() {
+                        self.duplicates.remove(&copy);
+                    }
                 }
             }
         }
     }
 
+    /// Combine processed shards and compare all their files in a single pass
+    pub fn merge_shards(&mut self, shards: Vec<FileIndex>) {
+        for shard in shards {
+            self.absorb(shard);
+        }
+        self.find_duplicates(None, None);
+    }
+
+    fn absorb(&mut self, other: FileIndex) {
+        self.dirs.extend(other.dirs);
+        self.files.extend(other.files);
+        self.errors.extend(other.errors);
+        self.denied.extend(other.denied);
+        for (file, copies) in other.duplicates {
+            self.duplicates.entry(file).or_default().extend(copies);
+        }
+        self.stats.readers = self.stats.readers.max(other.stats.readers);
+        self.stats.read_throughput = self.stats.read_throughput.max(other.stats.read_throughput);
+        self.stats.cache_hits += other.stats.cache_hits;
+        self.stats.changed += other.stats.changed;
+        self.stats.special_files += other.stats.special_files;
+        self.stats.cancelled |= other.stats.cancelled;
+    }
+
+    /// Split the indexed files into shards that can be processed by separate workers.
+    /// Files of the same size always land in the same shard, the shards are
+    /// balanced by the number of bytes they hold.
+    pub fn shard(&self, count: usize) -> Vec<FileIndex> {
+        let count = count.max(1);
+
+        let mut buckets: HashMap<u64, Vec<&FileEntry>> = HashMap::new();
+        for file in self.files.values() {
+            buckets.entry(file.size).or_default().push(file);
+        }
+        let mut buckets: Vec<(u64, Vec<&FileEntry>)> = buckets.into_iter().collect();
+        // place the heaviest buckets first
+        buckets.sort_by_key(|(size, files)| {
+            std::cmp::Reverse((size.saturating_mul(files.len() as u64), *size))
+        });
+
+        let mut shards: Vec<FileIndex> = (0..count)
+            .map(|_| FileIndex {
+                dirs: self.dirs.clone(),
+                config: self.config.clone(),
+                ..Default::default()
+            })
+            .collect();
+        let mut loads = vec![0u64; count];
+
+        for (size, files) in buckets {
+            let lightest = (0..count).min_by_key(|&i| loads[i]).unwrap_or(0);
+            loads[lightest] += size.saturating_mul(files.len() as u64);
+            shards[lightest]
+                .files
+                .extend(files.into_iter().map(|f| (f.path.clone(), f.clone())));
+        }
+        shards
+    }
+
+    /// Write the index to a JSON file
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), DeckardError> {
+        let writer = BufWriter::new(fs::File::create(path)?);
+        serde_json::to_writer(writer, self)?;
+        Ok(())
+    }
+
+    /// Read an index written by `save`
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<FileIndex, DeckardError> {
+        let reader = BufReader::new(fs::File::open(path)?);
+        Ok(serde_json::from_reader(reader)?)
+    }
+
     pub fn files_len(&self) -> usize {
         self.files.len()
     }
@@ -197,17 +914,713 @@ impl FileIndex {
         self.duplicates.len()
     }
 
+    pub fn errors_len(&self) -> usize {
+        self.errors.len()
+    }
+
+    /// Forget removed files, dropping them from the duplicates of the others
+    pub fn remove_files(&mut self, removed: &HashSet<PathBuf>) {
+        self.files.retain(|f, _| !removed.contains(f));
+        self.duplicates.retain(|f, _| !removed.contains(f));
+        for copies in self.duplicates.values_mut() {
+            copies.retain(|f| !removed.contains(f));
+        }
+        self.duplicates.retain(|_, copies| !copies.is_empty());
+    }
+
+    /// Rename the file on disk and in the index, refusing to replace an
+    /// existing file
+    pub fn rename_file(&mut self, from: &Path, to: PathBuf) -> Result<(), DeckardError> {
+        if to.symlink_metadata().is_ok() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::AlreadyExists,
+                format!("{} already exists", to.to_string_lossy()),
+            )
+            .into());
+        }
+        fs::rename(from, &to)?;
+
+        if let Some(mut file) = self.files.remove(from) {
+            file.set_path(to.clone());
+            self.files.insert(to.clone(), file);
+        }
+        if let Some(copies) = self.duplicates.remove(from) {
+            self.duplicates.insert(to.clone(), copies);
+        }
+        for copies in self.duplicates.values_mut() {
+            if copies.remove(from) {
+                copies.insert(to.clone());
+            }
+        }
+        Ok(())
+    }
+
+    /// Paths skipped for lack of permissions, sorted
+    pub fn denied_paths(&self) -> Vec<&PathBuf> {
+        let mut denied: Vec<&PathBuf> = self.denied.iter().collect();
+        denied.sort();
+        denied
+    }
+
     pub fn file_name(&self, file: &PathBuf) -> Option<String> {
-        self.files.get(file).and_then(|f| Some(f.name.clone()))
+        self.files.get(file).map(|f| f.name.clone())
+    }
+
+    pub fn file_entry(&self, file: &Path) -> Option<&FileEntry> {
+        self.files.get(file)
+    }
+
+    pub fn file_size(&self, file: &Path) -> Option<u64> {
+        self.files.get(file).map(|f| f.size)
+    }
+
+    /// Scan root the file was found in
+    pub fn root_of(&self, file: &Path) -> Option<&PathBuf> {
+        self.dirs.iter().find(|d| file.starts_with(d))
+    }
+
+    /// Short name of the scan root the file was found in
+    pub fn root_label(&self, file: &Path) -> Option<String> {
+        self.root_of(file).map(|root| {
+            root.file_name()
+                .map(|n| n.to_string_lossy().to_string())
+                .unwrap_or_else(|| root.to_string_lossy().to_string())
+        })
+    }
+
+    /// Group files that are connected through their duplicates
+    pub fn duplicate_groups(&self) -> Vec<Vec<PathBuf>> {
+        let threshold = self.config.chain_confidence;
+        if self.config.transitivity == Transitivity::Strict || threshold == 0 {
+            return deckard_core::groups_with(&self.duplicates, self.config.transitivity);
+        }
+        deckard_core::groups_chaining(&self.duplicates, |a, b| {
+            match (self.files.get(a), self.files.get(b)) {
+                (Some(a), Some(b)) => a.confidence(b, &self.config).unwrap_or(100) >= threshold,
+                _ => true,
+            }
+        })
+    }
+
+    /// Total size of all files that have at least one duplicate
+    pub fn duplicates_size(&self) -> u64 {
+        self.duplicates
+            .keys()
+            .filter_map(|f| self.file_size(f))
+            .sum()
+    }
+
+    /// Space freed by keeping only the largest file of every duplicate group
+    pub fn reclaimable_size(&self) -> u64 {
+        deckard_core::reclaimable_size(&self.duplicate_groups(), |f| self.file_size(f))
+    }
+}
+
+/// Sets of files whose every pair is compared following the comparison
+/// strategy, flagged when they are compared for perceptual matches
+fn comparison_candidates(files: &[&FileEntry], config: &SearchConfig) -> Vec<(Vec<usize>, bool)> {
+    if config.comparison_strategy == ComparisonStrategy::Pairwise {
+        return vec![((0..files.len()).collect(), false)];
+    }
+
+    let mut buckets: HashMap<(u64, &str), Vec<usize>> = HashMap::new();
+    for (i, file) in files.iter().enumerate() {
+        if let (size, Some(hash)) = bucket_of(file) {
+            buckets.entry((size, hash)).or_default().push(i);
+        }
+    }
+    let mut candidates: Vec<(Vec<usize>, bool)> = buckets
+        .into_values()
+        .filter(|bucket| bucket.len() > 1)
+        .map(|bucket| (bucket, false))
+        .collect();
+
+    if config.comparison_strategy == ComparisonStrategy::Hybrid {
+        let of_kind = |kind: &str, has_hash: fn(&FileEntry) -> bool| -> Vec<usize> {
+            (0..files.len())
+                .filter(|&i| {
+                    files[i]
+                        .mime_type
+                        .as_ref()
+                        .is_some_and(|m| m.contains(kind))
+                        && has_hash(files[i])
+                })
+                .collect()
+        };
+        if config.image_config.compare {
+            candidates.push((of_kind("image", |f| f.image_hash.is_some()), true));
+        }
+        if config.audio_config.compare {
+            candidates.push((of_kind("audio", |f| f.audio_hash.is_some()), true));
+        }
+    }
+    candidates
+}
+
+/// Size and quick hash, files can only have the same content when they share both
+fn bucket_of(file: &FileEntry) -> (u64, Option<&str>) {
+    (file.size, file.hash.as_deref())
+}
+
+/// Directory the file is compared within, `None` when comparing across all files
+fn scope_of(scope: CompareScope, dirs: &HashSet<PathBuf>, path: &Path) -> Option<PathBuf> {
+    match scope {
+        CompareScope::All => None,
+        CompareScope::Directory => path.parent().map(|p| p.to_path_buf()),
+        CompareScope::Subdirectory => {
+            let root = dirs.iter().find(|d| path.starts_with(d))?;
+            let mut components = path.strip_prefix(root).ok()?.components();
+            let first = components.next()?;
+            // files directly in the root share the root's scope
+            if components.next().is_some() {
+                Some(root.join(first))
+            } else {
+                Some(root.clone())
+            }
+        }
+    }
+}
+
+/// Directory or root whose copies are not reported, `None` when not isolating
+fn isolation_of(isolate: Isolate, dirs: &HashSet<PathBuf>, path: &Path) -> Option<PathBuf> {
+    match isolate {
+        Isolate::Off => None,
+        Isolate::Directory => path.parent().map(|p| p.to_path_buf()),
+        Isolate::Root => dirs.iter().find(|d| path.starts_with(d)).cloned(),
+    }
+}
+
+fn isolated(a: &Option<PathBuf>, b: &Option<PathBuf>) -> bool {
+    a.is_some() && a == b
+}
+
+/// Cheap check of the names before comparing the content
+fn names_match(mode: NameMatch, a: &FileEntry, b: &FileEntry) -> bool {
+    match mode {
+        NameMatch::Any => true,
+        NameMatch::Same => a.name == b.name,
+        NameMatch::Different => a.name != b.name,
+    }
+}
+
+/// Save the processed files, unprocessed ones are left out by the cache
+fn save_checkpoint(path: &Path, config: &SearchConfig, files: &[&mut FileEntry]) {
+    let mut cache = HashCache::new(config);
+    for file in files {
+        cache.insert(file);
+    }
+    match cache.save(path) {
+        Ok(()) => debug!("saved {} processed files to the checkpoint", cache.len()),
+        Err(e) => warn!("failed saving the checkpoint: {}", e),
+    }
+}
+
+/// Bytes the hasher read to process the file, the quick hash only reads a
+/// few samples, the full and the paranoid hash read the whole file again
+fn bytes_read(config: &SearchConfig, file: &FileEntry) -> u64 {
+    let config = config.hasher_config_for(&file.path, file.mime_type.as_deref());
+    let quick = file.size.min(config.size * config.splits);
+    let full = if config.hashes_fully() { file.size } else { 0 };
+    let paranoid = if config.paranoid_algorithm.is_some() {
+        file.size
+    } else {
+        0
+    };
+    quick + full + paranoid
+}
+
+/// Include or exclude filter, an invalid glob is matched as plain text
+fn filter_pattern(filter: &str) -> Pattern {
+    Pattern::new(filter).unwrap_or_else(|e| {
+        warn!("{}, matching '{}' as text", e, filter);
+        Pattern::substring(filter)
+    })
+}
+
+fn permission_denied(e: &std::io::Error) -> bool {
+    e.kind() == std::io::ErrorKind::PermissionDenied
+}
+
+fn emit(events: &Option<UnboundedSender<ScanEvent>>, event: ScanEvent) {
+    if let Some(events) = events {
+        // the receiver may have been dropped, the scan finishes anyway
+        let _ = events.send(event);
+    }
+}
+
+/// Without the sqlite feature there is nothing to spill the index to
+#[cfg(not(feature = "sqlite"))]
+enum SpillStore {}
+
+#[cfg(not(feature = "sqlite"))]
+impl SpillStore {
+    fn insert<I: IntoIterator<Item = FileEntry>>(
+        &mut self,
+        _files: I,
+    ) -> Result<usize, DeckardError> {
+        match *self {}
+    }
+}
+
+/// Check if the path is stored on a spinning disk
+#[cfg(target_os = "linux")]
+fn is_rotational(path: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    let dev = match fs::metadata(path) {
+        Ok(metadata) => metadata.dev(),
+        Err(_) => return false,
+    };
+    let major = ((dev >> 8) & 0xfff) | ((dev >> 32) & !0xfff);
+    let minor = (dev & 0xff) | ((dev >> 12) & !0xff);
+
+    // partitions keep the queue settings in the parent device
+    let block = PathBuf::from(format!("/sys/dev/block/{}:{}", major, minor));
+    [
+        block.join("queue/rotational"),
+        block.join("../queue/rotational"),
+    ]
+    .iter()
+    .find_map(|p| fs::read_to_string(p).ok())
+    .map(|r| r.trim() == "1")
+    .unwrap_or(false)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn is_rotational(_path: &Path) -> bool {
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::collect_paths;
+    use crate::config::{HashAlgorithm, HashRule, MediaClass};
+    use crate::test_dir::TestDir;
+
+    fn scan(path: &str) -> FileIndex {
+        let mut index = FileIndex::new(collect_paths(vec![path]), SearchConfig::default());
+        index.index_dirs();
+        index.process_files(None, None);
+        index.find_duplicates(None, None);
+        index
+    }
+
+    #[test]
+    fn merge_indexes() {
+        let mut index = scan("../test_files/same_files");
+        let other = scan("../test_files/empty");
+        let files_len = index.files_len() + other.files_len();
+
+        index.merge(other);
+
+        assert_eq!(index.dirs.len(), 2);
+        assert_eq!(index.files_len(), files_len);
+        assert_eq!(index.duplicate_groups().len(), 2);
+        assert_eq!(index.duplicates_len(), 5);
+    }
+
+    #[test]
+    fn merge_compares_across_indexes() {
+        let root = TestDir::new("merge");
+        for dir in ["one", "two"] {
+            fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        fs::write(root.join("one/a"), "shared").unwrap();
+        fs::write(root.join("one/b"), "shared").unwrap();
+        fs::write(root.join("one/c"), "only here").unwrap();
+        fs::write(root.join("two/d"), "shared").unwrap();
+        fs::write(root.join("two/e"), "elsewhere").unwrap();
+
+        let mut index = scan(root.join("one").to_str().unwrap());
+        let other = scan(root.join("two").to_str().unwrap());
+        assert_eq!(index.duplicates_len(), 2);
+        assert_eq!(other.duplicates_len(), 0);
+        index.merge(other);
+
+        let groups = index.duplicate_groups();
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].len(), 3);
+        assert_eq!(index.files_len(), 5);
+    }
+
+    #[test]
+    fn shard_and_merge() {
+        let whole = scan("../test_files");
+
+        let mut index = FileIndex::new(
+            collect_paths(vec!["../test_files"]),
+            SearchConfig::default(),
+        );
+        index.index_dirs();
+        let shards = index.shard(3);
+        assert_eq!(shards.len(), 3);
+
+        let dir = TestDir::new("shards");
+        let mut processed = Vec::new();
+        for (i, mut shard) in shards.into_iter().enumerate() {
+            shard.process_files(None, None);
+            let path = dir.join(format!("{}.json", i));
+            shard.save(&path).unwrap();
+            processed.push(FileIndex::load(&path).unwrap());
+        }
+
+        let mut merged = FileIndex::new(index.dirs.clone(), SearchConfig::default());
+        merged.merge_shards(processed);
+
+        assert_eq!(merged.files_len(), whole.files_len());
+        assert_eq!(merged.duplicates_len(), whole.duplicates_len());
+    }
+
+    #[test]
+    fn stream_events() {
+        let index = FileIndex::new(
+            collect_paths(vec!["../test_files/same_files"]),
+            SearchConfig::default(),
+        );
+        let mut events = index.scan_stream(None);
+
+        let mut indexed = 0;
+        let mut processed = 0;
+        let mut found = 0;
+        let done = loop {
+            match events.blocking_recv() {
+                Some(ScanEvent::FileIndexed(_)) => indexed += 1,
+                Some(ScanEvent::FileProcessed(_)) | Some(ScanEvent::FileCached(_)) => {
+                    processed += 1
+                }
+                Some(ScanEvent::DuplicateFound(_, _)) => found += 1,
+                Some(ScanEvent::Error(path, e)) => panic!("{:?}: {}", path, e),
+                Some(ScanEvent::Done(index)) => break index,
+                None => panic!("scan ended without a result"),
+            }
+        };
+
+        assert_eq!(indexed, done.files_len());
+        assert_eq!(processed, done.files_len());
+        assert_eq!(found, 3);
+        assert_eq!(done.duplicates_len(), 3);
+    }
+
+    #[test]
+    fn process_requested_first() {
+        let mut index = FileIndex::new(
+            collect_paths(vec!["../test_files/same_files"]),
+            SearchConfig::default(),
+        );
+        index.index_dirs();
+        let file_a = index
+            .files
+            .keys()
+            .find(|f| f.ends_with("file_a.txt"))
+            .unwrap()
+            .clone();
+
+        let copies = index.priority_queue().request(file_a);
+        index.process_files(None, None);
+
+        assert_eq!(copies.try_recv().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn compare_within_directory() {
+        let config = SearchConfig {
+            compare_scope: CompareScope::Directory,
+            ..Default::default()
+        };
+        let mut index = FileIndex::new(collect_paths(vec!["../test_files"]), config);
+        index.index_dirs();
+        index.process_files(None, None);
+        index.find_duplicates(None, None);
+
+        assert!(index.duplicates_len() > 0);
+        for (file, copies) in &index.duplicates {
+            assert!(copies.iter().all(|c| c.parent() == file.parent()));
+        }
+    }
+
+    #[test]
+    fn comparison_strategies() {
+        let duplicates = |strategy| {
+            let mut config = SearchConfig::default();
+            config.image_config.compare = true;
+            config.comparison_strategy = strategy;
+            let mut index = FileIndex::new(collect_paths(vec!["../test_files/images"]), config);
+            index.index_dirs();
+            index.process_files(None, None);
+            index.find_duplicates(None, None);
+            index.duplicate_groups()
+        };
+
+        let pairwise = duplicates(ComparisonStrategy::Pairwise);
+        assert_eq!(duplicates(ComparisonStrategy::Hybrid), pairwise);
+        // similar images are only found by comparing them perceptually
+        let buckets = duplicates(ComparisonStrategy::Buckets);
+        assert!(buckets
+            .iter()
+            .flatten()
+            .all(|f| pairwise.iter().flatten().any(|p| p == f)));
+    }
+
+    #[test]
+    fn hash_rules_override_hasher() {
+        let root = TestDir::new("hash_rules");
+        let mut content = vec![0u8; 64 * 1024];
+        fs::write(root.join("a.iso"), &content).unwrap();
+        // outside of the two samples the rule's quick hash reads
+        content[1000] = 1;
+        fs::write(root.join("b.iso"), &content).unwrap();
+
+        let duplicates = |rule: HashRule| {
+            let config = SearchConfig {
+                hash_rules: vec![rule],
+                ..Default::default()
+            };
+            let mut index = FileIndex::new(collect_paths(vec![&root]), config);
+            index.index_dirs();
+            index.process_files(None, None);
+            index.find_duplicates(None, None);
+            index.duplicates_len()
+        };
+
+        let sparse = HashRule {
+            pattern: "*.iso".to_string(),
+            size: Some(16),
+            splits: Some(2),
+            ..Default::default()
+        };
+        assert_eq!(duplicates(sparse.clone()), 2);
+        let full = HashRule {
+            full_hash: Some(true),
+            ..sparse.clone()
+        };
+        assert_eq!(duplicates(full), 0);
+        let other = HashRule {
+            pattern: "image/*".to_string(),
+            ..sparse
+        };
+        assert_eq!(duplicates(other), 0);
+    }
+
+    #[test]
+    fn drop_files_changed_during_scan() {
+        let root = TestDir::new("changed");
+        for name in ["one", "two", "gone", "grown"] {
+            fs::write(root.join(name), "same").unwrap();
+        }
+
+        let mut index = FileIndex::new(collect_paths(vec![&root]), SearchConfig::default());
+        index.index_dirs();
+        fs::remove_file(root.join("gone")).unwrap();
+        // written to between indexing and hashing
+        fs::write(root.join("grown"), "same, but longer").unwrap();
+        let summary = index.process_files(None, None);
+        index.find_duplicates(None, None);
+
+        assert_eq!(summary.changed, 2);
+        assert_eq!(summary.errors, 0);
+        assert_eq!(index.stats.changed, 2);
+        assert_eq!(index.files_len(), 2);
+        assert!(!index.files.keys().any(|f| f.ends_with("grown")));
+        assert_eq!(index.duplicates_len(), 2);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn skip_special_files() {
+        let root = TestDir::new("special");
+        fs::write(root.join("regular"), "regular").unwrap();
+        let _socket = std::os::unix::net::UnixListener::bind(root.join("socket")).unwrap();
+
+        let mut index = FileIndex::new(collect_paths(vec![&root]), SearchConfig::default());
+        index.index_dirs();
+
+        assert_eq!(index.files_len(), 1);
+        assert_eq!(index.stats.special_files, 1);
+    }
+
+    #[test]
+    fn isolate_directories() {
+        let config = SearchConfig {
+            isolate: Isolate::Directory,
+            ..Default::default()
+        };
+        let mut index = FileIndex::new(collect_paths(vec!["../test_files"]), config.clone());
+        index.index_dirs();
+        index.process_files(None, None);
+        index.find_duplicates(None, None);
+
+        for (file, copies) in &index.duplicates {
+            assert!(copies.iter().all(|c| c.parent() != file.parent()));
+        }
+
+        // all the copies of same_files live in one directory
+        let mut index = FileIndex::new(collect_paths(vec!["../test_files/same_files"]), config);
+        index.index_dirs();
+        index.process_files(None, None);
+        index.find_duplicates(None, None);
+        assert_eq!(index.duplicates_len(), 0);
+    }
+
+    #[test]
+    fn match_by_name() {
+        let config = SearchConfig {
+            name_match: NameMatch::Same,
+            ..Default::default()
+        };
+        let mut index = FileIndex::new(collect_paths(vec!["../test_files"]), config);
+        index.index_dirs();
+        index.process_files(None, None);
+        index.find_duplicates(None, None);
+        for (file, copies) in &index.duplicates {
+            assert!(copies.iter().all(|c| c.file_name() == file.file_name()));
+        }
+
+        // the copies in same_files all have different names
+        let config = SearchConfig {
+            name_match: NameMatch::Different,
+            ..Default::default()
+        };
+        let mut index = FileIndex::new(collect_paths(vec!["../test_files/same_files"]), config);
+        index.index_dirs();
+        index.process_files(None, None);
+        index.find_duplicates(None, None);
+        assert_eq!(index.duplicates_len(), 3);
+    }
+
+    #[test]
+    fn copies_of_one_file() {
+        let mut index = FileIndex::new(
+            collect_paths(vec!["../test_files"]),
+            SearchConfig::default(),
+        );
+        index.index_dirs();
+        let copies = index
+            .find_copies_of(Path::new("../test_files/same_files/file_a.txt"), None)
+            .unwrap();
+
+        assert!(copies.len() >= 2);
+        assert!(copies.iter().any(|c| c.ends_with("same_files/file_b.txt")));
+        assert!(!copies.iter().any(|c| c.ends_with("same_files/file_a.txt")));
+        assert!(index.duplicates.is_empty());
     }
 
-    pub fn file_entry(&self, file: &PathBuf) -> Option<FileEntry> {
-        self.files.get(file).and_then(|f| Some(f.clone()))
+    #[test]
+    fn hash_in_parallel() {
+        let mut config = SearchConfig::default();
+        config.hasher_config.full_hash = true;
+        config.hasher_config.parallel_size = 1;
+        let mut index = FileIndex::new(collect_paths(vec!["../test_files/same_files"]), config);
+        index.index_dirs();
+        index.process_files(None, None);
+        index.find_duplicates(None, None);
+
+        let whole = scan("../test_files/same_files");
+        assert_eq!(index.duplicates_len(), whole.duplicates_len());
+    }
+
+    #[test]
+    fn mark_cancelled_scan() {
+        let mut index = FileIndex::new(
+            collect_paths(vec!["../test_files/same_files"]),
+            SearchConfig::default(),
+        );
+        index.index_dirs();
+        index.process_files(Some(Arc::new(AtomicBool::new(true))), None);
+        index.find_duplicates(None, None);
+
+        assert!(index.stats.cancelled);
+        assert_eq!(index.duplicates_len(), 0);
+        assert!(crate::report::json_report(&index).partial);
+    }
+
+    #[test]
+    fn paranoid_hash() {
+        // the paranoid algorithm fully hashes files without full_hash set
+        let mut config = SearchConfig::default();
+        config.hasher_config.paranoid_algorithm = Some(HashAlgorithm::SHA256);
+        let mut index = FileIndex::new(collect_paths(vec!["../test_files/same_files"]), config);
+        index.index_dirs();
+        index.process_files(None, None);
+        assert!(index.files.values().all(|f| f.paranoid_hash.is_some()));
+        assert!(index.files.values().all(|f| f.full_hash.is_some()));
+
+        let mut files = index.files.values().filter(|f| f.size > 0);
+        let file = files.next().unwrap();
+        let mut copy = file.clone();
+        assert!(file.compare(&copy, &index.config));
+        // a collision in the first algorithm alone is not a match
+        copy.paranoid_hash = Some(String::new());
+        assert!(!file.compare(&copy, &index.config));
+    }
+
+    #[test]
+    #[cfg(feature = "sqlite")]
+    fn scan_with_low_memory() {
+        let whole = scan("../test_files");
+
+        let mut index = FileIndex::new(
+            collect_paths(vec!["../test_files"]),
+            SearchConfig::default(),
+        );
+        let summary = index.scan_low_memory(None).unwrap();
+
+        assert_eq!(summary.files, whole.files_len());
+        assert_eq!(index.duplicates_len(), whole.duplicates_len());
+        assert!(index.files.keys().all(|f| index.duplicates.contains_key(f)));
+    }
+
+    #[test]
+    fn read_image_info() {
+        let index = scan("../test_files/images");
+        let image = index
+            .files
+            .values()
+            .find(|f| f.path.ends_with("320px-Sapeck.jpg"))
+            .unwrap();
+
+        let info = image.image_info.as_ref().unwrap();
+        assert_eq!((info.width, info.height), (320, 480));
+        assert_eq!(info.format, "jpeg");
+        assert_eq!(info.pixels(), 320 * 480);
     }
 
-    pub fn file_size(&self, file: &PathBuf) -> Option<u64> {
-        self.files.get(file).and_then(|f| Some(f.size))
+    #[test]
+    fn rename_duplicate() {
+        let root = TestDir::new("rename");
+        fs::write(root.join("a.txt"), "same content").unwrap();
+        fs::write(root.join("b.txt"), "same content").unwrap();
+
+        let mut index = scan(root.to_str().unwrap());
+        let dir = index.dirs.iter().next().unwrap().clone();
+        let (a, b, c) = (dir.join("a.txt"), dir.join("b.txt"), dir.join("c.log"));
+
+        assert!(index.rename_file(&a, b.clone()).is_err());
+        index.rename_file(&a, c.clone()).unwrap();
+
+        assert!(!a.exists() && c.exists());
+        assert!(!index.files.contains_key(&a));
+        assert_eq!(index.files[&c].name, "c.log");
+        assert_eq!(index.files[&c].extension.as_deref(), Some("log"));
+        assert!(index.duplicates[&c].contains(&b));
+        assert!(index.duplicates[&b].contains(&c));
+        assert!(!index.duplicates[&b].contains(&a));
     }
 
+    #[test]
+    fn only_media_class() {
+        let config = SearchConfig {
+            media: Some(MediaClass::Images),
+            ..Default::default()
+        };
+        let mut index = FileIndex::new(collect_paths(vec!["../test_files"]), config);
+        index.index_dirs();
+
+        assert!(index.files_len() > 0);
+        assert!(index
+            .files
+            .keys()
+            .all(|f| get_mime_type(f).unwrap().starts_with("image/")));
+        assert!(index.files_len() < scan("../test_files").files_len());
     }
 }
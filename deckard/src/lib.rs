@@ -1,14 +1,45 @@
+pub mod cache;
+pub mod chunks;
 pub mod config;
+#[cfg(unix)]
+pub mod daemon;
+pub mod error;
+pub mod export;
 pub mod file;
+pub mod fsops;
 mod hasher;
+pub mod hook;
+pub mod ignores;
 pub mod index;
+mod limiter;
+pub mod metrics;
+#[cfg(feature = "notify")]
+pub mod notify;
+pub mod partial;
+pub mod pattern;
+mod platform;
+pub mod policy;
+pub mod progress;
+pub mod remote;
+pub mod report;
+#[cfg(feature = "s3")]
+pub mod s3;
+pub mod schedule;
+pub mod selection;
+#[cfg(feature = "sqlite")]
+pub mod spill;
+#[cfg(feature = "otel")]
+pub mod telemetry;
+#[cfg(test)]
+mod test_dir;
+pub mod verify;
+mod xattr;
 
 use config::SearchConfig;
-use file::{EntryType, FileEntry};
 use std::collections::{HashMap, HashSet};
 use std::{env, fs, path::Path, path::PathBuf};
 
-use log::{debug, warn};
+use log::debug;
 
 use index::FileIndex;
 
@@ -18,8 +49,8 @@ pub fn find_duplicates(
 ) -> HashMap<PathBuf, HashSet<PathBuf>> {
     let mut file_index = FileIndex::new(dirs, config);
     file_index.index_dirs();
-    file_index.process_files(None);
-    file_index.find_duplicates(None);
+    file_index.process_files(None, None);
+    file_index.find_duplicates(None, None);
     file_index.duplicates
 }
 
@@ -28,7 +59,9 @@ pub fn collect_paths<P: AsRef<Path>>(target_paths: Vec<P>) -> HashSet<PathBuf> {
 
     for path in target_paths {
         let path: PathBuf = path.as_ref().components().collect();
-        let path = fs::canonicalize(&path).unwrap_or(path);
+        let path = fs::canonicalize(&path)
+            .map(platform::normalize_path)
+            .unwrap_or(path);
 
         let mut to_insert = true;
 
@@ -54,10 +87,11 @@ pub fn find_common_path(target_paths: &HashSet<PathBuf>) -> Option<PathBuf> {
 }
 
 pub fn to_relative_path(path: &PathBuf) -> PathBuf {
-    let current_dir = env::current_dir().expect("failed getting current directory");
-    let relative_path =
-        pathdiff::diff_paths(path, current_dir).expect("failed getting relative path");
-    relative_path
+    let current_dir = env::current_dir()
+        .map(platform::normalize_path)
+        .expect("failed getting current directory");
+    // paths on another drive or share have no relative form
+    pathdiff::diff_paths(path, current_dir).unwrap_or_else(|| path.clone())
 }
 
 #[cfg(test)]
@@ -67,7 +101,6 @@ mod tests {
     #[test]
     fn it_works() {
         // visit_dir(Path::new("."));
-        assert!(true);
     }
 
     #[test]
@@ -0,0 +1,167 @@
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+use log::debug;
+
+/// How long throughput is measured before the reader count is reconsidered
+const ADAPT_WINDOW: Duration = Duration::from_millis(500);
+
+#[derive(Debug)]
+struct LimiterState {
+    limit: usize,
+    active: usize,
+    direction: isize,
+    window_start: Instant,
+    window_bytes: u64,
+    last_throughput: f64,
+}
+
+/// Limits the number of files read in parallel, adjusting the limit to
+/// the measured read throughput. Seek-bound storage gets faster with fewer
+/// readers while fast SSDs benefit from more of them. The limit stays
+/// between 1 and `max`, the threads reading the files, since a higher limit
+/// could never be reached.
+#[derive(Debug)]
+pub struct ReaderLimiter {
+    max: usize,
+    state: Mutex<LimiterState>,
+    available: Condvar,
+}
+
+/// Released reader slot when dropped
+pub struct ReaderPermit<'a> {
+    limiter: &'a ReaderLimiter,
+}
+
+impl ReaderLimiter {
+    pub fn new(max: usize) -> Self {
+        let max = max.max(1);
+        Self {
+            max,
+            state: Mutex::new(LimiterState {
+                limit: max,
+                active: 0,
+                // start probing with fewer readers
+                direction: -1,
+                window_start: Instant::now(),
+                window_bytes: 0,
+                last_throughput: 0.0,
+            }),
+            available: Condvar::new(),
+        }
+    }
+
+    /// Block until a reader slot is free
+    pub fn acquire(&self) -> ReaderPermit<'_> {
+        let mut state = self.state.lock().unwrap();
+        while state.active >= state.limit {
+            state = self.available.wait(state).unwrap();
+        }
+        state.active += 1;
+        ReaderPermit { limiter: self }
+    }
+
+    /// Account for the bytes read and adjust the reader limit at the end of each window
+    pub fn record(&self, bytes: u64) {
+        let mut state = self.state.lock().unwrap();
+        state.window_bytes += bytes;
+
+        let elapsed = state.window_start.elapsed();
+        if elapsed < ADAPT_WINDOW {
+            return;
+        }
+
+        let throughput = state.window_bytes as f64 / elapsed.as_secs_f64();
+        if throughput < state.last_throughput {
+            state.direction = -state.direction;
+        }
+        let limit = (state.limit as isize + state.direction).clamp(1, self.max as isize);
+        debug!(
+            "read throughput {:.0} B/s with {} readers, next limit {}",
+            throughput, state.limit, limit
+        );
+
+        state.limit = limit as usize;
+        state.last_throughput = throughput;
+        state.window_start = Instant::now();
+        state.window_bytes = 0;
+        self.available.notify_all();
+    }
+
+    /// Current number of parallel readers
+    pub fn limit(&self) -> usize {
+        self.state.lock().unwrap().limit
+    }
+
+    /// Throughput measured during the last full window, in bytes per second
+    pub fn throughput(&self) -> f64 {
+        self.state.lock().unwrap().last_throughput
+    }
+}
+
+/// Caps the read bandwidth, readers getting ahead of the limit wait
+#[derive(Debug)]
+pub struct IoThrottle {
+    bytes_per_second: u64,
+    start: Instant,
+    bytes: Mutex<u64>,
+}
+
+impl IoThrottle {
+    pub fn new(bytes_per_second: u64) -> Self {
+        Self {
+            bytes_per_second: bytes_per_second.max(1),
+            start: Instant::now(),
+            bytes: Mutex::new(0),
+        }
+    }
+
+    /// Account for the bytes read and sleep until they fit under the limit
+    pub fn consume(&self, bytes: u64) {
+        let wait = {
+            let mut total = self.bytes.lock().unwrap();
+            *total += bytes;
+            let due = Duration::from_secs_f64(*total as f64 / self.bytes_per_second as f64);
+            due.saturating_sub(self.start.elapsed())
+        };
+        if !wait.is_zero() {
+            std::thread::sleep(wait);
+        }
+    }
+}
+
+impl Drop for ReaderPermit<'_> {
+    fn drop(&mut self) {
+        let mut state = self.limiter.state.lock().unwrap();
+        state.active -= 1;
+        self.limiter.available.notify_one();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn limit_stays_in_bounds() {
+        let limiter = ReaderLimiter::new(2);
+        for _ in 0..4 {
+            {
+                let _permit = limiter.acquire();
+            }
+            std::thread::sleep(ADAPT_WINDOW);
+            limiter.record(1024);
+            assert!((1..=2).contains(&limiter.limit()));
+        }
+    }
+
+    #[test]
+    fn throttle_reads() {
+        let throttle = IoThrottle::new(1000);
+        let start = Instant::now();
+        for _ in 0..4 {
+            throttle.consume(50);
+        }
+        assert!(start.elapsed() >= Duration::from_millis(200));
+    }
+}
@@ -0,0 +1,136 @@
+use std::collections::BTreeMap;
+use std::fmt::{self, Display};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Slowest files kept for each stage
+const WORST_OFFENDERS: usize = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Stage {
+    Indexing,
+    Hashing,
+    ImageHashing,
+    AudioFingerprinting,
+    Comparing,
+}
+
+impl Display for Stage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Stage::Indexing => "indexing",
+            Stage::Hashing => "hashing",
+            Stage::ImageHashing => "image hashing",
+            Stage::AudioFingerprinting => "audio fingerprinting",
+            Stage::Comparing => "comparing",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct StageMetrics {
+    /// Time spent in the stage, summed over all threads
+    pub total: Duration,
+    pub count: usize,
+    /// Slowest paths, slowest first
+    pub slowest: Vec<(PathBuf, Duration)>,
+}
+
+/// Time spent in each stage of a scan
+#[derive(Debug, Default)]
+pub struct Metrics {
+    stages: Mutex<BTreeMap<Stage, StageMetrics>>,
+}
+
+impl Metrics {
+    pub fn record(&self, stage: Stage, path: &Path, elapsed: Duration) {
+        let mut stages = self.stages.lock().unwrap();
+        let metrics = stages.entry(stage).or_default();
+        metrics.total += elapsed;
+        metrics.count += 1;
+
+        let slower = metrics.slowest.len() < WORST_OFFENDERS
+            || metrics.slowest.last().is_some_and(|(_, d)| elapsed > *d);
+        if slower {
+            metrics.slowest.push((path.to_path_buf(), elapsed));
+            metrics.slowest.sort_by_key(|(_, d)| std::cmp::Reverse(*d));
+            metrics.slowest.truncate(WORST_OFFENDERS);
+        }
+    }
+
+    pub fn stage(&self, stage: Stage) -> Option<StageMetrics> {
+        self.stages.lock().unwrap().get(&stage).cloned()
+    }
+
+    pub fn stages(&self) -> Vec<(Stage, StageMetrics)> {
+        self.stages
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(s, m)| (*s, m.clone()))
+            .collect()
+    }
+}
+
+impl Display for Metrics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (stage, metrics) in self.stages() {
+            writeln!(
+                f,
+                "{:<22}{:>12.2?} ({})",
+                stage.to_string(),
+                metrics.total,
+                metrics.count
+            )?;
+            for (path, elapsed) in &metrics.slowest {
+                writeln!(f, "  {:>32.2?} {}", elapsed, path.to_string_lossy())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Run `f` and record its duration when metrics are collected
+pub(crate) fn timed<T>(
+    metrics: Option<&Metrics>,
+    stage: Stage,
+    path: &Path,
+    f: impl FnOnce() -> T,
+) -> T {
+    let Some(metrics) = metrics else {
+        return f();
+    };
+    let start = Instant::now();
+    let result = f();
+    metrics.record(stage, path, start.elapsed());
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keep_slowest_files() {
+        let metrics = Metrics::default();
+        for i in 0..10 {
+            metrics.record(
+                Stage::Hashing,
+                Path::new(&i.to_string()),
+                Duration::from_millis(i),
+            );
+        }
+
+        let hashing = metrics.stage(Stage::Hashing).unwrap();
+        assert_eq!(hashing.count, 10);
+        assert_eq!(hashing.total, Duration::from_millis(45));
+        assert_eq!(hashing.slowest.len(), WORST_OFFENDERS);
+        assert_eq!(
+            hashing.slowest[0],
+            (PathBuf::from("9"), Duration::from_millis(9))
+        );
+        assert!(metrics.stage(Stage::Comparing).is_none());
+    }
+}
@@ -0,0 +1,48 @@
+use crate::index::FileIndex;
+use log::warn;
+use notify_rust::Notification;
+
+/// Text of the notification sent when comparing finishes
+pub fn summary(index: &FileIndex) -> String {
+    format!(
+        "Found {} duplicate groups, {} wasted",
+        index.duplicate_groups().len(),
+        humansize::format_size(index.reclaimable_size(), humansize::DECIMAL)
+    )
+}
+
+/// Send a desktop notification with the results of the scan, failures are
+/// only logged since there may be no notification server running
+pub fn scan_finished(index: &FileIndex) {
+    if let Err(e) = Notification::new()
+        .summary("Deckard scan finished")
+        .body(&summary(index))
+        .show()
+    {
+        warn!("failed sending notification: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::collect_paths;
+    use crate::config::SearchConfig;
+    use crate::test_dir::TestDir;
+    use std::fs;
+
+    #[test]
+    fn summarize_results() {
+        let root = TestDir::new("notify");
+        for name in ["a", "b", "c"] {
+            fs::write(root.join(name), "wasted").unwrap();
+        }
+
+        let mut index = FileIndex::new(collect_paths(vec![&root]), SearchConfig::default());
+        index.index_dirs();
+        index.process_files(None, None);
+        index.find_duplicates(None, None);
+
+        assert_eq!(summary(&index), "Found 1 duplicate groups, 12 B wasted");
+    }
+}
@@ -0,0 +1,119 @@
+use crate::file::FileEntry;
+use crate::hasher::{self, is_cancelled};
+use crate::index::FileIndex;
+use log::{debug, warn};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+
+/// Leading bytes hashed to find the candidates, smaller files are not checked
+pub const PARTIAL_HEAD: u64 = 64 * 1024;
+
+/// A file whose whole content is the start of a larger file,
+/// like a truncated download or a partial copy
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PartialDuplicate {
+    pub partial: PathBuf,
+    pub full: PathBuf,
+    /// Size of the partial file, the length of the shared prefix
+    pub size: u64,
+}
+
+/// Find files that are a prefix of a larger file. Files sharing the hash of
+/// their leading bytes are candidates, confirmed by hashing the prefix of the
+/// larger file.
+pub fn find_partial_duplicates(
+    index: &FileIndex,
+    cancel: &Option<Arc<AtomicBool>>,
+) -> Vec<PartialDuplicate> {
+    let algorithm = &index.config.hasher_config.hash_algorithm;
+    let mut heads: HashMap<String, Vec<&FileEntry>> = HashMap::new();
+    let hashed: Vec<(String, &FileEntry)> = index
+        .files
+        .values()
+        .filter(|f| f.size >= PARTIAL_HEAD)
+        .collect::<Vec<_>>()
+        .par_iter()
+        .filter_map(
+            |f| match hasher::get_prefix_hash(algorithm, &f.path, PARTIAL_HEAD, cancel) {
+                Ok(head) => Some((head, *f)),
+                Err(e) => {
+                    warn!("failed hashing {}: {}", f.path.to_string_lossy(), e);
+                    None
+                }
+            },
+        )
+        .collect();
+    for (head, file) in hashed {
+        heads.entry(head).or_default().push(file);
+    }
+
+    let mut partials = Vec::new();
+    for mut group in heads.into_values().filter(|g| g.len() > 1) {
+        group.sort_by(|a, b| a.size.cmp(&b.size).then(a.path.cmp(&b.path)));
+        for (i, small) in group.iter().enumerate() {
+            if is_cancelled(cancel) {
+                return partials;
+            }
+            let Ok(whole) = hasher::get_prefix_hash(algorithm, &small.path, small.size, cancel)
+            else {
+                continue;
+            };
+            for large in group[i + 1..].iter().filter(|l| l.size > small.size) {
+                let prefix = hasher::get_prefix_hash(algorithm, &large.path, small.size, cancel);
+                if prefix.is_ok_and(|p| p == whole) {
+                    debug!(
+                        "{} is the start of {}",
+                        small.path.to_string_lossy(),
+                        large.path.to_string_lossy()
+                    );
+                    partials.push(PartialDuplicate {
+                        partial: small.path.clone(),
+                        full: large.path.clone(),
+                        size: small.size,
+                    });
+                }
+            }
+        }
+    }
+    partials.sort_by(|a, b| b.size.cmp(&a.size).then(a.partial.cmp(&b.partial)));
+    partials
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::collect_paths;
+    use crate::config::SearchConfig;
+    use crate::test_dir::TestDir;
+    use std::fs;
+
+    #[test]
+    fn truncated_copies() {
+        let root = TestDir::new("partial");
+
+        let content: Vec<u8> = (0..PARTIAL_HEAD * 3).map(|i| (i % 251) as u8).collect();
+        fs::write(root.join("full"), &content).unwrap();
+        fs::write(
+            root.join("truncated"),
+            &content[..(PARTIAL_HEAD * 2) as usize],
+        )
+        .unwrap();
+        let mut other = content[..(PARTIAL_HEAD * 2) as usize].to_vec();
+        other[PARTIAL_HEAD as usize + 1] ^= 0xff;
+        fs::write(root.join("diverged"), &other).unwrap();
+        fs::write(root.join("small"), &content[..1024]).unwrap();
+
+        let mut index = FileIndex::new(collect_paths(vec![&root]), SearchConfig::default());
+        index.index_dirs();
+        let partials = find_partial_duplicates(&index, &None);
+
+        assert_eq!(partials.len(), 1);
+        assert!(partials[0].partial.ends_with("truncated"));
+        assert!(partials[0].full.ends_with("full"));
+        assert_eq!(partials[0].size, PARTIAL_HEAD * 2);
+    }
+}
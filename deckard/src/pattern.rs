@@ -0,0 +1,89 @@
+use crate::error::DeckardError;
+use globset::{GlobBuilder, GlobMatcher};
+use std::fmt;
+use std::path::Path;
+
+/// File pattern of the include and exclude filters, ignoring case.
+///
+/// Text without glob characters matches file names containing it. A glob
+/// like `*.tmp` matches the file name, a glob with a `/` like
+/// `**/Downloads/**` matches the whole path.
+#[derive(Debug, Clone)]
+pub struct Pattern {
+    pattern: String,
+    kind: Kind,
+}
+
+#[derive(Debug, Clone)]
+enum Kind {
+    Substring(String),
+    Name(GlobMatcher),
+    Path(GlobMatcher),
+}
+
+impl Pattern {
+    pub fn new(pattern: &str) -> Result<Self, DeckardError> {
+        if !pattern.contains(['*', '?', '[', '{']) {
+            return Ok(Self::substring(pattern));
+        }
+        let matcher = GlobBuilder::new(pattern)
+            .case_insensitive(true)
+            .literal_separator(true)
+            .build()
+            .map_err(|e| DeckardError::Pattern(e.to_string()))?
+            .compile_matcher();
+        let kind = if pattern.contains('/') {
+            Kind::Path(matcher)
+        } else {
+            Kind::Name(matcher)
+        };
+        Ok(Self {
+            pattern: pattern.to_string(),
+            kind,
+        })
+    }
+
+    /// Match file names containing the text, even when it looks like a glob
+    pub fn substring(text: &str) -> Self {
+        Self {
+            pattern: text.to_string(),
+            kind: Kind::Substring(text.to_lowercase()),
+        }
+    }
+
+    pub fn matches(&self, path: &Path) -> bool {
+        let name = || path.file_name().unwrap_or(path.as_os_str());
+        match &self.kind {
+            Kind::Substring(text) => name().to_string_lossy().to_lowercase().contains(text),
+            Kind::Name(glob) => glob.is_match(name()),
+            Kind::Path(glob) => glob.is_match(path),
+        }
+    }
+}
+
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.pattern)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pattern_kinds() {
+        let path = Path::new("/home/user/Downloads/photos/IMG_0001.JPG");
+
+        assert!(Pattern::new("img_").unwrap().matches(path));
+        assert!(!Pattern::new("downloads").unwrap().matches(path));
+        assert!(Pattern::new("*.jpg").unwrap().matches(path));
+        assert!(!Pattern::new("*.tmp").unwrap().matches(path));
+        assert!(Pattern::new("**/Downloads/**").unwrap().matches(path));
+        assert!(!Pattern::new("**/Documents/**").unwrap().matches(path));
+        // a single star doesn't cross directories
+        assert!(!Pattern::new("/home/*.jpg").unwrap().matches(path));
+        assert!(Pattern::new("[").is_err());
+        assert!(Pattern::substring("*").matches(Path::new("/tmp/a*b")));
+    }
+}
@@ -0,0 +1,352 @@
+use chrono::{DateTime, Local};
+use std::fs::{FileType, Metadata};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::SystemTime;
+
+#[cfg(windows)]
+const FILE_ATTRIBUTE_REPARSE_POINT: u32 = 0x400;
+
+/// Inode of the file, 0 where the platform has no stable equivalent
+#[cfg(unix)]
+pub(crate) fn inode(metadata: &Metadata) -> u64 {
+    use std::os::unix::fs::MetadataExt;
+    metadata.ino()
+}
+
+#[cfg(not(unix))]
+pub(crate) fn inode(_metadata: &Metadata) -> u64 {
+    0
+}
+
+/// Device the file is stored on, `None` when it can't be told
+#[cfg(unix)]
+pub(crate) fn device(metadata: &Metadata) -> Option<u64> {
+    use std::os::unix::fs::MetadataExt;
+    Some(metadata.dev())
+}
+
+#[cfg(not(unix))]
+pub(crate) fn device(_metadata: &Metadata) -> Option<u64> {
+    None
+}
+
+/// Symlinks, and on Windows also junctions and other reparse points
+#[cfg(windows)]
+pub(crate) fn is_link(metadata: &Metadata) -> bool {
+    use std::os::windows::fs::MetadataExt;
+    metadata.file_type().is_symlink()
+        || metadata.file_attributes() & FILE_ATTRIBUTE_REPARSE_POINT != 0
+}
+
+#[cfg(not(windows))]
+pub(crate) fn is_link(metadata: &Metadata) -> bool {
+    metadata.file_type().is_symlink()
+}
+
+/// FIFOs, sockets and device nodes, reading them can block forever
+#[cfg(unix)]
+pub(crate) fn is_special(file_type: &FileType) -> bool {
+    use std::os::unix::fs::FileTypeExt;
+    file_type.is_fifo()
+        || file_type.is_socket()
+        || file_type.is_block_device()
+        || file_type.is_char_device()
+}
+
+#[cfg(not(unix))]
+pub(crate) fn is_special(_file_type: &FileType) -> bool {
+    false
+}
+
+pub(crate) fn modified(metadata: &Metadata) -> DateTime<Local> {
+    metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH).into()
+}
+
+/// Creation time, not every file system keeps one so fall back to the modification time
+pub(crate) fn created(metadata: &Metadata) -> DateTime<Local> {
+    metadata
+        .created()
+        .map(|t| t.into())
+        .unwrap_or_else(|_| modified(metadata))
+}
+
+/// Command running the given line through the platform shell
+#[cfg(windows)]
+pub(crate) fn shell(line: &str) -> Command {
+    let mut command = Command::new("cmd");
+    command.arg("/C").arg(line);
+    command
+}
+
+#[cfg(not(windows))]
+pub(crate) fn shell(line: &str) -> Command {
+    let mut command = Command::new("sh");
+    command.arg("-c").arg(line);
+    command
+}
+
+/// Quote the path so spaces and quotes survive the shell
+#[cfg(windows)]
+pub(crate) fn quote(path: &Path) -> String {
+    format!("\"{}\"", path.to_string_lossy())
+}
+
+#[cfg(not(windows))]
+pub(crate) fn quote(path: &Path) -> String {
+    format!("'{}'", path.to_string_lossy().replace('\'', r"'\''"))
+}
+
+#[cfg(target_os = "linux")]
+const IOPRIO_WHO_PROCESS: libc::c_int = 1;
+#[cfg(target_os = "linux")]
+const IOPRIO_CLASS_IDLE: libc::c_int = 3;
+#[cfg(target_os = "linux")]
+const IOPRIO_CLASS_SHIFT: libc::c_int = 13;
+
+/// Run the calling thread at the lowest CPU priority and, on Linux,
+/// only read from disk when no one else does
+#[cfg(target_os = "linux")]
+pub(crate) fn lower_priority() {
+    // SAFETY: both calls only change the scheduling of the calling thread
+    unsafe {
+        libc::setpriority(libc::PRIO_PROCESS, 0, 19);
+        libc::syscall(
+            libc::SYS_ioprio_set,
+            IOPRIO_WHO_PROCESS,
+            0,
+            IOPRIO_CLASS_IDLE << IOPRIO_CLASS_SHIFT,
+        );
+    }
+}
+
+#[cfg(all(unix, not(target_os = "linux")))]
+pub(crate) fn lower_priority() {
+    // SAFETY: only changes the scheduling priority of this process
+    unsafe {
+        libc::setpriority(libc::PRIO_PROCESS, 0, 19);
+    }
+}
+
+#[cfg(not(unix))]
+pub(crate) fn lower_priority() {}
+
+/// Longest extended attribute value read
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+const XATTR_MAX_SIZE: usize = 4096;
+
+/// Value of the extended attribute, `None` when it is missing or
+/// the file system doesn't support them
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+pub(crate) fn get_xattr(path: &Path, name: &str) -> Option<Vec<u8>> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = CString::new(path.as_os_str().as_bytes()).ok()?;
+    let name = CString::new(name).ok()?;
+    let mut value = vec![0u8; XATTR_MAX_SIZE];
+    let (path, name, buf, size) = (
+        path.as_ptr(),
+        name.as_ptr(),
+        value.as_mut_ptr().cast(),
+        value.len(),
+    );
+    // SAFETY: both strings are nul terminated and the buffer holds `size` bytes
+    #[cfg(target_os = "linux")]
+    let len = unsafe { libc::getxattr(path, name, buf, size) };
+    #[cfg(target_os = "macos")]
+    let len = unsafe { libc::getxattr(path, name, buf, size, 0, 0) };
+    if len < 0 {
+        return None;
+    }
+    value.truncate(len as usize);
+    Some(value)
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+pub(crate) fn get_xattr(_path: &Path, _name: &str) -> Option<Vec<u8>> {
+    None
+}
+
+/// Set the extended attribute, replacing its previous value
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+pub(crate) fn set_xattr(path: &Path, name: &str, value: &[u8]) -> std::io::Result<()> {
+    use std::ffi::CString;
+    use std::io::{Error, ErrorKind};
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = CString::new(path.as_os_str().as_bytes())
+        .map_err(|e| Error::new(ErrorKind::InvalidInput, e))?;
+    let name = CString::new(name).map_err(|e| Error::new(ErrorKind::InvalidInput, e))?;
+    let (path, name, buf, size) = (
+        path.as_ptr(),
+        name.as_ptr(),
+        value.as_ptr().cast(),
+        value.len(),
+    );
+    // SAFETY: both strings are nul terminated and the value holds `size` bytes
+    #[cfg(target_os = "linux")]
+    let result = unsafe { libc::setxattr(path, name, buf, size, 0) };
+    #[cfg(target_os = "macos")]
+    let result = unsafe { libc::setxattr(path, name, buf, size, 0, 0) };
+    if result < 0 {
+        return Err(Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+pub(crate) fn set_xattr(_path: &Path, _name: &str, _value: &[u8]) -> std::io::Result<()> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "extended attributes are not supported on this platform",
+    ))
+}
+
+/// `_IOWR(0x94, 54, struct file_dedupe_range)`
+#[cfg(target_os = "linux")]
+const FIDEDUPERANGE: libc::c_ulong = 0xc018_9436;
+#[cfg(target_os = "linux")]
+const FILE_DEDUPE_RANGE_DIFFERS: i32 = 1;
+
+/// `struct file_dedupe_range` followed by a single `struct file_dedupe_range_info`
+#[cfg(target_os = "linux")]
+#[repr(C)]
+#[derive(Default)]
+struct FileDedupeRange {
+    src_offset: u64,
+    src_length: u64,
+    dest_count: u16,
+    reserved1: u16,
+    reserved2: u32,
+    dest_fd: i64,
+    dest_offset: u64,
+    bytes_deduped: u64,
+    status: i32,
+    reserved: u32,
+}
+
+/// Ask the kernel to share the extents of `src` with `dest` at the same
+/// offset. The kernel compares the bytes itself and returns the number of
+/// bytes deduplicated, `Ok(None)` when the contents differ.
+#[cfg(target_os = "linux")]
+pub(crate) fn dedupe_range(
+    src: &std::fs::File,
+    dest: &std::fs::File,
+    offset: u64,
+    length: u64,
+) -> std::io::Result<Option<u64>> {
+    use std::os::unix::io::AsRawFd;
+
+    let mut range = FileDedupeRange {
+        src_offset: offset,
+        src_length: length,
+        dest_count: 1,
+        dest_fd: dest.as_raw_fd() as i64,
+        dest_offset: offset,
+        ..Default::default()
+    };
+    // SAFETY: the struct matches the kernel layout for one destination
+    // and both file descriptors stay open for the call
+    let result = unsafe { libc::ioctl(src.as_raw_fd(), FIDEDUPERANGE as _, &mut range) };
+    if result < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    match range.status {
+        FILE_DEDUPE_RANGE_DIFFERS => Ok(None),
+        status if status < 0 => Err(std::io::Error::from_raw_os_error(-status)),
+        _ => Ok(Some(range.bytes_deduped)),
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn dedupe_range(
+    _src: &std::fs::File,
+    _dest: &std::fs::File,
+    _offset: u64,
+    _length: u64,
+) -> std::io::Result<Option<u64>> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "kernel deduplication is only supported on Linux",
+    ))
+}
+
+/// Drop the `\\?\` prefix canonicalize adds on Windows, so paths display
+/// like the user typed them and compare with the current directory. The
+/// standard library adds the prefix back where long paths need it.
+#[cfg(windows)]
+pub(crate) fn normalize_path(path: PathBuf) -> PathBuf {
+    match strip_verbatim(&path.to_string_lossy()) {
+        Some(stripped) => PathBuf::from(stripped),
+        None => path,
+    }
+}
+
+#[cfg(not(windows))]
+pub(crate) fn normalize_path(path: PathBuf) -> PathBuf {
+    path
+}
+
+/// `\\?\C:\dir` to `C:\dir` and `\\?\UNC\server\share` to `\\server\share`
+#[cfg_attr(not(windows), allow(dead_code))]
+fn strip_verbatim(path: &str) -> Option<String> {
+    let rest = path.strip_prefix(r"\\?\")?;
+    if let Some(share) = rest.strip_prefix(r"UNC\") {
+        return Some(format!(r"\\{}", share));
+    }
+    // only drive paths, other verbatim paths like volume ids have no short form
+    let drive = rest.as_bytes();
+    if drive.len() >= 2 && drive[0].is_ascii_alphabetic() && drive[1] == b':' {
+        return Some(rest.to_string());
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn portable_metadata() {
+        let metadata = fs::symlink_metadata("../test_files/file.txt").unwrap();
+        assert!(!is_link(&metadata));
+        assert!(created(&metadata) <= Local::now());
+        assert_eq!(
+            modified(&metadata),
+            DateTime::<Local>::from(metadata.modified().unwrap())
+        );
+        #[cfg(unix)]
+        assert!(device(&metadata).is_some());
+        assert!(!is_special(&metadata.file_type()));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn detect_special_files() {
+        let null = fs::metadata("/dev/null").unwrap();
+        assert!(is_special(&null.file_type()));
+    }
+
+    #[test]
+    fn strip_verbatim_prefix() {
+        assert_eq!(
+            strip_verbatim(r"\\?\C:\Users\deckard").as_deref(),
+            Some(r"C:\Users\deckard")
+        );
+        assert_eq!(
+            strip_verbatim(r"\\?\UNC\server\share\dir").as_deref(),
+            Some(r"\\server\share\dir")
+        );
+        assert_eq!(strip_verbatim(r"\\?\Volume{1234}\dir"), None);
+        assert_eq!(strip_verbatim(r"C:\Users"), None);
+        assert_eq!(strip_verbatim("/home/deckard"), None);
+    }
+
+    #[test]
+    fn run_shell() {
+        assert!(shell("exit 0").status().unwrap().success());
+        assert!(!shell("exit 1").status().unwrap().success());
+    }
+}
@@ -0,0 +1,371 @@
+use crate::error::DeckardError;
+use crate::file::FileEntry;
+use crate::index::FileIndex;
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// Which file of a duplicate group to keep, all other copies are removed
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum KeepPolicy {
+    Newest,
+    /// The first copy is usually the original
+    #[default]
+    Oldest,
+    ShortestPath,
+    LongestPath,
+    /// Keep the copy inside the directory
+    InDirectory(PathBuf),
+    /// Keep the copy under the first listed path
+    Priority(Vec<PathBuf>),
+    /// Keep the image with the most pixels, then the largest file.
+    /// Groups without images are kept whole.
+    LargestResolution,
+    /// Keep lossless audio, then the highest bitrate and sample rate.
+    /// Groups without audio are kept whole.
+    BestAudio,
+}
+
+impl FromStr for KeepPolicy {
+    type Err = String;
+
+    /// Parse `newest`, `oldest`, `shortest_path`, `longest_path`, `largest_resolution`,
+    /// `best_audio`, `dir:<path>` or `priority:<path>,<path>`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            Some(("dir", path)) => Ok(KeepPolicy::InDirectory(PathBuf::from(path))),
+            Some(("priority", paths)) => Ok(KeepPolicy::Priority(
+                paths.split(',').map(PathBuf::from).collect(),
+            )),
+            _ => match s {
+                "newest" => Ok(KeepPolicy::Newest),
+                "oldest" => Ok(KeepPolicy::Oldest),
+                "shortest_path" => Ok(KeepPolicy::ShortestPath),
+                "longest_path" => Ok(KeepPolicy::LongestPath),
+                "largest_resolution" => Ok(KeepPolicy::LargestResolution),
+                "best_audio" => Ok(KeepPolicy::BestAudio),
+                _ => Err(format!("unknown keep policy: {}", s)),
+            },
+        }
+    }
+}
+
+/// What to do with the files of one group
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Decision {
+    pub keep: Vec<PathBuf>,
+    pub remove: Vec<PathBuf>,
+}
+
+/// Split the group into the file to keep and the copies to remove.
+/// When the policy matches no file everything is kept.
+pub fn decide(policy: &KeepPolicy, group: &[&FileEntry]) -> Decision {
+    let mut files: Vec<&FileEntry> = group.to_vec();
+    // ties are settled by path so runs are repeatable
+    files.sort_by(|a, b| a.path.cmp(&b.path));
+
+    let keep = match policy {
+        KeepPolicy::Newest => files.iter().max_by_key(|f| f.modified),
+        KeepPolicy::Oldest => files.iter().min_by_key(|f| f.modified),
+        KeepPolicy::ShortestPath => files.iter().min_by_key(|f| f.path.as_os_str().len()),
+        KeepPolicy::LongestPath => files.iter().max_by_key(|f| f.path.as_os_str().len()),
+        KeepPolicy::InDirectory(dir) => files.iter().find(|f| f.path.starts_with(dir)),
+        KeepPolicy::Priority(paths) => paths
+            .iter()
+            .find_map(|p| files.iter().find(|f| f.path.starts_with(p))),
+        KeepPolicy::LargestResolution => files
+            .iter()
+            .filter(|f| f.image_info.is_some())
+            .max_by_key(|f| (f.image_info.as_ref().map(|i| i.pixels()), f.size)),
+        KeepPolicy::BestAudio => files
+            .iter()
+            .filter_map(|f| f.audio_info.as_ref().map(|a| (f, a)))
+            .max_by_key(|(f, a)| {
+                (
+                    a.lossless,
+                    a.bitrate,
+                    a.sample_rate,
+                    a.bits_per_sample,
+                    f.size,
+                )
+            })
+            .map(|(f, _)| f),
+    };
+
+    let Some(keep) = keep.map(|f| f.path.clone()) else {
+        return Decision {
+            keep: files.iter().map(|f| f.path.clone()).collect(),
+            remove: Vec::new(),
+        };
+    };
+    Decision {
+        remove: files
+            .iter()
+            .filter(|f| f.path != keep)
+            .map(|f| f.path.clone())
+            .collect(),
+        keep: vec![keep],
+    }
+}
+
+/// Files to remove from every duplicate group of the index
+pub fn removals(policy: &KeepPolicy, index: &FileIndex) -> Vec<PathBuf> {
+    decisions(policy, index)
+        .into_iter()
+        .flat_map(|d| d.remove)
+        .collect()
+}
+
+/// Decide every duplicate group of the index, copies under the
+/// preferred paths of the config are kept first
+pub fn decisions(policy: &KeepPolicy, index: &FileIndex) -> Vec<Decision> {
+    let prefer = KeepPolicy::Priority(
+        index
+            .config
+            .action_config
+            .prefer_paths
+            .iter()
+            .map(|p| expand_home(p))
+            .collect(),
+    );
+
+    index
+        .duplicate_groups()
+        .iter()
+        .map(|group| {
+            let entries: Vec<&FileEntry> =
+                group.iter().filter_map(|f| index.files.get(f)).collect();
+            let preferred = decide(&prefer, &entries);
+            if preferred.keep.len() == 1 {
+                preferred
+            } else {
+                decide(policy, &entries)
+            }
+        })
+        .collect()
+}
+
+/// What an action does to the copies that aren't kept
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum PlanAction {
+    Remove,
+    /// Share the extents with the kept file
+    Dedupe,
+}
+
+/// One group of a plan, groups keeping every file are left out
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PlanGroup {
+    pub keep: PathBuf,
+    pub files: Vec<PathBuf>,
+    /// Bytes of the affected files
+    pub bytes: u64,
+}
+
+/// Exact changes an action would make, to review before running it for real
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Plan {
+    pub action: PlanAction,
+    pub groups: Vec<PlanGroup>,
+    pub files: usize,
+    pub bytes: u64,
+}
+
+/// Plan the action for every duplicate group with the keep policy of the config
+pub fn plan(index: &FileIndex, action: PlanAction) -> Plan {
+    let mut groups: Vec<PlanGroup> = decisions(&index.config.action_config.keep_policy, index)
+        .into_iter()
+        .filter(|d| d.keep.len() == 1 && !d.remove.is_empty())
+        .map(|mut d| {
+            d.remove.sort();
+            PlanGroup {
+                bytes: d.remove.iter().filter_map(|f| index.file_size(f)).sum(),
+                keep: d.keep.remove(0),
+                files: d.remove,
+            }
+        })
+        .collect();
+    groups.sort_by(|a, b| a.keep.cmp(&b.keep));
+
+    Plan {
+        action,
+        files: groups.iter().map(|g| g.files.len()).sum(),
+        bytes: groups.iter().map(|g| g.bytes).sum(),
+        groups,
+    }
+}
+
+/// The plan as JSON, to pipe into other tools
+pub fn plan_json(plan: &Plan) -> Result<String, DeckardError> {
+    Ok(serde_json::to_string_pretty(plan)?)
+}
+
+/// Replace a leading `~` with the home directory
+pub fn expand_home(path: &Path) -> PathBuf {
+    match (path.strip_prefix("~"), env::var_os("HOME")) {
+        (Ok(rest), Some(home)) => PathBuf::from(home).join(rest),
+        _ => path.to_path_buf(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::collect_paths;
+    use crate::config::SearchConfig;
+    use crate::test_dir::TestDir;
+
+    #[test]
+    fn parse_policies() {
+        assert_eq!("newest".parse(), Ok(KeepPolicy::Newest));
+        assert_eq!(
+            "dir:/mnt/master".parse(),
+            Ok(KeepPolicy::InDirectory(PathBuf::from("/mnt/master")))
+        );
+        assert_eq!(
+            "priority:/a,/b".parse(),
+            Ok(KeepPolicy::Priority(vec![
+                PathBuf::from("/a"),
+                PathBuf::from("/b")
+            ]))
+        );
+        assert!("largest".parse::<KeepPolicy>().is_err());
+    }
+
+    #[test]
+    fn keep_one_per_group() {
+        let mut index = FileIndex::new(
+            collect_paths(vec!["../test_files/same_files"]),
+            SearchConfig::default(),
+        );
+        index.index_dirs();
+        index.process_files(None, None);
+        index.find_duplicates(None, None);
+
+        let group: Vec<&FileEntry> = index
+            .files
+            .values()
+            .filter(|f| index.duplicates.contains_key(&f.path))
+            .collect();
+
+        let decision = decide(&KeepPolicy::Oldest, &group);
+        assert_eq!(decision.keep.len(), 1);
+        assert_eq!(decision.remove.len(), 2);
+
+        let keep = group
+            .iter()
+            .find(|f| f.path.ends_with("file_b.txt"))
+            .map(|f| f.path.clone())
+            .unwrap();
+        let decision = decide(&KeepPolicy::Priority(vec![keep.clone()]), &group);
+        assert_eq!(decision.keep, vec![keep.clone()]);
+
+        let decision = decide(&KeepPolicy::InDirectory(PathBuf::from("/nowhere")), &group);
+        assert!(decision.remove.is_empty());
+
+        assert_eq!(removals(&KeepPolicy::Newest, &index).len(), 2);
+
+        index.config.action_config.prefer_paths = vec![keep.clone()];
+        let removed = removals(&KeepPolicy::Newest, &index);
+        assert_eq!(removed.len(), 2);
+        assert!(!removed.contains(&keep));
+    }
+
+    #[test]
+    fn plan_removals() {
+        let mut index = FileIndex::new(
+            collect_paths(vec!["../test_files/same_files"]),
+            SearchConfig::default(),
+        );
+        index.index_dirs();
+        index.process_files(None, None);
+        index.find_duplicates(None, None);
+
+        let plan = plan(&index, PlanAction::Remove);
+        assert_eq!(plan.groups.len(), 1);
+        assert_eq!(plan.files, 2);
+        assert_eq!(plan.bytes, index.reclaimable_size());
+        assert!(!plan.groups[0].files.contains(&plan.groups[0].keep));
+
+        let json = serde_json::to_value(&plan).unwrap();
+        assert_eq!(json["action"], "remove");
+    }
+
+    #[test]
+    fn keep_largest_resolution() {
+        let mut index = FileIndex::new(
+            collect_paths(vec!["../test_files/images"]),
+            SearchConfig::default(),
+        );
+        index.index_dirs();
+        index.process_files(None, None);
+
+        let images: Vec<&FileEntry> = index
+            .files
+            .values()
+            .filter(|f| f.name.ends_with("Sapeck.jpg"))
+            .collect();
+        let decision = decide(&KeepPolicy::LargestResolution, &images);
+        assert_eq!(decision.keep.len(), 1);
+        assert!(decision.keep[0].ends_with("images/Sapeck.jpg"));
+
+        let texts: Vec<&FileEntry> = index
+            .files
+            .values()
+            .filter(|f| f.image_info.is_none())
+            .collect();
+        let decision = decide(&KeepPolicy::LargestResolution, &texts);
+        assert!(decision.remove.is_empty());
+    }
+
+    /// Write a second of silence as a 16 bit mono WAV file
+    fn write_wav(path: &Path, sample_rate: u32) {
+        let data_len = sample_rate * 2;
+        let mut wav = Vec::new();
+        wav.extend(b"RIFF");
+        wav.extend((36 + data_len).to_le_bytes());
+        wav.extend(b"WAVEfmt ");
+        wav.extend(16u32.to_le_bytes());
+        wav.extend(1u16.to_le_bytes());
+        wav.extend(1u16.to_le_bytes());
+        wav.extend(sample_rate.to_le_bytes());
+        wav.extend((sample_rate * 2).to_le_bytes());
+        wav.extend(2u16.to_le_bytes());
+        wav.extend(16u16.to_le_bytes());
+        wav.extend(b"data");
+        wav.extend(data_len.to_le_bytes());
+        wav.resize(wav.len() + data_len as usize, 0);
+        std::fs::write(path, wav).unwrap();
+    }
+
+    #[test]
+    fn keep_best_audio() {
+        let root = TestDir::new("best_audio");
+        write_wav(&root.join("low.wav"), 22050);
+        write_wav(&root.join("high.wav"), 44100);
+
+        let mut index = FileIndex::new(collect_paths(vec![&root]), SearchConfig::default());
+        index.index_dirs();
+        index.process_files(None, None);
+
+        let tracks: Vec<&FileEntry> = index.files.values().collect();
+        let info = tracks[0].audio_info.as_ref().unwrap();
+        assert!(info.lossless);
+        let decision = decide(&KeepPolicy::BestAudio, &tracks);
+        assert_eq!(decision.keep.len(), 1);
+        assert!(decision.keep[0].ends_with("high.wav"));
+    }
+
+    #[test]
+    fn expand_home_dir() {
+        let home = PathBuf::from(env::var_os("HOME").unwrap());
+        assert_eq!(expand_home(Path::new("~/Pictures")), home.join("Pictures"));
+        assert_eq!(
+            expand_home(Path::new("/mnt/nas")),
+            PathBuf::from("/mnt/nas")
+        );
+    }
+}
@@ -0,0 +1,200 @@
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum ScanPhase {
+    #[default]
+    Indexing,
+    Processing,
+    Comparing,
+}
+
+/// Snapshot of a running scan phase
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Progress {
+    pub phase: ScanPhase,
+    /// Files processed, or pairs of files compared while comparing
+    pub files_done: usize,
+    pub files_total: usize,
+    /// Files served from the hash cache
+    pub files_cached: usize,
+    pub bytes_done: u64,
+    pub bytes_total: u64,
+    pub current_path: Option<PathBuf>,
+    /// Time since the phase started
+    pub elapsed: Duration,
+    /// Estimated time until the phase is done
+    pub eta: Option<Duration>,
+}
+
+impl Progress {
+    /// Completed part of the phase between 0 and 1
+    pub fn ratio(&self) -> f64 {
+        if self.bytes_total > 0 {
+            (self.bytes_done as f64 / self.bytes_total as f64).clamp(0.0, 1.0)
+        } else if self.files_total > 0 {
+            (self.files_done as f64 / self.files_total as f64).clamp(0.0, 1.0)
+        } else {
+            0.0
+        }
+    }
+
+    /// Bytes processed per second since the phase started
+    pub fn throughput(&self) -> Option<f64> {
+        let secs = self.elapsed.as_secs_f64();
+        if secs > 0.0 && self.bytes_done > 0 {
+            Some(self.bytes_done as f64 / secs)
+        } else {
+            None
+        }
+    }
+}
+
+pub type ProgressCallback = Arc<dyn Fn(&Progress) + Send + Sync>;
+
+/// Counts the work done in one phase and reports it to the callback
+pub(crate) struct ProgressTracker {
+    phase: ScanPhase,
+    start: Instant,
+    files_total: usize,
+    bytes_total: u64,
+    files_done: AtomicUsize,
+    files_cached: AtomicUsize,
+    bytes_done: AtomicU64,
+    callback: Option<ProgressCallback>,
+    interval: Duration,
+    // time of the last report, also keeps the reported counts increasing
+    // when called from several threads
+    last_report: Mutex<Option<Instant>>,
+}
+
+impl ProgressTracker {
+    pub(crate) fn new(
+        phase: ScanPhase,
+        files_total: usize,
+        bytes_total: u64,
+        callback: Option<ProgressCallback>,
+    ) -> Self {
+        Self {
+            phase,
+            start: Instant::now(),
+            files_total,
+            bytes_total,
+            files_done: AtomicUsize::new(0),
+            files_cached: AtomicUsize::new(0),
+            bytes_done: AtomicU64::new(0),
+            callback,
+            interval: Duration::ZERO,
+            last_report: Mutex::new(None),
+        }
+    }
+
+    /// Report at most once per interval, the last report is always sent
+    pub(crate) fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    pub(crate) fn advance(&self, path: &Path, files: usize, bytes: u64) {
+        self.files_done.fetch_add(files, Ordering::SeqCst);
+        self.bytes_done.fetch_add(bytes, Ordering::SeqCst);
+        self.report(path);
+    }
+
+    pub(crate) fn advance_cached(&self, path: &Path, bytes: u64) {
+        self.files_cached.fetch_add(1, Ordering::SeqCst);
+        self.advance(path, 1, bytes);
+    }
+
+    pub(crate) fn cached(&self) -> usize {
+        self.files_cached.load(Ordering::SeqCst)
+    }
+
+    fn report(&self, path: &Path) {
+        let Some(callback) = &self.callback else {
+            return;
+        };
+        let mut last_report = self.last_report.lock().unwrap();
+        let finished = self.files_done.load(Ordering::SeqCst) >= self.files_total;
+        if !finished && last_report.is_some_and(|at| at.elapsed() < self.interval) {
+            return;
+        }
+        *last_report = Some(Instant::now());
+
+        let mut progress = Progress {
+            phase: self.phase,
+            files_done: self.files_done.load(Ordering::SeqCst),
+            files_total: self.files_total,
+            files_cached: self.cached(),
+            bytes_done: self.bytes_done.load(Ordering::SeqCst),
+            bytes_total: self.bytes_total,
+            current_path: Some(path.to_path_buf()),
+            elapsed: self.start.elapsed(),
+            eta: None,
+        };
+        let ratio = progress.ratio();
+        if ratio > 0.0 {
+            let elapsed = progress.elapsed.as_secs_f64();
+            progress.eta = Some(Duration::from_secs_f64(elapsed / ratio - elapsed));
+        }
+        callback(&progress);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn report_bytes_and_eta() {
+        let last = Arc::new(Mutex::new(Progress::default()));
+        let reported = last.clone();
+        let callback: ProgressCallback = Arc::new(move |progress: &Progress| {
+            *reported.lock().unwrap() = progress.clone();
+        });
+
+        let tracker = ProgressTracker::new(ScanPhase::Processing, 4, 400, Some(callback))
+            .with_interval(Duration::ZERO);
+        tracker.advance(Path::new("a"), 1, 100);
+        tracker.advance_cached(Path::new("b"), 100);
+
+        let progress = last.lock().unwrap().clone();
+        assert_eq!(progress.files_done, 2);
+        assert_eq!(progress.files_cached, 1);
+        assert_eq!(progress.bytes_done, 200);
+        assert_eq!(progress.ratio(), 0.5);
+        assert_eq!(progress.current_path, Some(PathBuf::from("b")));
+        assert!(progress.eta.is_some());
+    }
+
+    #[test]
+    fn throughput() {
+        let mut progress = Progress {
+            bytes_done: 3_000_000,
+            ..Default::default()
+        };
+        assert_eq!(progress.throughput(), None);
+        progress.elapsed = Duration::from_secs(2);
+        assert_eq!(progress.throughput(), Some(1_500_000.0));
+    }
+
+    #[test]
+    fn throttle_reports() {
+        let reports = Arc::new(Mutex::new(Vec::new()));
+        let reported = reports.clone();
+        let callback: ProgressCallback = Arc::new(move |progress: &Progress| {
+            reported.lock().unwrap().push(progress.files_done);
+        });
+
+        let tracker = ProgressTracker::new(ScanPhase::Processing, 100, 0, Some(callback))
+            .with_interval(Duration::from_secs(60));
+        for _ in 0..100 {
+            tracker.advance(Path::new("a"), 1, 0);
+        }
+
+        // the first and the last file
+        assert_eq!(*reports.lock().unwrap(), vec![1, 100]);
+    }
+}
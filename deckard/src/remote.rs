@@ -0,0 +1,257 @@
+use crate::config::{HashAlgorithm, SearchConfig};
+use crate::error::DeckardError;
+use crate::file::FileEntry;
+use crate::index::FileIndex;
+use crate::verify;
+use chrono::{DateTime, Local};
+use log::{debug, warn};
+use std::collections::{HashMap, HashSet};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::thread;
+
+/// A directory on another machine reached over SSH, written `[user@]host:/path`
+#[derive(Debug, Clone, PartialEq)]
+pub struct RemoteRoot {
+    pub host: String,
+    pub path: String,
+}
+
+impl RemoteRoot {
+    /// Parse a remote path, existing local paths are never remote
+    pub fn parse(arg: &str) -> Option<Self> {
+        if Path::new(arg).exists() {
+            return None;
+        }
+        let (host, path) = arg.split_once(':')?;
+        if host.is_empty() || host.contains('/') || !path.starts_with('/') {
+            return None;
+        }
+        // s3://bucket and other URLs are not SSH paths
+        if path.starts_with("//") {
+            return None;
+        }
+        Some(Self {
+            host: host.to_string(),
+            path: path.to_string(),
+        })
+    }
+
+    /// Path the remote file is listed under in the index
+    fn local_path(&self, path: &str) -> PathBuf {
+        PathBuf::from(format!("{}:{}", self.host, path))
+    }
+
+    /// Run the shell command on the host, with the input on stdin
+    fn run(&self, command: &str, input: Vec<u8>) -> Result<Vec<u8>, DeckardError> {
+        debug!("running {} on {}", command, self.host);
+        let mut child = Command::new("ssh")
+            .arg(&self.host)
+            .arg(command)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        // write from another thread, the output could fill up the pipe first
+        let writer = child
+            .stdin
+            .take()
+            .map(|mut stdin| thread::spawn(move || stdin.write_all(&input)));
+        let output = child.wait_with_output()?;
+        if let Some(writer) = writer {
+            writer.join().unwrap_or(Ok(()))?;
+        }
+        if !output.status.success() {
+            return Err(DeckardError::Remote(format!(
+                "{} failed on {}: {}",
+                command,
+                self.host,
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+        Ok(output.stdout)
+    }
+
+    /// Size, modification time and path of every file under the root
+    fn list(&self) -> Result<Vec<(u64, DateTime<Local>, String)>, DeckardError> {
+        let output = self.run(
+            &format!("find {} -type f -printf '%s %T@ %p\\0'", quote(&self.path)),
+            Vec::new(),
+        )?;
+        Ok(output
+            .split(|b| *b == 0)
+            .filter(|line| !line.is_empty())
+            .filter_map(|line| parse_listing(&String::from_utf8_lossy(line)))
+            .collect())
+    }
+
+    /// Hash the files on the host, the hashes are keyed by remote path
+    fn hash(
+        &self,
+        paths: &[&str],
+        algorithm: HashAlgorithm,
+    ) -> Result<HashMap<PathBuf, String>, DeckardError> {
+        let mut input = Vec::new();
+        for path in paths {
+            input.extend_from_slice(path.as_bytes());
+            input.push(0);
+        }
+        let output = self.run(&format!("xargs -0 {}", tool(algorithm)), input)?;
+        Ok(String::from_utf8_lossy(&output)
+            .lines()
+            .filter_map(verify::parse_line)
+            .map(|c| (c.path, c.hash))
+            .collect())
+    }
+}
+
+/// `<size> <seconds>.<fraction> <path>` as printed by find
+fn parse_listing(line: &str) -> Option<(u64, DateTime<Local>, String)> {
+    let mut parts = line.splitn(3, ' ');
+    let size = parts.next()?.parse().ok()?;
+    let (secs, fraction) = parts.next()?.split_once('.').unwrap_or(("0", "0"));
+    let nanos = format!("{:0<9}", fraction)[..9].parse().ok()?;
+    let modified = DateTime::from_timestamp(secs.parse().ok()?, nanos)?.with_timezone(&Local);
+    Some((size, modified, parts.next()?.to_string()))
+}
+
+/// coreutils tool producing the same hashes
+fn tool(algorithm: HashAlgorithm) -> &'static str {
+    match algorithm {
+        HashAlgorithm::MD5 => "md5sum",
+        HashAlgorithm::SHA1 => "sha1sum",
+        HashAlgorithm::SHA256 => "sha256sum",
+        HashAlgorithm::SHA512 => "sha512sum",
+    }
+}
+
+/// Quote for the POSIX shell of the host, whatever the local platform is
+fn quote(path: &str) -> String {
+    format!("'{}'", path.replace('\'', r"'\''"))
+}
+
+/// Hashes of the local files can only match the hashes computed by the
+/// coreutils tools on the host when they are full hashes of the whole file
+pub fn prepare_config(config: &mut SearchConfig) {
+    config.hasher_config.full_hash = true;
+    config.hasher_config.parallel_size = 0;
+}
+
+/// Index the files under the remote root, only the files with one of the
+/// given sizes are hashed, on the host itself
+pub fn scan(
+    root: &RemoteRoot,
+    config: &SearchConfig,
+    sizes: &HashSet<u64>,
+) -> Result<FileIndex, DeckardError> {
+    let listing = root.list()?;
+    let candidates: Vec<&str> = listing
+        .iter()
+        .filter(|(size, _, _)| sizes.contains(size))
+        .map(|(_, _, path)| path.as_str())
+        .collect();
+    let hashes = if candidates.is_empty() {
+        HashMap::new()
+    } else {
+        root.hash(&candidates, config.hasher_config.hash_algorithm)?
+    };
+    debug!(
+        "listed {} files on {}, hashed {}",
+        listing.len(),
+        root.host,
+        hashes.len()
+    );
+
+    let mut index = FileIndex::new(HashSet::new(), config.clone());
+    index.dirs.insert(root.local_path(&root.path));
+    for (size, modified, path) in listing {
+        let mut file = FileEntry::detached(root.local_path(&path), size, modified);
+        if let Some(hash) = hashes.get(Path::new(&path)) {
+            file.full_hash = Some(hash.clone());
+            file.processed = true;
+        }
+        index.files.insert(file.path.clone(), file);
+    }
+    Ok(index)
+}
+
+/// Add the remote files to the index and link them with the local files of
+/// the same size and full hash
+pub fn link(index: &mut FileIndex, remote: FileIndex) {
+    if index.config.hasher_config.paranoid_algorithm.is_some() {
+        warn!("paranoid hashes are not computed on remote hosts");
+    }
+    let mut local: HashMap<(u64, &str), Vec<&PathBuf>> = HashMap::new();
+    for file in index.files.values() {
+        if let Some(hash) = file.full_hash.as_deref() {
+            local.entry((file.size, hash)).or_default().push(&file.path);
+        }
+    }
+
+    let mut found = Vec::new();
+    for file in remote.files.values() {
+        let Some(hash) = file.full_hash.as_deref() else {
+            continue;
+        };
+        for copy in local.get(&(file.size, hash)).into_iter().flatten() {
+            found.push((file.path.clone(), (*copy).clone()));
+        }
+    }
+    for (a, b) in found {
+        deckard_core::link(&mut index.duplicates, &a, &b);
+    }
+    index.dirs.extend(remote.dirs);
+    index.files.extend(remote.files);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_remote_paths() {
+        assert_eq!(
+            RemoteRoot::parse("user@nas:/srv/photos"),
+            Some(RemoteRoot {
+                host: "user@nas".to_string(),
+                path: "/srv/photos".to_string()
+            })
+        );
+        assert_eq!(RemoteRoot::parse("nas:relative"), None);
+        assert_eq!(RemoteRoot::parse("/local/path"), None);
+        assert_eq!(RemoteRoot::parse("."), None);
+        assert_eq!(RemoteRoot::parse("s3://bucket/prefix"), None);
+    }
+
+    #[test]
+    fn parse_find_listing() {
+        let (size, modified, path) =
+            parse_listing("1024 1700000000.5000000000 /srv/a file").unwrap();
+        assert_eq!(size, 1024);
+        assert_eq!(modified.timestamp(), 1_700_000_000);
+        assert_eq!(modified.timestamp_subsec_millis(), 500);
+        assert_eq!(path, "/srv/a file");
+        assert_eq!(parse_listing("garbage"), None);
+    }
+
+    #[test]
+    fn link_remote_files() {
+        let mut index = FileIndex::default();
+        let mut local = FileEntry::detached(PathBuf::from("/local/a"), 4, Local::now());
+        local.full_hash = Some("abcd".to_string());
+        index.files.insert(local.path.clone(), local);
+
+        let mut remote = FileIndex::default();
+        let mut copy = FileEntry::detached(PathBuf::from("nas:/srv/a"), 4, Local::now());
+        copy.full_hash = Some("abcd".to_string());
+        let other = FileEntry::detached(PathBuf::from("nas:/srv/b"), 4, Local::now());
+        remote.files.insert(copy.path.clone(), copy);
+        remote.files.insert(other.path.clone(), other);
+
+        link(&mut index, remote);
+        assert_eq!(index.files_len(), 3);
+        assert_eq!(index.duplicate_groups().len(), 1);
+        assert!(index.duplicates[Path::new("/local/a")].contains(Path::new("nas:/srv/a")));
+    }
+}
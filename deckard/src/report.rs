@@ -0,0 +1,789 @@
+use crate::config::{GroupOrder, SearchConfig};
+use crate::error::DeckardError;
+use crate::file::{FileEntry, ImageInfo};
+use crate::index::FileIndex;
+use humansize::{format_size, DECIMAL};
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+/// Rows in each table of the markdown report
+const REPORT_ROWS: usize = 10;
+
+/// Bumped whenever the shape of the JSON report changes
+pub const JSON_SCHEMA_VERSION: u32 = 1;
+
+/// Files sharing a name whose content differs
+#[derive(Debug, Clone, PartialEq)]
+pub struct DivergentName {
+    pub name: String,
+    /// Files grouped by content, each group holds identical copies
+    pub variants: Vec<Vec<PathBuf>>,
+}
+
+/// Find files with the same name but different content, like diverged
+/// copies of a config or a document
+pub fn divergent_names(index: &FileIndex) -> Vec<DivergentName> {
+    let mut by_name: BTreeMap<&str, Vec<&PathBuf>> = BTreeMap::new();
+    for (path, file) in &index.files {
+        by_name.entry(&file.name).or_default().push(path);
+    }
+
+    by_name
+        .into_iter()
+        .filter(|(_, paths)| paths.len() > 1)
+        .filter_map(|(name, paths)| {
+            let variants = content_variants(index, &paths);
+            (variants.len() > 1).then(|| DivergentName {
+                name: name.to_string(),
+                variants,
+            })
+        })
+        .collect()
+}
+
+/// Split the paths into groups of duplicates
+fn content_variants(index: &FileIndex, paths: &[&PathBuf]) -> Vec<Vec<PathBuf>> {
+    let mut visited: HashSet<&PathBuf> = HashSet::new();
+    let mut variants = Vec::new();
+
+    for &path in paths {
+        if !visited.insert(path) {
+            continue;
+        }
+        let mut variant = vec![path.clone()];
+        if let Some(copies) = index.duplicates.get(path) {
+            for &other in paths {
+                if copies.contains(other) && visited.insert(other) {
+                    variant.push(other.clone());
+                }
+            }
+        }
+        variant.sort();
+        variants.push(variant);
+    }
+    variants.sort();
+    variants
+}
+
+/// Duplicate group with the space freed by keeping only its largest file
+#[derive(Debug, Clone, PartialEq)]
+pub struct GroupSummary {
+    pub files: Vec<PathBuf>,
+    pub size: u64,
+    pub reclaimable: u64,
+    /// From 0 to 100, lowered by perceptual matches
+    pub confidence: u8,
+}
+
+/// Duplicate groups in the configured order, the ones freeing the most space first by default
+pub fn group_summaries(index: &FileIndex) -> Vec<GroupSummary> {
+    let mut groups: Vec<GroupSummary> = index
+        .duplicate_groups()
+        .into_iter()
+        .map(|mut files| {
+            files.sort();
+            let sizes: Vec<u64> = files
+                .iter()
+                .map(|f| index.file_size(f).unwrap_or_default())
+                .collect();
+            let size = sizes.iter().sum();
+            GroupSummary {
+                reclaimable: size - sizes.iter().max().unwrap_or(&0),
+                confidence: group_confidence(index, &files),
+                size,
+                files,
+            }
+        })
+        .filter(|group| {
+            (index.config.min_confidence..=index.config.max_confidence).contains(&group.confidence)
+        })
+        .collect();
+    sort_groups(&mut groups, index.config.group_order);
+    groups
+}
+
+/// Sort the groups in a canonical order, so identical scans give identical reports
+pub fn sort_groups(groups: &mut [GroupSummary], order: GroupOrder) {
+    groups.sort_by(|a, b| {
+        let by = match order {
+            GroupOrder::Reclaimable => b.reclaimable.cmp(&a.reclaimable),
+            GroupOrder::Size => b.size.cmp(&a.size),
+            GroupOrder::Count => b.files.len().cmp(&a.files.len()),
+            GroupOrder::Path => Ordering::Equal,
+            GroupOrder::Confidence => a.confidence.cmp(&b.confidence),
+        };
+        by.then(a.files.cmp(&b.files))
+    });
+}
+
+/// Confidence of the weakest match between two linked files of the group,
+/// links made outside of `compare` like remote hashes count as exact
+pub fn group_confidence(index: &FileIndex, files: &[PathBuf]) -> u8 {
+    let mut confidence = 100;
+    for (i, a) in files.iter().enumerate() {
+        let Some(copies) = index.duplicates.get(a) else {
+            continue;
+        };
+        for b in files[i + 1..].iter().filter(|b| copies.contains(*b)) {
+            if let (Some(x), Some(y)) = (index.files.get(a), index.files.get(b)) {
+                confidence = confidence.min(x.confidence(y, &index.config).unwrap_or(100));
+            }
+        }
+    }
+    confidence
+}
+
+/// Every duplicate except the largest file of its group
+pub fn removable_files(index: &FileIndex) -> Vec<PathBuf> {
+    let mut removable = Vec::new();
+    for group in group_summaries(index) {
+        let keep = group
+            .files
+            .iter()
+            .max_by_key(|f| index.file_size(f).unwrap_or_default())
+            .cloned();
+        removable.extend(group.files.into_iter().filter(|f| Some(f) != keep.as_ref()));
+    }
+    removable
+}
+
+/// Space freed in each directory by keeping only the largest file of every group
+pub fn reclaimable_by_dir(index: &FileIndex) -> Vec<(PathBuf, u64, usize)> {
+    let mut dirs: HashMap<PathBuf, (u64, usize)> = HashMap::new();
+    for file in removable_files(index) {
+        let dir = file.parent().unwrap_or(Path::new("")).to_path_buf();
+        let entry = dirs.entry(dir).or_default();
+        entry.0 += index.file_size(&file).unwrap_or_default();
+        entry.1 += 1;
+    }
+
+    let mut dirs: Vec<(PathBuf, u64, usize)> =
+        dirs.into_iter().map(|(d, (s, c))| (d, s, c)).collect();
+    dirs.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    dirs
+}
+
+/// Wasted bytes and duplicate count for each file extension, files
+/// without an extension are grouped under an empty string
+pub fn wasted_by_extension(index: &FileIndex) -> Vec<(String, u64, usize)> {
+    wasted_by(index, |file| {
+        index
+            .files
+            .get(file)
+            .and_then(|f| f.extension.clone())
+            .unwrap_or_default()
+    })
+}
+
+/// Wasted bytes and duplicate count for each top level directory of the scan roots
+pub fn wasted_by_top_dir(index: &FileIndex) -> Vec<(PathBuf, u64, usize)> {
+    wasted_by(index, |file| {
+        let Some(root) = index.root_of(file) else {
+            return file.parent().unwrap_or(Path::new("")).to_path_buf();
+        };
+        match file
+            .strip_prefix(root)
+            .ok()
+            .and_then(|p| p.components().next())
+        {
+            // files directly in the root are counted to the root itself
+            Some(top) if root.join(top) != file => root.join(top),
+            _ => root.clone(),
+        }
+    })
+}
+
+/// Sum the bytes of the removable duplicates by key, the most wasted first
+fn wasted_by<K: Ord + std::hash::Hash>(
+    index: &FileIndex,
+    key: impl Fn(&Path) -> K,
+) -> Vec<(K, u64, usize)> {
+    let mut wasted: HashMap<K, (u64, usize)> = HashMap::new();
+    for file in removable_files(index) {
+        let entry = wasted.entry(key(&file)).or_default();
+        entry.0 += index.file_size(&file).unwrap_or_default();
+        entry.1 += 1;
+    }
+
+    let mut wasted: Vec<(K, u64, usize)> =
+        wasted.into_iter().map(|(k, (s, c))| (k, s, c)).collect();
+    wasted.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    wasted
+}
+
+/// Files that are hardlinks of the same data
+#[derive(Debug, Clone, PartialEq)]
+pub struct LinkGroup {
+    pub files: Vec<PathBuf>,
+    pub size: u64,
+}
+
+impl LinkGroup {
+    /// Apparent bytes beyond the single stored copy
+    pub fn shared(&self) -> u64 {
+        self.size * (self.files.len() as u64 - 1)
+    }
+}
+
+/// Indexed files already hardlinked together, sharing a device and inode,
+/// the ones sharing the most space first
+pub fn hardlink_groups(index: &FileIndex) -> Vec<LinkGroup> {
+    let mut links: HashMap<(u64, u64), Vec<&FileEntry>> = HashMap::new();
+    for file in index.files.values() {
+        // inodes are only known on unix
+        if let Some(device) = file.device.filter(|_| file.inode != 0) {
+            links.entry((device, file.inode)).or_default().push(file);
+        }
+    }
+
+    let mut groups: Vec<LinkGroup> = links
+        .into_values()
+        .filter(|files| files.len() > 1)
+        .map(|files| {
+            let mut paths: Vec<PathBuf> = files.iter().map(|f| f.path.clone()).collect();
+            paths.sort();
+            LinkGroup {
+                files: paths,
+                size: files[0].size,
+            }
+        })
+        .collect();
+    groups.sort_by(|a, b| b.shared().cmp(&a.shared()).then(a.files.cmp(&b.files)));
+    groups
+}
+
+/// Directories whose files are hardlinked to each other, like backup
+/// snapshots or package store farms
+#[derive(Debug, Clone, PartialEq)]
+pub struct LinkFarm {
+    pub dirs: Vec<PathBuf>,
+    /// Hardlink groups spanning exactly these directories
+    pub groups: usize,
+    pub shared: u64,
+}
+
+/// Hardlink groups summed by the directories they span, the most shared space first
+pub fn link_farms(groups: &[LinkGroup]) -> Vec<LinkFarm> {
+    let mut farms: HashMap<Vec<PathBuf>, (usize, u64)> = HashMap::new();
+    for group in groups {
+        let mut dirs: Vec<PathBuf> = group
+            .files
+            .iter()
+            .map(|f| f.parent().unwrap_or(Path::new("")).to_path_buf())
+            .collect();
+        dirs.sort();
+        dirs.dedup();
+        let farm = farms.entry(dirs).or_default();
+        farm.0 += 1;
+        farm.1 += group.shared();
+    }
+
+    let mut farms: Vec<LinkFarm> = farms
+        .into_iter()
+        .map(|(dirs, (groups, shared))| LinkFarm {
+            dirs,
+            groups,
+            shared,
+        })
+        .collect();
+    farms.sort_by(|a, b| b.shared.cmp(&a.shared).then(a.dirs.cmp(&b.dirs)));
+    farms
+}
+
+/// Changes in the duplicates between two scans, each file with the copies
+/// it gained or lost
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct DuplicateDiff {
+    pub appeared: Vec<(PathBuf, Vec<PathBuf>)>,
+    pub resolved: Vec<(PathBuf, Vec<PathBuf>)>,
+}
+
+impl DuplicateDiff {
+    pub fn is_empty(&self) -> bool {
+        self.appeared.is_empty() && self.resolved.is_empty()
+    }
+}
+
+/// Compare the duplicates of the current scan with a previous snapshot
+pub fn diff(previous: &FileIndex, current: &FileIndex) -> DuplicateDiff {
+    DuplicateDiff {
+        appeared: missing_copies(&current.duplicates, &previous.duplicates),
+        resolved: missing_copies(&previous.duplicates, &current.duplicates),
+    }
+}
+
+/// Copies in `from` that `other` doesn't have
+fn missing_copies(
+    from: &HashMap<PathBuf, HashSet<PathBuf>>,
+    other: &HashMap<PathBuf, HashSet<PathBuf>>,
+) -> Vec<(PathBuf, Vec<PathBuf>)> {
+    let mut missing: Vec<(PathBuf, Vec<PathBuf>)> = from
+        .iter()
+        .filter_map(|(file, copies)| {
+            let known = other.get(file);
+            let mut copies: Vec<PathBuf> = copies
+                .iter()
+                .filter(|c| !known.is_some_and(|k| k.contains(*c)))
+                .cloned()
+                .collect();
+            copies.sort();
+            (!copies.is_empty()).then(|| (file.clone(), copies))
+        })
+        .collect();
+    missing.sort();
+    missing
+}
+
+/// Markdown summary of the scan to paste into tickets or wikis
+pub fn markdown(index: &FileIndex) -> String {
+    let groups = group_summaries(index);
+    let mut roots: Vec<String> = index
+        .dirs
+        .iter()
+        .map(|d| format!("`{}`", d.to_string_lossy()))
+        .collect();
+    roots.sort();
+
+    let mut md = String::new();
+    let _ = writeln!(md, "# Duplicate report\n");
+    let _ = writeln!(md, "| | |\n|---|---|");
+    let _ = writeln!(md, "| Paths | {} |", escape(&roots.join(", ")));
+    let _ = writeln!(md, "| Files | {} |", index.files_len());
+    let _ = writeln!(md, "| Files with duplicates | {} |", index.duplicates_len());
+    let _ = writeln!(md, "| Duplicate groups | {} |", groups.len());
+    let _ = writeln!(
+        md,
+        "| Duplicate size | {} |",
+        format_size(index.duplicates_size(), DECIMAL)
+    );
+    let _ = writeln!(
+        md,
+        "| Reclaimable | {} |",
+        format_size(index.reclaimable_size(), DECIMAL)
+    );
+    let _ = writeln!(md, "| Errors | {} |", index.errors_len());
+
+    let _ = writeln!(md, "\n## Top duplicate groups\n");
+    let _ = writeln!(
+        md,
+        "| Copies | Size | Reclaimable | Files |\n|---|---|---|---|"
+    );
+    for group in groups.iter().take(REPORT_ROWS) {
+        let files: Vec<String> = group
+            .files
+            .iter()
+            .map(|f| format!("`{}`", f.to_string_lossy()))
+            .collect();
+        let _ = writeln!(
+            md,
+            "| {} | {} | {} | {} |",
+            group.files.len(),
+            format_size(group.size, DECIMAL),
+            format_size(group.reclaimable, DECIMAL),
+            escape(&files.join("<br>"))
+        );
+    }
+
+    let _ = writeln!(md, "\n## Biggest reclaimable directories\n");
+    let _ = writeln!(md, "| Directory | Reclaimable | Files |\n|---|---|---|");
+    for (dir, size, count) in reclaimable_by_dir(index).iter().take(REPORT_ROWS) {
+        let _ = writeln!(
+            md,
+            "| `{}` | {} | {} |",
+            escape(&dir.to_string_lossy()),
+            format_size(*size, DECIMAL),
+            count
+        );
+    }
+    md
+}
+
+/// Why the files of a group were matched
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum MatchReason {
+    /// Same sampled hash
+    Hash,
+    /// Same hash of the whole content
+    FullHash,
+    /// Perceptual image hashes within the threshold
+    Image,
+    /// Audio fingerprints within the threshold
+    Audio,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct JsonReport {
+    pub schema_version: u32,
+    pub config: JsonConfig,
+    pub files: usize,
+    pub errors: usize,
+    pub reclaimable: u64,
+    /// The scan was interrupted, only the files processed until then are compared
+    #[serde(default)]
+    pub partial: bool,
+    pub groups: Vec<JsonGroup>,
+}
+
+/// Settings affecting what counts as a duplicate
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct JsonConfig {
+    pub roots: Vec<PathBuf>,
+    pub full_hash: bool,
+    pub compare_images: bool,
+    pub compare_audio: bool,
+    pub skip_empty: bool,
+    pub skip_hidden: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct JsonGroup {
+    pub reason: MatchReason,
+    pub size: u64,
+    pub reclaimable: u64,
+    #[serde(default = "full_confidence")]
+    pub confidence: u8,
+    pub members: Vec<JsonMember>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct JsonMember {
+    pub path: PathBuf,
+    pub size: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mime_type: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image: Option<ImageInfo>,
+}
+
+/// Versioned report of the scan meant to be consumed by other tools
+pub fn json_report(index: &FileIndex) -> JsonReport {
+    let mut roots: Vec<PathBuf> = index.dirs.iter().cloned().collect();
+    roots.sort();
+
+    let groups = group_summaries(index)
+        .into_iter()
+        .map(|group| {
+            let entries: Vec<&FileEntry> = group
+                .files
+                .iter()
+                .filter_map(|f| index.files.get(f))
+                .collect();
+            JsonGroup {
+                reason: match_reason(&entries, &index.config),
+                size: group.size,
+                reclaimable: group.reclaimable,
+                confidence: group.confidence,
+                members: entries
+                    .iter()
+                    .map(|f| JsonMember {
+                        path: f.path.clone(),
+                        size: f.size,
+                        mime_type: f.mime_type.clone(),
+                        image: f.image_info.clone(),
+                    })
+                    .collect(),
+            }
+        })
+        .collect();
+
+    JsonReport {
+        schema_version: JSON_SCHEMA_VERSION,
+        config: JsonConfig {
+            roots,
+            full_hash: index.config.hasher_config.hashes_fully(),
+            compare_images: index.config.image_config.compare,
+            compare_audio: index.config.audio_config.compare,
+            skip_empty: index.config.skip_empty,
+            skip_hidden: index.config.skip_hidden,
+        },
+        files: index.files_len(),
+        errors: index.errors_len(),
+        reclaimable: index.reclaimable_size(),
+        partial: index.stats.cancelled,
+        groups,
+    }
+}
+
+pub fn json(index: &FileIndex) -> Result<String, DeckardError> {
+    Ok(serde_json::to_string_pretty(&json_report(index))?)
+}
+
+/// The map of paths to their copies, kept until tools move to the versioned report
+pub fn json_legacy(index: &FileIndex) -> Result<String, DeckardError> {
+    let sorted: BTreeMap<&PathBuf, BTreeSet<&PathBuf>> = index
+        .duplicates
+        .iter()
+        .map(|(file, copies)| (file, copies.iter().collect()))
+        .collect();
+    Ok(serde_json::to_string_pretty(&sorted)?)
+}
+
+/// Standalone HTML page of the duplicate groups
+pub fn html(index: &FileIndex) -> String {
+    let report = json_report(index);
+    let mut html = String::new();
+    let _ = writeln!(
+        html,
+        "<!DOCTYPE html>\n<html>\n<head><meta charset=\"utf-8\"><title>Duplicate report</title></head>\n<body>"
+    );
+    let _ = writeln!(html, "<h1>Duplicate report</h1>");
+    let _ = writeln!(
+        html,
+        "<p>{} files, {} duplicate groups, {} reclaimable, {} errors</p>",
+        report.files,
+        report.groups.len(),
+        format_size(report.reclaimable, DECIMAL),
+        report.errors
+    );
+    let _ = writeln!(
+        html,
+        "<table>\n<tr><th>Reason</th><th>Size</th><th>Reclaimable</th><th>Files</th></tr>"
+    );
+    for group in &report.groups {
+        let files: Vec<String> = group
+            .members
+            .iter()
+            .map(|m| escape_html(&m.path.to_string_lossy()))
+            .collect();
+        let _ = writeln!(
+            html,
+            "<tr><td>{:?}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            group.reason,
+            format_size(group.size, DECIMAL),
+            format_size(group.reclaimable, DECIMAL),
+            files.join("<br>")
+        );
+    }
+    let _ = writeln!(html, "</table>\n</body>\n</html>");
+    html
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// Files sharing the same hashes matched on content, otherwise on media similarity
+/// Groups written before confidence was reported only held exact matches
+fn full_confidence() -> u8 {
+    100
+}
+
+fn match_reason(files: &[&FileEntry], config: &SearchConfig) -> MatchReason {
+    let same = |hash: fn(&FileEntry) -> &Option<String>| {
+        files
+            .first()
+            .and_then(|f| hash(f).as_ref())
+            .is_some_and(|first| files.iter().all(|f| hash(f).as_ref() == Some(first)))
+    };
+
+    let is_media = |kind: &str| {
+        files
+            .iter()
+            .all(|f| f.mime_type.as_ref().is_some_and(|m| m.contains(kind)))
+    };
+
+    if same(|f| &f.hash) && files.iter().all(|f| f.size == files[0].size) {
+        if config.hasher_config.hashes_fully() && same(|f| &f.full_hash) {
+            MatchReason::FullHash
+        } else {
+            MatchReason::Hash
+        }
+    } else if config.image_config.compare && is_media("image") {
+        MatchReason::Image
+    } else if config.audio_config.compare && is_media("audio") {
+        MatchReason::Audio
+    } else {
+        MatchReason::Hash
+    }
+}
+
+/// Keep pipes in paths from breaking the table
+fn escape(text: &str) -> String {
+    text.replace('|', "\\|")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::collect_paths;
+    use crate::config::SearchConfig;
+    use crate::test_dir::TestDir;
+
+    #[test]
+    fn same_name_different_content() {
+        let mut index = FileIndex::new(
+            collect_paths(vec![
+                "../test_files/same_files",
+                "../test_files/similar_files",
+            ]),
+            SearchConfig::default(),
+        );
+        index.index_dirs();
+        index.process_files(None, None);
+        index.find_duplicates(None, None);
+
+        let divergent = divergent_names(&index);
+        assert_eq!(divergent.len(), 3);
+        for name in &divergent {
+            assert!(name.variants.len() > 1);
+            assert!(name
+                .variants
+                .iter()
+                .flatten()
+                .all(|p| p.ends_with(&name.name)));
+        }
+    }
+
+    #[test]
+    fn canonical_group_order() {
+        let group = |files: &[&str], size: u64, reclaimable: u64| GroupSummary {
+            files: files.iter().map(PathBuf::from).collect(),
+            size,
+            reclaimable,
+            confidence: (size * 5) as u8,
+        };
+        let mut groups = vec![
+            group(&["/c", "/d"], 10, 5),
+            group(&["/a", "/b", "/e"], 9, 6),
+            group(&["/b", "/f"], 12, 6),
+        ];
+        let firsts = |groups: &[GroupSummary]| -> Vec<PathBuf> {
+            groups.iter().map(|g| g.files[0].clone()).collect()
+        };
+
+        sort_groups(&mut groups, GroupOrder::Reclaimable);
+        assert_eq!(firsts(&groups), ["/a", "/b", "/c"].map(PathBuf::from));
+        sort_groups(&mut groups, GroupOrder::Size);
+        assert_eq!(firsts(&groups), ["/b", "/c", "/a"].map(PathBuf::from));
+        sort_groups(&mut groups, GroupOrder::Count);
+        assert_eq!(firsts(&groups), ["/a", "/b", "/c"].map(PathBuf::from));
+        sort_groups(&mut groups, GroupOrder::Path);
+        assert_eq!(firsts(&groups), ["/a", "/b", "/c"].map(PathBuf::from));
+        sort_groups(&mut groups, GroupOrder::Confidence);
+        assert_eq!(firsts(&groups), ["/a", "/c", "/b"].map(PathBuf::from));
+    }
+
+    #[test]
+    fn markdown_summary() {
+        let mut index = FileIndex::new(
+            collect_paths(vec!["../test_files/same_files"]),
+            SearchConfig::default(),
+        );
+        index.index_dirs();
+        index.process_files(None, None);
+        index.find_duplicates(None, None);
+
+        let groups = group_summaries(&index);
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].files.len(), 3);
+        assert_eq!(groups[0].reclaimable, index.reclaimable_size());
+        assert_eq!(groups[0].confidence, 100);
+
+        let dirs = reclaimable_by_dir(&index);
+        assert_eq!(dirs.len(), 1);
+        assert_eq!(dirs[0].2, 2);
+
+        let md = markdown(&index);
+        assert!(md.starts_with("# Duplicate report"));
+        assert!(md.contains("| Duplicate groups | 1 |"));
+        assert!(md.contains("file_a.txt"));
+    }
+
+    #[test]
+    fn wasted_by_extension_and_dir() {
+        let mut index = FileIndex::new(
+            collect_paths(vec!["../test_files"]),
+            SearchConfig::default(),
+        );
+        index.index_dirs();
+        index.process_files(None, None);
+        index.find_duplicates(None, None);
+
+        let by_ext = wasted_by_extension(&index);
+        let by_dir = wasted_by_top_dir(&index);
+        let total: u64 = by_ext.iter().map(|(_, size, _)| size).sum();
+        assert_eq!(total, index.reclaimable_size());
+        assert_eq!(total, by_dir.iter().map(|(_, size, _)| size).sum::<u64>());
+        assert!(by_dir
+            .iter()
+            .all(|(dir, _, _)| index.root_of(dir).is_some()));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn report_hardlinks() {
+        let root = TestDir::new("hardlink_report");
+        std::fs::create_dir_all(root.join("a")).unwrap();
+        std::fs::create_dir_all(root.join("b")).unwrap();
+        std::fs::write(root.join("a/file"), "linked content").unwrap();
+        std::fs::hard_link(root.join("a/file"), root.join("b/file")).unwrap();
+        std::fs::hard_link(root.join("a/file"), root.join("b/other")).unwrap();
+        std::fs::write(root.join("b/single"), "linked content").unwrap();
+
+        let mut index = FileIndex::new(collect_paths(vec![&root]), SearchConfig::default());
+        index.index_dirs();
+
+        let groups = hardlink_groups(&index);
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].files.len(), 3);
+        assert_eq!(groups[0].shared(), 28);
+
+        let farms = link_farms(&groups);
+        assert_eq!(farms.len(), 1);
+        assert_eq!(farms[0].dirs.len(), 2);
+        assert_eq!(farms[0].groups, 1);
+        assert_eq!(farms[0].shared, 28);
+    }
+
+    #[test]
+    fn diff_snapshots() {
+        let mut previous = FileIndex::new(
+            collect_paths(vec!["../test_files/same_files"]),
+            SearchConfig::default(),
+        );
+        previous.index_dirs();
+        previous.process_files(None, None);
+        previous.find_duplicates(None, None);
+
+        assert!(diff(&previous, &previous).is_empty());
+
+        let mut current = FileIndex::new(previous.dirs.clone(), previous.config.clone());
+        let (file, copies) = previous.duplicates.iter().next().unwrap();
+        current.duplicates.insert(file.clone(), copies.clone());
+
+        let delta = diff(&previous, &current);
+        assert!(delta.appeared.is_empty());
+        assert_eq!(delta.resolved.len(), 2);
+
+        let delta = diff(&current, &previous);
+        assert_eq!(delta.appeared.len(), 2);
+        assert!(delta.resolved.is_empty());
+    }
+
+    #[test]
+    fn versioned_json() {
+        let mut index = FileIndex::new(
+            collect_paths(vec!["../test_files/same_files"]),
+            SearchConfig::default(),
+        );
+        index.index_dirs();
+        index.process_files(None, None);
+        index.find_duplicates(None, None);
+
+        let report = json_report(&index);
+        assert_eq!(report.schema_version, JSON_SCHEMA_VERSION);
+        assert_eq!(report.groups.len(), 1);
+        assert_eq!(report.groups[0].members.len(), 3);
+        assert_eq!(report.groups[0].reason, MatchReason::Hash);
+
+        let value: serde_json::Value = serde_json::from_str(&json(&index).unwrap()).unwrap();
+        assert_eq!(value["schema_version"], JSON_SCHEMA_VERSION);
+        assert_eq!(value["groups"][0]["reason"], "hash");
+    }
+}
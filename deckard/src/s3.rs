@@ -0,0 +1,226 @@
+use crate::config::{HashAlgorithm, RemoteConfig};
+use crate::error::DeckardError;
+use crate::file::FileEntry;
+use crate::hasher;
+use crate::index::FileIndex;
+use ::s3::bucket::Bucket;
+use ::s3::creds::Credentials;
+use ::s3::region::Region;
+use chksum::md5;
+use chrono::{DateTime, Local};
+use log::debug;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+/// Objects under a prefix of a bucket, written `s3://bucket/prefix`
+#[derive(Debug, Clone, PartialEq)]
+pub struct BucketRoot {
+    pub bucket: String,
+    pub prefix: String,
+}
+
+/// An object with the ETag the storage computed on upload
+#[derive(Debug, Clone, PartialEq)]
+pub struct Object {
+    pub key: String,
+    pub size: u64,
+    pub etag: String,
+    pub modified: DateTime<Local>,
+}
+
+impl BucketRoot {
+    pub fn parse(arg: &str) -> Option<Self> {
+        let rest = arg.strip_prefix("s3://")?;
+        let (bucket, prefix) = rest.split_once('/').unwrap_or((rest, ""));
+        if bucket.is_empty() {
+            return None;
+        }
+        Some(Self {
+            bucket: bucket.to_string(),
+            prefix: prefix.to_string(),
+        })
+    }
+
+    /// Path the object is listed under in the index
+    fn local_path(&self, key: &str) -> PathBuf {
+        PathBuf::from(format!("s3://{}/{}", self.bucket, key))
+    }
+
+    /// List every object under the prefix
+    pub fn list(&self, config: &RemoteConfig) -> Result<Vec<Object>, DeckardError> {
+        let remote = |e: &dyn std::fmt::Display| DeckardError::Remote(e.to_string());
+        let region = match &config.s3_endpoint {
+            Some(endpoint) => Region::Custom {
+                region: config.s3_region.clone(),
+                endpoint: endpoint.clone(),
+            },
+            None => config.s3_region.parse().map_err(|e| remote(&e))?,
+        };
+        let credentials = Credentials::new(
+            config.s3_access_key.as_deref(),
+            config.s3_secret_key.as_deref(),
+            None,
+            None,
+            None,
+        )
+        .map_err(|e| remote(&e))?;
+        let bucket = Bucket::new(&self.bucket, region, credentials)
+            .map_err(|e| remote(&e))?
+            .with_path_style();
+
+        let mut objects = Vec::new();
+        for page in bucket
+            .list(self.prefix.clone(), None)
+            .map_err(|e| remote(&e))?
+        {
+            for object in page.contents {
+                let Some(etag) = object.e_tag else {
+                    continue;
+                };
+                objects.push(Object {
+                    modified: DateTime::parse_from_rfc3339(&object.last_modified)
+                        .map(|t| t.with_timezone(&Local))
+                        .unwrap_or_default(),
+                    etag: etag.trim_matches('"').to_lowercase(),
+                    size: object.size,
+                    key: object.key,
+                });
+            }
+        }
+        debug!("listed {} objects in {}", objects.len(), self.bucket);
+        Ok(objects)
+    }
+}
+
+/// ETag the storage would compute for the file, the MD5 of the content for
+/// a single upload, or the MD5 of the part digests followed by the number of
+/// parts for a multipart upload
+pub fn etag(path: &Path, parts: usize, part_size: u64) -> Result<String, DeckardError> {
+    if parts == 0 {
+        return hasher::get_full_hash(&HashAlgorithm::MD5, path, &None);
+    }
+    let mut file = File::open(path)?;
+    let mut digests = Vec::new();
+    for _ in 0..parts {
+        let mut part = Vec::new();
+        (&mut file).take(part_size).read_to_end(&mut part)?;
+        digests.extend(from_hex(&md5::chksum(&part).unwrap().to_hex_lowercase()));
+    }
+    let digest = md5::chksum(&digests).unwrap().to_hex_lowercase();
+    Ok(format!("{}-{}", digest, parts))
+}
+
+fn from_hex(hex: &str) -> Vec<u8> {
+    (0..hex.len())
+        .step_by(2)
+        .filter_map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+        .collect()
+}
+
+/// Number of parts of a multipart ETag, 0 for a single upload
+fn parts(etag: &str) -> usize {
+    etag.rsplit_once('-')
+        .and_then(|(_, parts)| parts.parse().ok())
+        .unwrap_or(0)
+}
+
+/// Add the objects to the index and link them with the local files of the same
+/// size and ETag. Only local files sharing a size with an object are read, and
+/// multipart ETags only match when the objects were uploaded in parts of
+/// `part_size`.
+pub fn link(index: &mut FileIndex, root: &BucketRoot, objects: Vec<Object>, part_size: u64) {
+    let mut by_size: HashMap<u64, Vec<PathBuf>> = HashMap::new();
+    for file in index.files.values() {
+        by_size
+            .entry(file.size)
+            .or_default()
+            .push(file.path.clone());
+    }
+
+    let mut etags: HashMap<(PathBuf, usize), Option<String>> = HashMap::new();
+    for object in objects {
+        let path = root.local_path(&object.key);
+        let parts = parts(&object.etag);
+        if parts > 0 && object.size.div_ceil(part_size) != parts as u64 {
+            debug!(
+                "{} was uploaded with another part size, it can't be matched",
+                path.to_string_lossy()
+            );
+        } else {
+            for local in by_size.get(&object.size).into_iter().flatten() {
+                let local_etag = etags
+                    .entry((local.clone(), parts))
+                    .or_insert_with(|| etag(local, parts, part_size).ok());
+                if local_etag.as_deref() == Some(object.etag.as_str()) {
+                    deckard_core::link(&mut index.duplicates, &path, local);
+                }
+            }
+        }
+
+        let mut file = FileEntry::detached(path, object.size, object.modified);
+        file.full_hash = Some(object.etag);
+        file.processed = true;
+        index.files.insert(file.path.clone(), file);
+    }
+    index.dirs.insert(root.local_path(&root.prefix));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_dir::TestDir;
+    use std::fs;
+
+    #[test]
+    fn parse_bucket_paths() {
+        assert_eq!(
+            BucketRoot::parse("s3://backups/photos/2024"),
+            Some(BucketRoot {
+                bucket: "backups".to_string(),
+                prefix: "photos/2024".to_string()
+            })
+        );
+        assert_eq!(BucketRoot::parse("s3://backups").unwrap().prefix, "");
+        assert_eq!(BucketRoot::parse("s3://"), None);
+        assert_eq!(BucketRoot::parse("/local"), None);
+    }
+
+    #[test]
+    fn single_and_multipart_etags() {
+        let dir = TestDir::new("s3");
+        let path = dir.join("object");
+        fs::write(&path, "hello world").unwrap();
+
+        assert_eq!(
+            etag(&path, 0, 8).unwrap(),
+            "5eb63bbbe01eeed093cb22bb8f5acdc3"
+        );
+        let multipart = etag(&path, 2, 8).unwrap();
+        assert!(multipart.ends_with("-2"));
+        assert_eq!(parts(&multipart), 2);
+        assert_eq!(parts("5eb63bbbe01eeed093cb22bb8f5acdc3"), 0);
+
+        let mut index = FileIndex::default();
+        let local = FileEntry::new(path.clone(), "object".into(), fs::metadata(&path).unwrap());
+        index.files.insert(path.clone(), local);
+        let root = BucketRoot::parse("s3://bucket").unwrap();
+        let object = |key: &str, etag: &str| Object {
+            key: key.to_string(),
+            size: 11,
+            etag: etag.to_string(),
+            modified: Local::now(),
+        };
+        link(
+            &mut index,
+            &root,
+            vec![object("same", &multipart), object("other", "0-2")],
+            8,
+        );
+
+        assert_eq!(index.files_len(), 3);
+        assert_eq!(index.duplicates_len(), 2);
+        assert!(index.duplicates[&path].contains(Path::new("s3://bucket/same")));
+    }
+}
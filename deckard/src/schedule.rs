@@ -0,0 +1,177 @@
+use crate::collect_paths;
+use crate::config::SearchConfig;
+use crate::error::DeckardError;
+use crate::index::FileIndex;
+use crate::report::{self, JsonReport};
+use chrono::Local;
+use log::{debug, error, info};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+
+/// Paths scanned together with the search settings of a configuration profile
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Profile {
+    pub name: String,
+    pub paths: Vec<PathBuf>,
+    /// Name of the configuration the search settings are loaded from
+    pub config: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ScheduleConfig {
+    pub reports_dir: PathBuf,
+    /// Write an HTML page next to each JSON report
+    pub html: bool,
+    pub profiles: Vec<Profile>,
+}
+
+impl Default for ScheduleConfig {
+    fn default() -> Self {
+        Self {
+            reports_dir: SearchConfig::get_config_path("reports").with_extension(""),
+            html: false,
+            profiles: Vec::new(),
+        }
+    }
+}
+
+impl ScheduleConfig {
+    pub fn load() -> Self {
+        match confy::load("deckard", "schedule") {
+            Ok(c) => c,
+            Err(e) => {
+                error!("failed loading schedule config {:?}", e);
+                Self::default()
+            }
+        }
+    }
+}
+
+/// Outcome of scanning one profile
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScheduledRun {
+    pub profile: String,
+    pub report: PathBuf,
+    /// Duplicates that were not part of the previous report of the profile
+    pub new_duplicates: Vec<PathBuf>,
+}
+
+/// Scan every profile and write its timestamped report into the reports directory
+pub fn run(schedule: &ScheduleConfig) -> Result<Vec<ScheduledRun>, DeckardError> {
+    fs::create_dir_all(&schedule.reports_dir)?;
+    schedule
+        .profiles
+        .iter()
+        .map(|profile| run_profile(schedule, profile, SearchConfig::load(&profile.config)))
+        .collect()
+}
+
+fn run_profile(
+    schedule: &ScheduleConfig,
+    profile: &Profile,
+    config: SearchConfig,
+) -> Result<ScheduledRun, DeckardError> {
+    info!("scanning profile {}", profile.name);
+    let mut index = FileIndex::new(collect_paths(profile.paths.clone()), config);
+    index.index_dirs();
+    index.process_files(None, None);
+    index.find_duplicates(None, None);
+
+    let previous = latest_report(&schedule.reports_dir, &profile.name)?;
+    let report = report::json_report(&index);
+    let new_duplicates = match previous {
+        Some(previous) => new_duplicates(&previous, &report),
+        None => members(&report).into_iter().collect(),
+    };
+
+    let stamp = Local::now().format("%Y%m%d-%H%M%S");
+    let path = schedule
+        .reports_dir
+        .join(format!("{}-{}.json", profile.name, stamp));
+    fs::write(&path, serde_json::to_string_pretty(&report)?)?;
+    if schedule.html {
+        fs::write(path.with_extension("html"), report::html(&index))?;
+    }
+
+    Ok(ScheduledRun {
+        profile: profile.name.clone(),
+        report: path,
+        new_duplicates,
+    })
+}
+
+/// Most recent JSON report of the profile, the timestamps sort by name
+fn latest_report(dir: &Path, profile: &str) -> Result<Option<JsonReport>, DeckardError> {
+    let prefix = format!("{}-", profile);
+    let latest = fs::read_dir(dir)?
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| {
+            p.extension().is_some_and(|e| e == "json")
+                && p.file_name()
+                    .is_some_and(|n| n.to_string_lossy().starts_with(&prefix))
+        })
+        .max();
+
+    let Some(latest) = latest else {
+        return Ok(None);
+    };
+    debug!("comparing with {}", latest.to_string_lossy());
+    let reader = BufReader::new(fs::File::open(latest)?);
+    Ok(Some(serde_json::from_reader(reader)?))
+}
+
+fn members(report: &JsonReport) -> HashSet<PathBuf> {
+    report
+        .groups
+        .iter()
+        .flat_map(|g| g.members.iter().map(|m| m.path.clone()))
+        .collect()
+}
+
+/// Files in duplicate groups of the current report but not of the previous one
+fn new_duplicates(previous: &JsonReport, current: &JsonReport) -> Vec<PathBuf> {
+    let previous = members(previous);
+    let mut new: Vec<PathBuf> = members(current)
+        .into_iter()
+        .filter(|p| !previous.contains(p))
+        .collect();
+    new.sort();
+    new
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_dir::TestDir;
+
+    #[test]
+    fn report_new_duplicates_once() {
+        let reports_dir = TestDir::new("schedule");
+
+        let schedule = ScheduleConfig {
+            reports_dir: reports_dir.to_path_buf(),
+            html: true,
+            profiles: Vec::new(),
+        };
+        let profile = Profile {
+            name: "same".to_string(),
+            paths: vec![PathBuf::from("../test_files/same_files")],
+            config: "unused".to_string(),
+        };
+
+        let first = run_profile(&schedule, &profile, SearchConfig::default()).unwrap();
+        assert_eq!(first.new_duplicates.len(), 3);
+        assert!(first.report.exists());
+        assert!(first.report.with_extension("html").exists());
+
+        // reports are stamped by the second
+        std::thread::sleep(std::time::Duration::from_millis(1100));
+        let second = run_profile(&schedule, &profile, SearchConfig::default()).unwrap();
+        assert!(second.new_duplicates.is_empty());
+        assert_ne!(first.report, second.report);
+    }
+}
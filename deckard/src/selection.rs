@@ -0,0 +1,112 @@
+use crate::error::DeckardError;
+use crate::index::FileIndex;
+use crate::platform;
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs;
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+
+/// A selected file with the size and modification time it had when selected
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SelectedFile {
+    pub path: PathBuf,
+    pub size: u64,
+    pub modified: DateTime<Local>,
+}
+
+/// Files picked for removal, saved to review and apply later
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+pub struct Selection {
+    /// Scan roots, emptied directories are only removed inside them
+    pub roots: HashSet<PathBuf>,
+    pub files: Vec<SelectedFile>,
+}
+
+impl Selection {
+    /// Select the indexed files among the paths, other paths are ignored
+    pub fn new<'a>(index: &FileIndex, paths: impl IntoIterator<Item = &'a PathBuf>) -> Self {
+        let mut files: Vec<SelectedFile> = paths
+            .into_iter()
+            .filter_map(|p| index.file_entry(p))
+            .map(|f| SelectedFile {
+                path: f.path.clone(),
+                size: f.size,
+                modified: f.modified,
+            })
+            .collect();
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+        files.dedup_by(|a, b| a.path == b.path);
+        Self {
+            roots: index.dirs.clone(),
+            files,
+        }
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), DeckardError> {
+        let writer = BufWriter::new(fs::File::create(path)?);
+        serde_json::to_writer_pretty(writer, self)?;
+        Ok(())
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, DeckardError> {
+        let reader = BufReader::new(fs::File::open(path)?);
+        Ok(serde_json::from_reader(reader)?)
+    }
+
+    /// Split the files into the unchanged ones and the ones that changed
+    /// or are gone since they were selected, with the reason
+    pub fn check(&self) -> (Vec<PathBuf>, Vec<(PathBuf, String)>) {
+        let mut unchanged = Vec::new();
+        let mut changed = Vec::new();
+        for file in &self.files {
+            match fs::symlink_metadata(&file.path) {
+                Ok(m) if m.len() != file.size => {
+                    changed.push((file.path.clone(), "size changed".to_string()))
+                }
+                Ok(m) if platform::modified(&m) != file.modified => {
+                    changed.push((file.path.clone(), "modified since selected".to_string()))
+                }
+                Ok(_) => unchanged.push(file.path.clone()),
+                Err(e) => changed.push((file.path.clone(), e.to_string())),
+            }
+        }
+        (unchanged, changed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::collect_paths;
+    use crate::config::SearchConfig;
+    use crate::test_dir::TestDir;
+
+    #[test]
+    fn check_saved_selection() {
+        let dir = TestDir::new("selection");
+        let root = dir.join("files");
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("same"), "same").unwrap();
+        fs::write(root.join("changed"), "changed").unwrap();
+        fs::write(root.join("gone"), "gone").unwrap();
+
+        let mut index = FileIndex::new(collect_paths(vec![&root]), SearchConfig::default());
+        index.index_dirs();
+        let paths: Vec<PathBuf> = index.files.keys().cloned().collect();
+        let path = dir.join("selection.json");
+        Selection::new(&index, &paths).save(&path).unwrap();
+
+        fs::write(root.join("changed"), "changed again").unwrap();
+        fs::remove_file(root.join("gone")).unwrap();
+        let selection = Selection::load(&path).unwrap();
+        let (unchanged, changed) = selection.check();
+
+        assert_eq!(selection.files.len(), 3);
+        assert_eq!(selection.roots, index.dirs);
+        assert_eq!(unchanged.len(), 1);
+        assert!(unchanged[0].ends_with("same"));
+        assert_eq!(changed.len(), 2);
+    }
+}
@@ -0,0 +1,91 @@
+use crate::error::DeckardError;
+use crate::file::FileEntry;
+use log::{debug, warn};
+use rusqlite::{params, Connection};
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+const SCHEMA: &str = "
+PRAGMA journal_mode = OFF;
+PRAGMA synchronous = OFF;
+CREATE TABLE files (
+    size INTEGER NOT NULL,
+    entry TEXT NOT NULL
+);
+CREATE INDEX files_size ON files(size);
+";
+
+static STORES: AtomicUsize = AtomicUsize::new(0);
+
+/// Indexed files kept in a temporary database instead of memory,
+/// read back one size bucket at a time. The file is removed when dropped.
+pub struct SpillStore {
+    db: Connection,
+    path: PathBuf,
+}
+
+impl SpillStore {
+    pub fn new() -> Result<Self, DeckardError> {
+        let path = std::env::temp_dir().join(format!(
+            "deckard_spill_{}_{}.db",
+            std::process::id(),
+            STORES.fetch_add(1, Ordering::Relaxed)
+        ));
+        let _ = fs::remove_file(&path);
+        let db = Connection::open(&path)?;
+        db.execute_batch(SCHEMA)?;
+        debug!("spilling the index to {:?}", path);
+        Ok(Self { db, path })
+    }
+
+    /// Write the files to the store, returns how many were written
+    pub fn insert<I: IntoIterator<Item = FileEntry>>(
+        &mut self,
+        files: I,
+    ) -> Result<usize, DeckardError> {
+        let tx = self.db.transaction()?;
+        let mut count = 0;
+        {
+            let mut insert = tx.prepare("INSERT INTO files (size, entry) VALUES (?1, ?2)")?;
+            for file in files {
+                insert.execute(params![file.size as i64, serde_json::to_string(&file)?])?;
+                count += 1;
+            }
+        }
+        tx.commit()?;
+        Ok(count)
+    }
+
+    /// Sizes shared by more than one file, largest first
+    pub fn duplicate_sizes(&self) -> Result<Vec<u64>, DeckardError> {
+        let mut query = self.db.prepare(
+            "SELECT size FROM files GROUP BY size HAVING COUNT(*) > 1 ORDER BY size DESC",
+        )?;
+        let sizes = query
+            .query_map([], |row| row.get::<_, i64>(0))?
+            .map(|size| size.map(|s| s as u64))
+            .collect::<Result<Vec<u64>, _>>()?;
+        Ok(sizes)
+    }
+
+    /// All the files of the given size
+    pub fn bucket(&self, size: u64) -> Result<HashMap<PathBuf, FileEntry>, DeckardError> {
+        let mut query = self.db.prepare("SELECT entry FROM files WHERE size = ?1")?;
+        let mut files = HashMap::new();
+        for entry in query.query_map(params![size as i64], |row| row.get::<_, String>(0))? {
+            let file: FileEntry = serde_json::from_str(&entry?)?;
+            files.insert(file.path.clone(), file);
+        }
+        Ok(files)
+    }
+}
+
+impl Drop for SpillStore {
+    fn drop(&mut self) {
+        if let Err(e) = fs::remove_file(&self.path) {
+            warn!("failed removing {:?}: {}", self.path, e);
+        }
+    }
+}
@@ -0,0 +1,48 @@
+use crate::error::DeckardError;
+use log::warn;
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry::KeyValue;
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::trace::{Config, TracerProvider};
+use opentelemetry_sdk::Resource;
+use tracing_subscriber::layer::SubscriberExt;
+
+/// Flushes the exported spans when dropped
+pub struct Telemetry {
+    provider: TracerProvider,
+}
+
+impl Drop for Telemetry {
+    fn drop(&mut self) {
+        for result in self.provider.force_flush() {
+            if let Err(e) = result {
+                warn!("failed exporting spans: {}", e);
+            }
+        }
+    }
+}
+
+/// Export the scan spans to the OTLP endpoint over HTTP, keep the returned
+/// guard alive until the program exits
+pub fn init(endpoint: &str) -> Result<Telemetry, DeckardError> {
+    let provider = opentelemetry_otlp::new_pipeline()
+        .tracing()
+        .with_exporter(
+            opentelemetry_otlp::new_exporter()
+                .http()
+                .with_endpoint(endpoint),
+        )
+        .with_trace_config(
+            Config::default().with_resource(Resource::new(vec![KeyValue::new(
+                "service.name",
+                "deckard",
+            )])),
+        )
+        .install_simple()
+        .map_err(|e| DeckardError::Telemetry(e.to_string()))?;
+
+    let layer = tracing_opentelemetry::layer().with_tracer(provider.tracer("deckard"));
+    tracing::subscriber::set_global_default(tracing_subscriber::registry().with(layer))
+        .map_err(|e| DeckardError::Telemetry(e.to_string()))?;
+    Ok(Telemetry { provider })
+}
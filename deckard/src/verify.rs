@@ -0,0 +1,252 @@
+use crate::config::HashAlgorithm;
+use crate::error::DeckardError;
+use crate::hasher;
+use crate::index::FileIndex;
+use crate::platform;
+use log::{debug, warn};
+use rayon::prelude::*;
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+
+/// A file and its expected hash, one line of a checksum file
+#[derive(Debug, Clone, PartialEq)]
+pub struct Checksum {
+    pub path: PathBuf,
+    pub hash: String,
+}
+
+/// Outcome of checking the files against a checksum baseline
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Verification {
+    /// Files still matching their hash
+    pub unchanged: usize,
+    pub modified: Vec<PathBuf>,
+    pub missing: Vec<PathBuf>,
+    /// Indexed files not listed in the baseline
+    pub new: Vec<PathBuf>,
+    pub errors: Vec<(PathBuf, String)>,
+}
+
+impl Verification {
+    /// True when every listed file matches and nothing was added
+    pub fn is_clean(&self) -> bool {
+        self.modified.is_empty()
+            && self.missing.is_empty()
+            && self.new.is_empty()
+            && self.errors.is_empty()
+    }
+}
+
+/// Read a checksum file in the format of sha256sum and the other coreutils
+/// tools, as written by `export::checksums`. Malformed lines are skipped.
+pub fn read_checksums<P: AsRef<Path>>(path: P) -> Result<Vec<Checksum>, DeckardError> {
+    let content = fs::read_to_string(path)?;
+    let mut checksums = Vec::new();
+    for (i, line) in content.lines().enumerate() {
+        match parse_line(line) {
+            Some(checksum) => checksums.push(checksum),
+            None if line.trim().is_empty() => {}
+            None => warn!("skipping malformed checksum line {}", i + 1),
+        }
+    }
+    Ok(checksums)
+}
+
+/// `<hash>  <path>`, or `<hash> *<path>` for binary mode, escaped lines start with a backslash
+pub(crate) fn parse_line(line: &str) -> Option<Checksum> {
+    let (escaped, line) = match line.strip_prefix('\\') {
+        Some(line) => (true, line),
+        None => (false, line),
+    };
+    let (hash, path) = line.split_once(' ')?;
+    let path = path.strip_prefix([' ', '*'])?;
+    if hash.is_empty() || path.is_empty() || !hash.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    let path = if escaped {
+        unescape(path)?
+    } else {
+        path.to_string()
+    };
+    Some(Checksum {
+        path: PathBuf::from(path),
+        hash: hash.to_lowercase(),
+    })
+}
+
+fn unescape(path: &str) -> Option<String> {
+    let mut unescaped = String::with_capacity(path.len());
+    let mut chars = path.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next()? {
+            '\\' => unescaped.push('\\'),
+            'n' => unescaped.push('\n'),
+            'r' => unescaped.push('\r'),
+            _ => return None,
+        }
+    }
+    Some(unescaped)
+}
+
+/// Algorithm producing hashes of this length, the configured one when unknown
+fn algorithm_for(hash: &str, configured: HashAlgorithm) -> HashAlgorithm {
+    match hash.len() {
+        32 => HashAlgorithm::MD5,
+        40 => HashAlgorithm::SHA1,
+        64 => HashAlgorithm::SHA256,
+        128 => HashAlgorithm::SHA512,
+        _ => configured,
+    }
+}
+
+/// Re-hash the listed files in parallel and compare them with the baseline.
+/// Files are hashed as a whole like `export::checksums` writes them, never in
+/// parallel slices.
+/// Indexed files that are not listed are reported as new, so only index
+/// the directories the baseline covers.
+pub fn verify(
+    index: &FileIndex,
+    baseline: &[Checksum],
+    cancel: &Option<Arc<AtomicBool>>,
+) -> Verification {
+    let configured = index.config.hasher_config.hash_algorithm;
+    let checked: Vec<(&Checksum, Result<String, DeckardError>)> = baseline
+        .par_iter()
+        .map(|checksum| {
+            let algorithm = algorithm_for(&checksum.hash, configured);
+            (
+                checksum,
+                hasher::get_full_hash(&algorithm, &checksum.path, cancel),
+            )
+        })
+        .collect();
+
+    let mut verification = Verification::default();
+    for (checksum, result) in checked {
+        match result {
+            Ok(hash) if hash == checksum.hash => verification.unchanged += 1,
+            Ok(_) => {
+                debug!("{} was modified", checksum.path.to_string_lossy());
+                verification.modified.push(checksum.path.clone());
+            }
+            Err(DeckardError::Io(e)) if e.kind() == std::io::ErrorKind::NotFound => {
+                verification.missing.push(checksum.path.clone())
+            }
+            Err(e) => verification
+                .errors
+                .push((checksum.path.clone(), e.to_string())),
+        }
+    }
+
+    // the index holds canonical paths, the baseline may not
+    let listed: HashSet<PathBuf> = baseline
+        .iter()
+        .map(|c| {
+            fs::canonicalize(&c.path)
+                .map(platform::normalize_path)
+                .unwrap_or_else(|_| c.path.clone())
+        })
+        .collect();
+    verification.new = index
+        .files
+        .keys()
+        .filter(|path| !listed.contains(*path))
+        .cloned()
+        .collect();
+
+    verification.modified.sort();
+    verification.missing.sort();
+    verification.new.sort();
+    verification.errors.sort();
+    verification
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::collect_paths;
+    use crate::config::SearchConfig;
+    use crate::export;
+    use crate::test_dir::TestDir;
+
+    #[test]
+    fn parse_checksum_lines() {
+        assert_eq!(
+            parse_line("ABCD  some file"),
+            Some(Checksum {
+                path: PathBuf::from("some file"),
+                hash: "abcd".to_string()
+            })
+        );
+        assert_eq!(parse_line("abcd *bin").unwrap().path, PathBuf::from("bin"));
+        assert_eq!(
+            parse_line("\\abcd  a\\\\b\\nc").unwrap().path,
+            PathBuf::from("a\\b\nc")
+        );
+        assert_eq!(parse_line("abcd"), None);
+        assert_eq!(parse_line("xyz  file"), None);
+    }
+
+    #[test]
+    fn verify_against_baseline() {
+        let dir = TestDir::new("verify");
+        let root = dir.join("files");
+        fs::create_dir_all(&root).unwrap();
+        for name in ["kept", "modified", "removed"] {
+            fs::write(root.join(name), name).unwrap();
+        }
+        let baseline = dir.join("baseline.sha256");
+
+        let mut config = SearchConfig::default();
+        config.hasher_config.full_hash = true;
+        let mut index = FileIndex::new(collect_paths(vec![&root]), config.clone());
+        index.index_dirs();
+        index.process_files(None, None);
+        export::checksums(&index, &baseline).unwrap();
+
+        fs::write(root.join("modified"), "changed").unwrap();
+        fs::remove_file(root.join("removed")).unwrap();
+        fs::write(root.join("added"), "added").unwrap();
+
+        let mut index = FileIndex::new(collect_paths(vec![&root]), config);
+        index.index_dirs();
+        let verification = verify(&index, &read_checksums(&baseline).unwrap(), &None);
+        let root = fs::canonicalize(&root).unwrap();
+
+        assert_eq!(verification.unchanged, 1);
+        assert_eq!(verification.modified, vec![root.join("modified")]);
+        assert_eq!(verification.missing, vec![root.join("removed")]);
+        assert_eq!(verification.new, vec![root.join("added")]);
+        assert!(verification.errors.is_empty());
+        assert!(!verification.is_clean());
+    }
+
+    #[test]
+    fn verify_files_hashed_in_slices() {
+        let dir = TestDir::new("verify_sliced");
+        let root = dir.join("files");
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("large"), "larger than the parallel size").unwrap();
+        let baseline = dir.join("baseline.sha256");
+
+        let mut config = SearchConfig::default();
+        config.hasher_config.full_hash = true;
+        config.hasher_config.parallel_size = 4;
+        let mut index = FileIndex::new(collect_paths(vec![&root]), config);
+        index.index_dirs();
+        index.process_files(None, None);
+        export::checksums(&index, &baseline).unwrap();
+
+        let verification = verify(&index, &read_checksums(&baseline).unwrap(), &None);
+
+        assert_eq!(verification.unchanged, 1);
+        assert!(verification.is_clean());
+    }
+}
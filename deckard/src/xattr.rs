@@ -0,0 +1,127 @@
+use crate::config::{HashAlgorithm, HasherConfig};
+use crate::file::FileEntry;
+use crate::platform;
+use chrono::{DateTime, Local};
+use log::{debug, trace};
+use serde::{Deserialize, Serialize};
+
+/// Extended attribute the hashes of a file are stored in, as JSON
+pub const HASH_ATTRIBUTE: &str = "user.deckard.hash";
+
+/// Hashes of a file stamped with the size and modification time they were computed at
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+struct HashStamp {
+    size: u64,
+    modified: DateTime<Local>,
+    algorithm: HashAlgorithm,
+    /// Bytes and number of slices read for the quick hash
+    quick_size: u64,
+    quick_splits: u64,
+    hash: String,
+    full_hash: Option<String>,
+}
+
+/// Fill in the hashes stored on an unchanged file, returns false when they are
+/// missing, stale or were computed with other settings. Paranoid hashes are
+/// never stored, so files are always hashed in paranoid mode.
+pub(crate) fn apply(file: &mut FileEntry, config: &HasherConfig) -> bool {
+    if config.paranoid_algorithm.is_some() {
+        return false;
+    }
+    let Some(stamp) = read(file) else {
+        return false;
+    };
+    let usable = stamp.size == file.size
+        && stamp.modified == file.modified
+        && stamp.algorithm == config.hash_algorithm
+        && stamp.quick_size == config.size
+        && stamp.quick_splits == config.splits
+        && (!config.hashes_fully() || stamp.full_hash.is_some());
+    if !usable {
+        trace!("stale hash attribute on {}", file.path.to_string_lossy());
+        return false;
+    }
+    file.hash = Some(stamp.hash);
+    file.full_hash = stamp.full_hash.filter(|_| config.full_hash);
+    true
+}
+
+/// Store the hashes of the file in its extended attribute, failures are only
+/// logged since many file systems and read-only files don't allow it
+pub(crate) fn store(file: &FileEntry, config: &HasherConfig) {
+    let Some(hash) = file.hash.clone() else {
+        return;
+    };
+    let stamp = HashStamp {
+        size: file.size,
+        modified: file.modified,
+        algorithm: config.hash_algorithm,
+        quick_size: config.size,
+        quick_splits: config.splits,
+        hash,
+        full_hash: file.full_hash.clone(),
+    };
+    let Ok(value) = serde_json::to_vec(&stamp) else {
+        return;
+    };
+    if let Err(e) = platform::set_xattr(&file.path, HASH_ATTRIBUTE, &value) {
+        debug!(
+            "failed storing hashes of {}: {}",
+            file.path.to_string_lossy(),
+            e
+        );
+    }
+}
+
+fn read(file: &FileEntry) -> Option<HashStamp> {
+    let value = platform::get_xattr(&file.path, HASH_ATTRIBUTE)?;
+    serde_json::from_slice(&value).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_dir::TestDir;
+    use std::fs;
+    use std::path::PathBuf;
+
+    fn entry(path: &PathBuf) -> FileEntry {
+        FileEntry::new(
+            path.clone(),
+            path.file_name().unwrap().to_os_string(),
+            fs::metadata(path).unwrap(),
+        )
+    }
+
+    #[test]
+    fn reuse_stored_hashes() {
+        let dir = TestDir::new("xattr");
+        let path = dir.join("file");
+        fs::write(&path, "hashed once").unwrap();
+        let mut config = HasherConfig {
+            full_hash: true,
+            ..Default::default()
+        };
+
+        let mut file = entry(&path);
+        file.hash = Some("quick".to_string());
+        file.full_hash = Some("full".to_string());
+        store(&file, &config);
+        if platform::get_xattr(&path, HASH_ATTRIBUTE).is_none() {
+            // the temporary directory doesn't support user attributes
+            return;
+        }
+
+        let mut unchanged = entry(&path);
+        assert!(apply(&mut unchanged, &config));
+        assert_eq!(unchanged.hash.as_deref(), Some("quick"));
+        assert_eq!(unchanged.full_hash.as_deref(), Some("full"));
+
+        config.splits += 1;
+        assert!(!apply(&mut entry(&path), &config));
+        config.splits -= 1;
+
+        fs::write(&path, "hashed twice").unwrap();
+        assert!(!apply(&mut entry(&path), &config));
+    }
+}